@@ -0,0 +1,851 @@
+use crate::check;
+use crate::diagnostic::Diagnostic;
+use crate::interp::BUILTINS;
+use crate::program::Program;
+use crate::resolve::Declared;
+use crate::resolve::Scopes;
+use crate::span::SourceMap;
+use crate::span::Span;
+use crate::tokenizer::tokenize_spanned;
+use crate::tokenizer::Token;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/*
+ * Answers an editor's questions about some code
+ *
+ * Everything here uses byte offsets into the code. The code is parsed once,
+ * with the same parser that `check` uses, which recovers from problems so
+ * that hovering, jumping and completing still work while the code is being
+ * edited. Names inside of a function's body are looked up in the `Scopes`
+ * that `resolve` checks them against, so an editor and `check` can't
+ * disagree about what a name refers to. The tokens are only scanned to find
+ * where each definition and variable is written.
+ *
+ * If the code parses, the free functions and results are the ones in the
+ * parsed program. Otherwise, or if a name is defined more than once, they're
+ * found by scanning the tokens.
+ *
+ * What every name refers to is found once, when the code is analysed.
+ */
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+	Function,
+	Result,
+	Implementation,
+	Closure,
+	Parameter,
+	Variable,
+	Builtin,
+}
+
+// something with a name which can be used in the code
+#[derive(Clone, Debug, PartialEq)]
+pub struct Definition {
+	pub name: String,
+	pub kind: SymbolKind,
+	pub span: Option<Span>, // where it's defined, unless it's built in
+	pub detail: String,     // how it's declared, like `fn add(one: int): int`
+}
+
+// a definition in the outline of a file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outline {
+	pub name: String,
+	pub kind: SymbolKind,
+	pub detail: String,
+	pub span: Span,      // the whole definition, including its body
+	pub selection: Span, // the definition's header
+	pub children: Vec<Outline>,
+}
+
+// a `fn` or `result`, with the tokens it's made of
+#[derive(Clone, Debug)]
+struct Region {
+	kind: SymbolKind,
+	name: Option<Token>,
+	result: Option<String>, // the result a `fn => result` belongs to
+	header: Span,
+	body: Span,
+	parameters: Vec<(Token, String)>,
+	parent: Option<usize>,
+}
+
+// a variable declared with `var`
+#[derive(Clone, Debug)]
+struct Variable {
+	name: Token,
+	detail: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Analysis {
+	pub code: String,
+	pub diagnostics: Vec<Diagnostic>,
+	tokens: Vec<Token>,
+	regions: Vec<Region>,
+	variables: Vec<Variable>,
+	globals: Vec<Definition>,
+	definitions: HashMap<usize, Definition>, // by where each name starts
+	source_map: SourceMap,
+	scopes: Scopes,
+}
+
+impl Analysis {
+	/** Checks some code and finds everything that's defined in it */
+	pub fn new(code: &str) -> Self {
+		Analysis::analyse(code, true)
+	}
+
+	/** Finds everything that's defined in some code, without checking it */
+	pub fn unchecked(code: &str) -> Self {
+		Analysis::analyse(code, false)
+	}
+
+	/** Finds everything that's defined in some code, checking it if asked */
+	fn analyse(code: &str, checked: bool) -> Self {
+		let (program, source_map, errors) = check::parse_with_source_map(code);
+		let mut analysis = Analysis {
+			code: String::from(code),
+			diagnostics: Vec::new(),
+			tokens: tokenize_spanned(code),
+			regions: Vec::new(),
+			variables: Vec::new(),
+			globals: Vec::new(),
+			definitions: HashMap::new(),
+			scopes: Scopes::new(&program, &source_map),
+			source_map,
+		};
+		analysis.scan();
+		let resolved = match errors.is_empty() {
+			true => analysis.resolved(&program),
+			false => None,
+		};
+		analysis.globals = match resolved {
+			Some(globals) => globals,
+			None => analysis.scanned(),
+		};
+		analysis.definitions = analysis.bind();
+		if checked {
+			analysis.diagnostics =
+				check::check_parsed(&program, &analysis.source_map, errors);
+		}
+		analysis
+	}
+
+	/** Finds the definitions and variables in the tokens */
+	fn scan(&mut self) {
+		let tokens = &self.tokens;
+		let mut open: Vec<usize> = Vec::new(); // regions whose body isn't closed
+		let mut depth: Vec<usize> = Vec::new(); // the brackets open in each one
+		let mut token = 0;
+		while token < tokens.len() {
+			let text = tokens[token].text.as_str();
+			match text {
+				"fn" | "result" => {
+					let parent = open.last().copied();
+					let (region, body) = self.region(token, parent);
+					self.regions.push(region);
+					open.push(self.regions.len() - 1);
+					depth.push(0);
+					token = body;
+					continue;
+				}
+				"var" => {
+					if let Some(name) = tokens.get(token + 1) {
+						let mut detail = format!("var {}", name.text);
+						if tokens.get(token + 2).map(|t| t.text.as_str())
+							== Some(":")
+						{
+							if let Some(kind) = tokens.get(token + 3) {
+								detail = format!("{}: {}", detail, kind.text);
+							}
+						}
+						self.variables.push(Variable {
+							name: name.clone(),
+							detail,
+						});
+					}
+				}
+				"{" => {
+					if let Some(depth) = depth.last_mut() {
+						*depth += 1;
+					}
+				}
+				"}" => {
+					if let Some(brackets) = depth.last_mut() {
+						*brackets -= 1;
+						if *brackets == 0 {
+							depth.pop();
+							if let Some(region) = open.pop() {
+								let body = &mut self.regions[region].body;
+								body.end = tokens[token].span.end;
+							}
+						}
+					}
+				}
+				_ => (),
+			}
+			token += 1;
+		}
+
+		// bodies which are never closed go to the end of the code
+		for region in open {
+			self.regions[region].body.end = self.code.len();
+		}
+	}
+
+	/**
+	 * Reads the header of the definition starting at `start`
+	 * Returns the region, and the index of the `{` which starts its body
+	 */
+	fn region(&self, start: usize, parent: Option<usize>) -> (Region, usize) {
+		let tokens = &self.tokens;
+		let mut end = start + 1;
+		while end < tokens.len() && tokens[end].text != "{" {
+			end += 1;
+		}
+		let header = &tokens[start..end];
+		let span = header
+			.iter()
+			.fold(tokens[start].span, |span, token| span.to(token.span));
+		let body = tokens
+			.get(end)
+			.map_or(Span::new(span.end, self.code.len()), |t| {
+				Span::new(t.span.start, self.code.len())
+			});
+
+		let is_name = |token: &&Token| is_identifier(&token.text);
+		let name = header.get(1).filter(is_name).cloned();
+		let arrow = header.iter().position(|t| t.text == "=>");
+		let result = arrow
+			.and_then(|i| header.get(i + 1))
+			.map(|t| t.text.clone());
+
+		let parent_kind = parent.map(|p| self.regions[p].kind);
+		let kind = if header[0].text == "result" {
+			SymbolKind::Result
+		} else if result.is_some() || parent_kind == Some(SymbolKind::Result) {
+			SymbolKind::Implementation
+		} else if parent.is_some() {
+			SymbolKind::Closure
+		} else {
+			SymbolKind::Function
+		};
+
+		let region = Region {
+			kind,
+			name,
+			result,
+			header: span,
+			body,
+			parameters: parameters(header),
+			parent,
+		};
+		(region, end)
+	}
+
+	/** Gets the result that an implementation belongs to */
+	fn result_of(&self, region: &Region) -> Option<&Region> {
+		match (&region.result, region.parent) {
+			(Some(name), _) => self.regions.iter().find(|r| {
+				r.kind == SymbolKind::Result
+					&& r.name.as_ref().map(|n| &n.text) == Some(name)
+			}),
+			(None, Some(parent)) => Some(&self.regions[parent]),
+			(None, None) => None,
+		}
+	}
+
+	/** Gets the definitions which are seen by every part of the code */
+	pub fn globals(&self) -> Vec<Definition> {
+		self.globals.clone()
+	}
+
+	/**
+	 * Gets the free functions and results in the parsed program, or nothing
+	 * if the code can't be parsed or a name is defined more than once
+	 */
+	fn resolved(&self, program: &Program) -> Option<Vec<Definition>> {
+		let source_map = &self.source_map;
+		let functions = program
+			.functions
+			.iter()
+			.filter_map(|f| f.signature.name.as_deref())
+			.map(|name| {
+				(name, SymbolKind::Function, source_map.function(name))
+			});
+		let results = program.results.iter().map(|r| {
+			let name = r.signature.name.as_str();
+			(
+				name,
+				SymbolKind::Result,
+				source_map.results.get(name).copied(),
+			)
+		});
+
+		// free functions come before results, like in the interpreter
+		let mut globals: Vec<Definition> = Vec::new();
+		for (name, kind, span) in functions.chain(results) {
+			if globals.iter().any(|d| d.name == name) {
+				return None;
+			}
+			let span = span?;
+			let region = self.regions.iter().find(|r| r.header == span)?;
+			globals.push(Definition {
+				name: String::from(name),
+				kind,
+				span: Some(span),
+				detail: self.header_text(region),
+			});
+		}
+		globals.extend(builtins());
+		Some(globals)
+	}
+
+	/** Gets the free functions and results by scanning the tokens */
+	fn scanned(&self) -> Vec<Definition> {
+		let mut globals = Vec::new();
+
+		// free functions come before results, like in the interpreter
+		for kind in &[SymbolKind::Function, SymbolKind::Result] {
+			for region in self.regions.iter().filter(|r| r.kind == *kind) {
+				if let Some(name) = &region.name {
+					globals.push(Definition {
+						name: name.text.clone(),
+						kind: *kind,
+						span: Some(region.header),
+						detail: self.header_text(region),
+					});
+				}
+			}
+		}
+		globals.extend(builtins());
+		globals
+	}
+
+	/**
+	 * Gets every name which can be used at an offset
+	 * If a name is shadowed, only the innermost definition is given
+	 */
+	pub fn scope(&self, offset: usize) -> Vec<Definition> {
+		let mut scope: Vec<Definition> = Vec::new();
+		let chain = self.enclosing(offset);
+		match self.locate(&chain, offset) {
+			Some((body, calls)) => {
+				for (body, name, declared) in self.scopes.visible(body, calls) {
+					scope.extend(self.declaration(body, &name, declared));
+				}
+			}
+			None => {
+				let result = self.outer_result(&chain);
+				scope.extend(
+					result.iter().flat_map(|r| &r.parameters).map(parameter),
+				);
+			}
+		}
+		scope.extend(self.globals());
+
+		let mut seen = HashSet::new();
+		scope.retain(|d| seen.insert(d.name.clone()));
+		scope
+	}
+
+	/**
+	 * Finds the innermost body around an offset which was parsed, and how
+	 * many of its calls come from statements which start before the offset
+	 */
+	fn locate(&self, chain: &[usize], offset: usize) -> Option<(usize, usize)> {
+		chain.iter().find_map(|region| {
+			let header = self.regions[*region].header;
+			let body = self.scopes.body(header)?;
+			let statements = self.source_map.calls.get(&header);
+			let calls = statements.map_or(0, |statements| {
+				statements.partition_point(|s| s.start < offset)
+			});
+			Some((body, calls))
+		})
+	}
+
+	/** Gets the result around some regions, if they're in one */
+	fn outer_result(&self, chain: &[usize]) -> Option<&Region> {
+		chain
+			.iter()
+			.map(|r| &self.regions[*r])
+			.find(|r| r.kind == SymbolKind::Result)
+	}
+
+	/** Creates the definition of a name declared in a body */
+	fn declaration(
+		&self,
+		body: usize,
+		name: &str,
+		declared: Declared,
+	) -> Option<Definition> {
+		let header = self.scopes.header(body)?;
+		let region = || self.regions.iter().find(|r| r.header == header);
+		match declared {
+			Declared::Parameter => self.parameter_of(region()?, name),
+			Declared::Variable(call) => {
+				let statement = self.source_map.call(header, call)?;
+				let variable = self.variables.iter().find(|v| {
+					v.name.text == name
+						&& statement.start <= v.name.span.start
+						&& v.name.span.end <= statement.end
+				})?;
+				Some(Definition {
+					name: String::from(name),
+					kind: SymbolKind::Variable,
+					span: Some(variable.name.span),
+					detail: variable.detail.clone(),
+				})
+			}
+			Declared::Closure(call) => {
+				let closure = self.source_map.closure(header, call)?;
+				let region =
+					self.regions.iter().find(|r| r.header == closure)?;
+				Some(Definition {
+					name: String::from(name),
+					kind: SymbolKind::Closure,
+					span: Some(region.header),
+					detail: self.header_text(region),
+				})
+			}
+		}
+	}
+
+	/**
+	 * Finds a parameter of a definition
+	 * An implementation's parameters either rename the result's parameters,
+	 * or repeat them and refer to the result's
+	 */
+	fn parameter_of(&self, region: &Region, name: &str) -> Option<Definition> {
+		let named = |(parameter, _): &&(Token, String)| parameter.text == name;
+		if region.kind != SymbolKind::Implementation {
+			return region.parameters.iter().find(named).map(parameter);
+		}
+
+		let renamed = region
+			.parameters
+			.iter()
+			.filter(|(name, detail)| *detail != name.text)
+			.find(named);
+		let inherited = self
+			.result_of(region)
+			.and_then(|r| r.parameters.iter().find(named));
+		renamed.or(inherited).map(parameter)
+	}
+
+	/** Gets the regions around an offset, from the innermost one out */
+	fn enclosing(&self, offset: usize) -> Vec<usize> {
+		let mut chain = Vec::new();
+		let mut region = self
+			.regions
+			.iter()
+			.rposition(|r| r.header.start <= offset && offset <= r.body.end);
+		while let Some(index) = region {
+			chain.push(index);
+			region = self.regions[index].parent;
+		}
+		chain
+	}
+
+	/** Finds the token at an offset, if it's a name */
+	pub fn name_at(&self, offset: usize) -> Option<&Token> {
+		// an offset can be at the end of one token and the start of another
+		let first = self.tokens.partition_point(|t| t.span.end < offset);
+		self.tokens[first..].iter().take(2).find(|t| {
+			t.span.start <= offset
+				&& offset <= t.span.end
+				&& is_identifier(&t.text)
+		})
+	}
+
+	/** Gets every token which could be the name of something */
+	pub fn names(&self) -> impl Iterator<Item = &Token> {
+		self.tokens.iter().filter(|t| is_identifier(&t.text))
+	}
+
+	/**
+	 * Gets every name with what it refers to, in order
+	 * This is one pass over the names, rather than a lookup for each one
+	 */
+	pub fn bindings(
+		&self,
+	) -> impl Iterator<Item = (&Token, Option<&Definition>)> {
+		self.names()
+			.map(move |name| (name, self.definitions.get(&name.span.start)))
+	}
+
+	/** Finds what the name at an offset refers to */
+	pub fn definition(&self, offset: usize) -> Option<Definition> {
+		let token = self.name_at(offset)?;
+		self.definitions.get(&token.span.start).cloned()
+	}
+
+	/** Finds what every name refers to */
+	fn bind(&self) -> HashMap<usize, Definition> {
+		let mut definitions = HashMap::new();
+
+		// the name of a definition refers to itself
+		for region in &self.regions {
+			if let Some(name) = &region.name {
+				definitions.insert(
+					name.span.start,
+					Definition {
+						name: name.text.clone(),
+						kind: region.kind,
+						span: Some(region.header),
+						detail: self.header_text(region),
+					},
+				);
+			}
+		}
+		for variable in &self.variables {
+			definitions
+				.entry(variable.name.span.start)
+				.or_insert_with(|| Definition {
+					name: variable.name.text.clone(),
+					kind: SymbolKind::Variable,
+					span: Some(variable.name.span),
+					detail: variable.detail.clone(),
+				});
+		}
+
+		let mut globals: HashMap<&str, &Definition> = HashMap::new();
+		for definition in &self.globals {
+			globals.entry(&definition.name).or_insert(definition);
+		}
+
+		// the regions are started in order, so the innermost one around a
+		// name is the last one which has started and hasn't ended
+		let mut open: Vec<usize> = Vec::new();
+		let mut next = 0;
+		for (index, token) in self.tokens.iter().enumerate() {
+			let offset = token.span.start;
+			while self
+				.regions
+				.get(next)
+				.is_some_and(|r| r.header.start <= offset)
+			{
+				open.push(next);
+				next += 1;
+			}
+			while open
+				.last()
+				.is_some_and(|r| self.regions[*r].body.end < offset)
+			{
+				open.pop();
+			}
+			if !is_identifier(&token.text) || definitions.contains_key(&offset)
+			{
+				continue;
+			}
+
+			let mut chain = Vec::new();
+			let mut region = open.last().copied();
+			while let Some(index) = region {
+				chain.push(index);
+				region = self.regions[index].parent;
+			}
+			let definition = match self.binding(index) {
+				Some(definition) => Some(definition),
+				None => self.lookup(token, &chain, &globals),
+			};
+			if let Some(definition) = definition {
+				definitions.insert(offset, definition);
+			}
+		}
+		definitions
+	}
+
+	/**
+	 * Finds what a name refers to, in the regions around it
+	 * This is the definition that `scope` would give for the name
+	 */
+	fn lookup(
+		&self,
+		token: &Token,
+		chain: &[usize],
+		globals: &HashMap<&str, &Definition>,
+	) -> Option<Definition> {
+		let name = token.text.as_str();
+		match self.locate(chain, token.span.start) {
+			Some((body, calls)) => {
+				let found = self
+					.scopes
+					.visible(body, calls)
+					.find(|(_, visible, _)| *visible == name);
+				if let Some((body, _, declared)) = found {
+					return self.declaration(body, name, declared);
+				}
+			}
+			None => {
+				let result = self.outer_result(chain);
+				if let Some(found) =
+					result.and_then(|r| self.parameter_of(r, name))
+				{
+					return Some(found);
+				}
+			}
+		}
+		globals.get(name).map(|d| (*d).clone())
+	}
+
+	/**
+	 * Finds what the name at an index refers to if it's bound to a result,
+	 * like the result in `fn => add` or the result's parameter in
+	 * `fn(first = one)`
+	 */
+	fn binding(&self, index: usize) -> Option<Definition> {
+		let token = &self.tokens[index];
+		let previous = self.tokens[..index].last()?;
+		if previous.text == "=>" {
+			return self
+				.globals
+				.iter()
+				.filter(|d| d.kind == SymbolKind::Result)
+				.find(|d| d.name == token.text)
+				.cloned();
+		}
+		if previous.text != "=" {
+			return None;
+		}
+
+		let region = self.regions.iter().find(|r| {
+			r.kind == SymbolKind::Implementation
+				&& r.header.start <= token.span.start
+				&& token.span.end <= r.header.end
+		})?;
+		self.result_of(region)?
+			.parameters
+			.iter()
+			.find(|(name, _)| name.text == token.text)
+			.map(parameter)
+	}
+
+	/**
+	 * Describes the name at an offset, as Markdown
+	 * This is its declaration, followed by its doc comment
+	 */
+	pub fn hover(&self, offset: usize) -> Option<(Span, String)> {
+		let token = self.name_at(offset)?;
+		let definition = self.definition(offset)?;
+		let mut text = format!("```ro\n{}\n```", definition.detail);
+		if let Some(span) = definition.span {
+			if let Some(docs) = self.doc_comment(span.start) {
+				text.push_str("\n\n");
+				text.push_str(&docs);
+			}
+		}
+		Some((token.span, text))
+	}
+
+	/**
+	 * Finds the doc comment before a definition, either `///` lines or a
+	 * `/** */` block
+	 */
+	pub fn doc_comment(&self, offset: usize) -> Option<String> {
+		// a declaration's comment comes before the line it's on
+		let line_start = self.code[..offset].rfind('\n').map_or(0, |i| i + 1);
+		let before = self.code[..line_start].trim_end();
+
+		if before.ends_with("*/") {
+			let start = before.rfind("/*")?;
+			if !before[start..].starts_with("/**") {
+				return None;
+			}
+			let lines: Vec<&str> = before[start + 3..before.len() - 2]
+				.lines()
+				.map(|line| line.trim().trim_start_matches('*').trim())
+				.filter(|line| !line.is_empty())
+				.collect();
+			return Some(lines.join("\n"));
+		}
+
+		let mut lines: Vec<&str> = before
+			.lines()
+			.rev()
+			.map(str::trim)
+			.take_while(|line| line.starts_with("///"))
+			.map(|line| line[3..].trim())
+			.collect();
+		lines.reverse();
+		if lines.is_empty() {
+			None
+		} else {
+			Some(lines.join("\n"))
+		}
+	}
+
+	/** Lists the results with their implementations, and the free functions */
+	pub fn outline(&self) -> Vec<Outline> {
+		let outline = |region: &Region, name: String| Outline {
+			name,
+			kind: region.kind,
+			detail: self.header_text(region),
+			span: region.header.to(region.body),
+			selection: region.header,
+			children: Vec::new(),
+		};
+
+		let mut symbols = Vec::new();
+		for (index, region) in self.regions.iter().enumerate() {
+			let name = region.name.as_ref().map(|n| n.text.clone());
+			match region.kind {
+				SymbolKind::Function => {
+					symbols.push(outline(region, name.unwrap_or_default()))
+				}
+				SymbolKind::Result => {
+					let mut symbol =
+						outline(region, name.clone().unwrap_or_default());
+					symbol.children = self
+						.regions
+						.iter()
+						.filter(|r| r.kind == SymbolKind::Implementation)
+						.filter(|r| {
+							r.parent == Some(index)
+								|| (r.result.is_some() && r.result == name)
+						})
+						.map(|r| {
+							let name = match &r.name {
+								Some(name) => name.text.clone(),
+								None => String::from("fn"),
+							};
+							outline(r, name)
+						})
+						.collect();
+					symbols.push(symbol);
+				}
+				_ => (),
+			}
+		}
+		symbols
+	}
+
+	/** Lists the names which could be typed at an offset */
+	pub fn completions(&self, offset: usize) -> Vec<Definition> {
+		let prefix = self
+			.name_at(offset)
+			.map(|t| &t.text[..offset.min(t.span.end) - t.span.start])
+			.unwrap_or_default();
+		self.scope(offset)
+			.into_iter()
+			.filter(|d| d.name.starts_with(prefix))
+			.collect()
+	}
+
+	/** Gets the header of a definition, with its whitespace tidied up */
+	fn header_text(&self, region: &Region) -> String {
+		self.code[region.header.start..region.header.end]
+			.split_whitespace()
+			.collect::<Vec<&str>>()
+			.join(" ")
+	}
+
+	/** Converts a byte offset to a zero-based line and UTF-16 column */
+	pub fn position(&self, offset: usize) -> (u32, u32) {
+		let offset = floor_char_boundary(&self.code, offset);
+		let before = &self.code[..offset];
+		let line = before.matches('\n').count();
+		let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+		let column = before[line_start..].encode_utf16().count();
+		(line as u32, column as u32)
+	}
+
+	/** Converts a zero-based line and UTF-16 column to a byte offset */
+	pub fn offset(&self, line: u32, column: u32) -> usize {
+		offset(&self.code, line, column)
+	}
+}
+
+/**
+ * Converts a zero-based line and UTF-16 column in some code to a byte offset
+ * Columns past the end of a line are at the end of it
+ */
+pub fn offset(code: &str, line: u32, column: u32) -> usize {
+	let mut start = 0;
+	for _ in 0..line {
+		match code[start..].find('\n') {
+			Some(i) => start += i + 1,
+			None => return code.len(),
+		}
+	}
+
+	let mut units = 0;
+	for (i, c) in code[start..].char_indices() {
+		if units >= column as usize || c == '\n' {
+			return start + i;
+		}
+		units += c.len_utf16();
+	}
+	code.len()
+}
+
+/** Creates the definitions of the built-in functions */
+fn builtins() -> impl Iterator<Item = Definition> {
+	BUILTINS.iter().map(|builtin| Definition {
+		name: String::from(*builtin),
+		kind: SymbolKind::Builtin,
+		span: None,
+		detail: format!("fn {}(...)", builtin),
+	})
+}
+
+/** Creates the definition of a parameter */
+fn parameter((name, detail): &(Token, String)) -> Definition {
+	Definition {
+		name: name.text.clone(),
+		kind: SymbolKind::Parameter,
+		span: Some(name.span),
+		detail: detail.clone(),
+	}
+}
+
+/**
+ * Reads the parameters in a definition's header, with how each one is
+ * declared, like `one: int` or `first = one`
+ */
+fn parameters(header: &[Token]) -> Vec<(Token, String)> {
+	let open = match header.iter().position(|t| t.text == "(") {
+		Some(open) => open,
+		None => return Vec::new(),
+	};
+	let close = header
+		.iter()
+		.position(|t| t.text == ")")
+		.unwrap_or(header.len());
+	if close <= open {
+		return Vec::new();
+	}
+
+	header[open + 1..close]
+		.split(|t| t.text == ",")
+		.filter(|p| p.first().is_some_and(|t| is_identifier(&t.text)))
+		.map(|p| {
+			let text: Vec<&str> = p.iter().map(|t| t.text.as_str()).collect();
+			let detail = text.join(" ").replace(" :", ":");
+			(p[0].clone(), detail)
+		})
+		.collect()
+}
+
+/** Checks if a token could be the name of something */
+fn is_identifier(text: &str) -> bool {
+	let keywords = ["fn", "result", "var", "return", "ret", "true", "false"];
+	text.chars()
+		.next()
+		.is_some_and(|c| c.is_alphabetic() || c == '_')
+		&& !keywords.contains(&text)
+}
+
+/** Moves an offset back until it's at the start of a character */
+fn floor_char_boundary(code: &str, offset: usize) -> usize {
+	let mut offset = offset.min(code.len());
+	while !code.is_char_boundary(offset) {
+		offset -= 1;
+	}
+	offset
+}
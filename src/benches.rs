@@ -9,12 +9,47 @@ use std::fs::read_to_string;
 extern crate test;
 use test::Bencher;
 
+/** Generates a large program, with a thousand copies of the benchmark */
+fn large_program() -> String {
+	let code = read_to_string("src/benchmark.ro").unwrap();
+	let mut program = String::new();
+	for i in 0..1000 {
+		program.push_str(&code.replace("add", &format!("add{}", i)));
+		program.push('\n');
+	}
+	program
+}
+
 #[bench]
 fn tokenize_bench(b: &mut Bencher) {
 	let code = read_to_string("src/benchmark.ro").unwrap();
 	b.iter(|| tokenize_with_block_comments(code.clone()))
 }
 
+#[bench]
+fn tokenize_large_bench(b: &mut Bencher) {
+	let code = large_program();
+	b.iter(|| tokenize_with_block_comments(code.clone()))
+}
+
+#[bench]
+fn lex_bench(b: &mut Bencher) {
+	let code = read_to_string("src/benchmark.ro").unwrap();
+	b.iter(|| lex_with_block_comments(&code))
+}
+
+#[bench]
+fn lex_large_bench(b: &mut Bencher) {
+	let code = large_program();
+	b.iter(|| lex_with_block_comments(&code))
+}
+
+#[bench]
+fn lexer_large_bench(b: &mut Bencher) {
+	let code = large_program();
+	b.iter(|| Lexer::from_reader(code.as_bytes()).count())
+}
+
 #[bench]
 fn code_block_bench(b: &mut Bencher) {
 	let code = read_to_string("src/benchmark.ro").unwrap();
@@ -56,10 +91,91 @@ fn parse_fn_sig_bench(b: &mut Bencher) {
 	let string_vec = |vec: Vec<&str>| -> Vec<String> {
 		vec.iter().map(|s| String::from(*s)).collect()
 	};
-	let mut func_parser = FuncParser::default();
-	func_parser.signature = string_vec(vec![
-		"(", "one", ":", "float", ",", "two", ":", "float", ")", ":", "float",
-		"=>", "add",
-	]);
+	let func_parser = FuncParser {
+		signature: string_vec(vec![
+			"(", "one", ":", "float", ",", "two", ":", "float", ")", ":",
+			"float", "=>", "add",
+		]),
+		..Default::default()
+	};
 	b.iter(|| func_parser.clone().parse_signature())
 }
+
+/**
+ * Moving the tokens through the parsers instead of cloning them took this
+ * from 31us to 23us
+ */
+#[bench]
+fn parse_bench(b: &mut Bencher) {
+	let code = read_to_string("src/benchmark.ro").unwrap();
+	b.iter(|| parse(code.clone()))
+}
+
+/**
+ * Moving the tokens through the parsers, and looking results up by name
+ * once, took this from 577ms to 29ms
+ */
+#[bench]
+fn parse_large_bench(b: &mut Bencher) {
+	let code = large_program();
+	b.iter(|| parse(code.clone()))
+}
+
+#[bench]
+fn cst_large_bench(b: &mut Bencher) {
+	let code = large_program();
+	b.iter(|| cst::parse(&code))
+}
+
+#[bench]
+fn reparse_large_bench(b: &mut Bencher) {
+	let code = large_program();
+	let tree = cst::parse(&code);
+	let middle = code[code.len() / 2..].find("one + two").unwrap();
+	let start = code.len() / 2 + middle;
+	let edit = span::TextEdit::new(span::Span::new(start, start + 3), "two");
+	b.iter(|| cst::reparse(&tree, &edit))
+}
+
+/** Generates a program which makes a lot of calls and does some arithmetic */
+fn busy_program() -> String {
+	let mut program = String::from(
+		"fn step(x: int): int {
+			var y = x * 3 + 1
+			return y % 1000 - x / 2
+		}
+
+		result scale(x: int, by: float): float {
+			fn {
+				return x * by
+			}
+		}
+
+		fn main() {
+			var a = 1
+",
+	);
+	for _ in 0..1000 {
+		program.push_str("\t\t\ta = step(a) + int(scale(a, 0.5))\n");
+	}
+	program.push_str("\t\t\tprint(a)\n\t\t}\n");
+	program
+}
+
+#[bench]
+fn interpret_bench(b: &mut Bencher) {
+	let program = parse(busy_program());
+	b.iter(|| interp::Interpreter::new(&program, std::io::sink()).run())
+}
+
+#[bench]
+fn bytecode_compile_bench(b: &mut Bencher) {
+	let program = parse(busy_program());
+	b.iter(|| bytecode::compile(&program))
+}
+
+#[bench]
+fn vm_bench(b: &mut Bencher) {
+	let module = bytecode::compile(&parse(busy_program()));
+	b.iter(|| vm::Vm::new(&module, std::io::sink()).run())
+}
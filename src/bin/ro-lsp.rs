@@ -0,0 +1,572 @@
+use lsp_server::Connection;
+use lsp_server::ExtractError;
+use lsp_server::Message;
+use lsp_server::Notification;
+use lsp_server::Request;
+use lsp_server::RequestId;
+use lsp_server::Response;
+use lsp_types::notification::DidChangeTextDocument;
+use lsp_types::notification::DidCloseTextDocument;
+use lsp_types::notification::DidOpenTextDocument;
+use lsp_types::notification::Notification as _;
+use lsp_types::notification::PublishDiagnostics;
+use lsp_types::request::Completion;
+use lsp_types::request::DocumentSymbolRequest;
+use lsp_types::request::GotoDefinition;
+use lsp_types::request::HoverRequest;
+use lsp_types::request::References;
+use lsp_types::request::Rename;
+use lsp_types::request::Request as _;
+use lsp_types::request::SemanticTokensFullRequest;
+use lsp_types::CompletionItem;
+use lsp_types::CompletionItemKind;
+use lsp_types::CompletionResponse;
+use lsp_types::DiagnosticRelatedInformation;
+use lsp_types::DiagnosticSeverity;
+use lsp_types::DocumentSymbol;
+use lsp_types::DocumentSymbolResponse;
+use lsp_types::GotoDefinitionResponse;
+use lsp_types::Hover;
+use lsp_types::HoverContents;
+use lsp_types::HoverProviderCapability;
+use lsp_types::Location;
+use lsp_types::MarkupContent;
+use lsp_types::MarkupKind;
+use lsp_types::OneOf;
+use lsp_types::Position;
+use lsp_types::PublishDiagnosticsParams;
+use lsp_types::Range;
+use lsp_types::SemanticToken;
+use lsp_types::SemanticTokenModifier;
+use lsp_types::SemanticTokenType;
+use lsp_types::SemanticTokens;
+use lsp_types::SemanticTokensFullOptions;
+use lsp_types::SemanticTokensLegend;
+use lsp_types::SemanticTokensOptions;
+use lsp_types::SemanticTokensResult;
+use lsp_types::SemanticTokensServerCapabilities;
+use lsp_types::ServerCapabilities;
+use lsp_types::SymbolKind as LspSymbolKind;
+use lsp_types::TextDocumentContentChangeEvent;
+use lsp_types::TextDocumentPositionParams;
+use lsp_types::TextDocumentSyncCapability;
+use lsp_types::TextDocumentSyncKind;
+use lsp_types::TextEdit;
+use lsp_types::Url;
+use lsp_types::WorkspaceEdit;
+use ro_parser::analysis;
+use ro_parser::analysis::Analysis;
+use ro_parser::analysis::Outline;
+use ro_parser::analysis::SymbolKind;
+use ro_parser::cst;
+use ro_parser::cst::SyntaxNode;
+use ro_parser::diagnostic::Label;
+use ro_parser::highlight;
+use ro_parser::highlight::Highlight;
+use ro_parser::refactor;
+use ro_parser::span::Span;
+use ro_parser::span::TextEdit as Edit;
+use ro_parser::symbol::Arena;
+use ro_parser::symbol::Symbol;
+
+use std::collections::HashMap;
+use std::error::Error;
+
+/*
+ * A language server for Ro, speaking LSP over stdin and stdout
+ *
+ * Documents are synced incrementally. Each change is made to the document's
+ * syntax tree with `cst::reparse`, which only parses the items around it,
+ * and then the code is checked again. The answers to requests come from
+ * `analysis`, which works in byte offsets, so positions are converted here. Each document keeps its names in its own
+ * arena, so the names of old versions are freed after every message.
+ */
+
+type Documents = HashMap<Url, Document>;
+
+/**
+ * An open document, with the syntax tree which is updated as it's edited,
+ * and the arena that keeps the names it uses
+ */
+struct Document {
+	tree: SyntaxNode,
+	analysis: Analysis,
+	_names: Arena,
+}
+
+impl Document {
+	fn new(tree: SyntaxNode) -> Self {
+		let names = Arena::new();
+		Document {
+			analysis: names.enter(|| Analysis::new(&tree.text())),
+			tree,
+			_names: names,
+		}
+	}
+
+	/** Makes the changes a client sent, reparsing the items around them */
+	fn change(&self, changes: Vec<TextDocumentContentChangeEvent>) -> Self {
+		let mut tree = self.tree.clone();
+		for change in changes {
+			tree = match change.range {
+				Some(range) => {
+					let code = tree.text();
+					let start = analysis::offset(
+						&code,
+						range.start.line,
+						range.start.character,
+					);
+					let end = analysis::offset(
+						&code,
+						range.end.line,
+						range.end.character,
+					);
+					let span = Span::new(start, end.max(start));
+					cst::reparse(&tree, &Edit::new(span, &change.text))
+				}
+				None => cst::parse(&change.text),
+			};
+		}
+		Document::new(tree)
+	}
+}
+
+fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
+	let (connection, threads) = Connection::stdio();
+	let capabilities = ServerCapabilities {
+		text_document_sync: Some(TextDocumentSyncCapability::Kind(
+			TextDocumentSyncKind::INCREMENTAL,
+		)),
+		hover_provider: Some(HoverProviderCapability::Simple(true)),
+		definition_provider: Some(OneOf::Left(true)),
+		document_symbol_provider: Some(OneOf::Left(true)),
+		completion_provider: Some(Default::default()),
+		references_provider: Some(OneOf::Left(true)),
+		rename_provider: Some(OneOf::Left(true)),
+		semantic_tokens_provider: Some(
+			SemanticTokensServerCapabilities::SemanticTokensOptions(
+				SemanticTokensOptions {
+					legend: SemanticTokensLegend {
+						token_types: Highlight::ALL
+							.iter()
+							.map(|h| token_type(*h))
+							.collect(),
+						token_modifiers: vec![
+							SemanticTokenModifier::DOCUMENTATION,
+						],
+					},
+					full: Some(SemanticTokensFullOptions::Bool(true)),
+					..Default::default()
+				},
+			),
+		),
+		..Default::default()
+	};
+	connection.initialize(serde_json::to_value(capabilities)?)?;
+
+	// the connection has to be dropped for the threads to finish
+	serve(connection)?;
+	threads.join()?;
+	Ok(())
+}
+
+/** Handles messages until the client asks the server to shut down */
+fn serve(connection: Connection) -> Result<(), Box<dyn Error + Sync + Send>> {
+	let mut documents = Documents::new();
+	for message in &connection.receiver {
+		match message {
+			Message::Request(request) => {
+				if connection.handle_shutdown(&request)? {
+					break;
+				}
+				// names which only come up while answering aren't kept
+				let response =
+					Arena::new().enter(|| respond(&documents, request));
+				connection.sender.send(Message::Response(response))?;
+			}
+			Message::Notification(notification) => {
+				if let Some(uri) = update(&mut documents, notification) {
+					let params = publish(&documents, uri);
+					let notification = Notification::new(
+						PublishDiagnostics::METHOD.into(),
+						params,
+					);
+					connection
+						.sender
+						.send(Message::Notification(notification))?;
+				}
+			}
+			Message::Response(_) => (),
+		}
+
+		// SAFETY: nothing borrows names between messages, and the names of
+		// open documents are kept by their arenas
+		unsafe { Symbol::collect() };
+	}
+	Ok(())
+}
+
+/**
+ * Keeps track of the open documents
+ * Returns the document whose diagnostics need to be published
+ */
+fn update(
+	documents: &mut Documents,
+	notification: Notification,
+) -> Option<Url> {
+	match notification.method.as_str() {
+		DidOpenTextDocument::METHOD => {
+			let params = notify::<DidOpenTextDocument>(notification)?;
+			let document = params.text_document;
+			let tree = cst::parse(&document.text);
+			documents.insert(document.uri.clone(), Document::new(tree));
+			Some(document.uri)
+		}
+		DidChangeTextDocument::METHOD => {
+			let params = notify::<DidChangeTextDocument>(notification)?;
+			let uri = params.text_document.uri;
+			let document = documents.get(&uri)?.change(params.content_changes);
+			documents.insert(uri.clone(), document);
+			Some(uri)
+		}
+		DidCloseTextDocument::METHOD => {
+			let params = notify::<DidCloseTextDocument>(notification)?;
+			let uri = params.text_document.uri;
+			documents.remove(&uri);
+			Some(uri)
+		}
+		_ => None,
+	}
+}
+
+/** Converts a document's diagnostics, which are empty once it's closed */
+fn publish(documents: &Documents, uri: Url) -> PublishDiagnosticsParams {
+	let analysis = match documents.get(&uri) {
+		Some(document) => &document.analysis,
+		None => return PublishDiagnosticsParams::new(uri, Vec::new(), None),
+	};
+
+	let diagnostics = analysis
+		.diagnostics
+		.iter()
+		.map(|diagnostic| {
+			let related = diagnostic
+				.notes
+				.iter()
+				.map(|note| DiagnosticRelatedInformation {
+					location: Location::new(
+						uri.clone(),
+						label_range(analysis, note),
+					),
+					message: note.message.clone(),
+				})
+				.collect();
+			lsp_types::Diagnostic {
+				range: label_range(analysis, &diagnostic.primary),
+				severity: Some(DiagnosticSeverity::ERROR),
+				source: Some(String::from("ro")),
+				message: diagnostic.primary.message.clone(),
+				related_information: Some(related),
+				..Default::default()
+			}
+		})
+		.collect();
+	PublishDiagnosticsParams::new(uri, diagnostics, None)
+}
+
+/** Answers a request, or says that it isn't supported */
+fn respond(documents: &Documents, request: Request) -> Response {
+	let id = request.id.clone();
+	let result = match request.method.as_str() {
+		HoverRequest::METHOD => {
+			extract::<HoverRequest>(request).map(|(id, params)| {
+				Response::new_ok(id, hover(documents, &params))
+			})
+		}
+		GotoDefinition::METHOD => {
+			extract::<GotoDefinition>(request).map(|(id, params)| {
+				Response::new_ok(
+					id,
+					definition(
+						documents,
+						&params.text_document_position_params,
+					),
+				)
+			})
+		}
+		DocumentSymbolRequest::METHOD => {
+			extract::<DocumentSymbolRequest>(request).map(|(id, params)| {
+				let uri = params.text_document.uri;
+				Response::new_ok(
+					id,
+					documents.get(&uri).map(|d| symbols(&d.analysis)),
+				)
+			})
+		}
+		Completion::METHOD => {
+			extract::<Completion>(request).map(|(id, params)| {
+				Response::new_ok(
+					id,
+					completion(documents, &params.text_document_position),
+				)
+			})
+		}
+		References::METHOD => {
+			extract::<References>(request).map(|(id, params)| {
+				Response::new_ok(
+					id,
+					references(documents, &params.text_document_position),
+				)
+			})
+		}
+		Rename::METHOD => extract::<Rename>(request).map(|(id, params)| {
+			match rename(documents, &params) {
+				Ok(edit) => Response::new_ok(id, edit),
+				Err(message) => Response::new_err(
+					id,
+					lsp_server::ErrorCode::RequestFailed as i32,
+					message,
+				),
+			}
+		}),
+		SemanticTokensFullRequest::METHOD => {
+			extract::<SemanticTokensFullRequest>(request).map(|(id, params)| {
+				let uri = params.text_document.uri;
+				Response::new_ok(id, documents.get(&uri).map(semantic_tokens))
+			})
+		}
+		_ => {
+			return Response::new_err(
+				id,
+				lsp_server::ErrorCode::MethodNotFound as i32,
+				format!("`{}` isn't supported", request.method),
+			)
+		}
+	};
+
+	result.unwrap_or_else(|e| {
+		Response::new_err(
+			id,
+			lsp_server::ErrorCode::InvalidParams as i32,
+			e.to_string(),
+		)
+	})
+}
+
+/** Reads the parameters of a notification */
+fn notify<N: lsp_types::notification::Notification>(
+	notification: Notification,
+) -> Option<N::Params> {
+	notification.extract(N::METHOD).ok()
+}
+
+/** Reads the parameters of a request */
+fn extract<R: lsp_types::request::Request>(
+	request: Request,
+) -> Result<(RequestId, R::Params), ExtractError<Request>> {
+	request.extract(R::METHOD)
+}
+
+/** Finds the document and offset that a request is about */
+fn locate<'a>(
+	documents: &'a Documents,
+	params: &TextDocumentPositionParams,
+) -> Option<(&'a Analysis, usize)> {
+	let analysis = &documents.get(&params.text_document.uri)?.analysis;
+	let position = params.position;
+	Some((analysis, analysis.offset(position.line, position.character)))
+}
+
+fn hover(
+	documents: &Documents,
+	params: &lsp_types::HoverParams,
+) -> Option<Hover> {
+	let (analysis, offset) =
+		locate(documents, &params.text_document_position_params)?;
+	let (span, text) = analysis.hover(offset)?;
+	Some(Hover {
+		contents: HoverContents::Markup(MarkupContent {
+			kind: MarkupKind::Markdown,
+			value: text,
+		}),
+		range: Some(range(analysis, span)),
+	})
+}
+
+fn definition(
+	documents: &Documents,
+	params: &TextDocumentPositionParams,
+) -> Option<GotoDefinitionResponse> {
+	let (analysis, offset) = locate(documents, params)?;
+	let span = analysis.definition(offset)?.span?;
+	let uri = params.text_document.uri.clone();
+	Some(GotoDefinitionResponse::Scalar(Location::new(
+		uri,
+		range(analysis, span),
+	)))
+}
+
+fn symbols(analysis: &Analysis) -> DocumentSymbolResponse {
+	DocumentSymbolResponse::Nested(
+		analysis
+			.outline()
+			.iter()
+			.map(|outline| symbol(analysis, outline))
+			.collect(),
+	)
+}
+
+#[allow(deprecated)] // `deprecated` has to be given to create the symbol
+fn symbol(analysis: &Analysis, outline: &Outline) -> DocumentSymbol {
+	DocumentSymbol {
+		name: outline.name.clone(),
+		detail: Some(outline.detail.clone()),
+		kind: match outline.kind {
+			SymbolKind::Result => LspSymbolKind::INTERFACE,
+			SymbolKind::Implementation => LspSymbolKind::METHOD,
+			_ => LspSymbolKind::FUNCTION,
+		},
+		tags: None,
+		deprecated: None,
+		range: range(analysis, outline.span),
+		selection_range: range(analysis, outline.selection),
+		children: Some(
+			outline
+				.children
+				.iter()
+				.map(|child| symbol(analysis, child))
+				.collect(),
+		),
+	}
+}
+
+fn completion(
+	documents: &Documents,
+	params: &TextDocumentPositionParams,
+) -> Option<CompletionResponse> {
+	let (analysis, offset) = locate(documents, params)?;
+	let items = analysis
+		.completions(offset)
+		.into_iter()
+		.map(|definition| CompletionItem {
+			label: definition.name,
+			kind: Some(match definition.kind {
+				SymbolKind::Parameter | SymbolKind::Variable => {
+					CompletionItemKind::VARIABLE
+				}
+				SymbolKind::Result => CompletionItemKind::INTERFACE,
+				_ => CompletionItemKind::FUNCTION,
+			}),
+			detail: Some(definition.detail),
+			..Default::default()
+		})
+		.collect();
+	Some(CompletionResponse::Array(items))
+}
+
+fn references(
+	documents: &Documents,
+	params: &TextDocumentPositionParams,
+) -> Option<Vec<Location>> {
+	let (analysis, offset) = locate(documents, params)?;
+	let uri = &params.text_document.uri;
+	let locations = refactor::references(analysis, offset)
+		.into_iter()
+		.map(|span| Location::new(uri.clone(), range(analysis, span)))
+		.collect();
+	Some(locations)
+}
+
+/** Renames a symbol, or explains why it can't be renamed */
+fn rename(
+	documents: &Documents,
+	params: &lsp_types::RenameParams,
+) -> Result<Option<WorkspaceEdit>, String> {
+	let position = &params.text_document_position;
+	let (analysis, offset) = match locate(documents, position) {
+		Some(found) => found,
+		None => return Ok(None),
+	};
+	let edits = refactor::rename(analysis, offset, &params.new_name)
+		.map_err(|diagnostic| diagnostic.primary.message)?
+		.into_iter()
+		.map(|edit| TextEdit::new(range(analysis, edit.span), edit.text))
+		.collect();
+	let uri = position.text_document.uri.clone();
+	Ok(Some(WorkspaceEdit::new(HashMap::from([(uri, edits)]))))
+}
+
+/**
+ * Gets the highlighted tokens in a document
+ * Each token is relative to the one before it, and tokens which cover more
+ * than one line are split into a token for each line
+ */
+fn semantic_tokens(document: &Document) -> SemanticTokensResult {
+	let analysis = &document.analysis;
+	let mut data = Vec::new();
+	let mut last = (0, 0); // where the last token started
+	for (span, highlight) in highlight::highlight_tree(analysis, &document.tree)
+	{
+		let index = Highlight::ALL.iter().position(|h| *h == highlight);
+		let text = &analysis.code[span.start..span.end];
+		let mut start = span.start;
+		for line in text.split('\n') {
+			let length = line.trim_end_matches('\r').encode_utf16().count();
+			if length > 0 {
+				let (line, column) = analysis.position(start);
+				let delta_start = if line == last.0 {
+					column - last.1
+				} else {
+					column
+				};
+				data.push(SemanticToken {
+					delta_line: line - last.0,
+					delta_start,
+					length: length as u32,
+					token_type: index.unwrap_or_default() as u32,
+					token_modifiers_bitset: u32::from(
+						highlight == Highlight::DocComment,
+					),
+				});
+				last = (line, column);
+			}
+			start += line.len() + 1;
+		}
+	}
+	SemanticTokensResult::Tokens(SemanticTokens {
+		result_id: None,
+		data,
+	})
+}
+
+/** Gets the LSP type of a highlight, whose index is in `Highlight::ALL` */
+fn token_type(highlight: Highlight) -> SemanticTokenType {
+	match highlight {
+		Highlight::Keyword => SemanticTokenType::KEYWORD,
+		Highlight::Result => SemanticTokenType::INTERFACE,
+		Highlight::Function => SemanticTokenType::FUNCTION,
+		Highlight::Parameter => SemanticTokenType::PARAMETER,
+		Highlight::Variable => SemanticTokenType::VARIABLE,
+		Highlight::Type => SemanticTokenType::TYPE,
+		Highlight::Operator => SemanticTokenType::OPERATOR,
+		Highlight::Number => SemanticTokenType::NUMBER,
+		Highlight::String => SemanticTokenType::STRING,
+		Highlight::Comment | Highlight::DocComment => {
+			SemanticTokenType::COMMENT
+		}
+	}
+}
+
+/** Converts a span of bytes to a range of LSP positions */
+fn range(analysis: &Analysis, span: Span) -> Range {
+	let position = |offset| {
+		let (line, character) = analysis.position(offset);
+		Position::new(line, character)
+	};
+	Range::new(position(span.start), position(span.end))
+}
+
+/** Gets the range of a label, or the start of the file if it has no span */
+fn label_range(analysis: &Analysis, label: &Label) -> Range {
+	range(analysis, label.span.unwrap_or_default())
+}
@@ -0,0 +1,363 @@
+use ro_parser::c;
+use ro_parser::check;
+use ro_parser::diagnostic::Diagnostic;
+use ro_parser::formatter;
+use ro_parser::formatter::FormatOptions;
+use ro_parser::formatter::Indent;
+use ro_parser::highlight;
+use ro_parser::interp::Interpreter;
+#[cfg(feature = "serde")]
+use ro_parser::json;
+use ro_parser::program::Program;
+use ro_parser::repl::Repl;
+use ro_parser::sexp;
+use ro_parser::tokenizer::tokenize_spanned;
+use ro_parser::wasm;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::process;
+use std::thread;
+
+const USAGE: &str = "usage: ro <command> [file...]
+
+commands:
+	tokens <file>     print each token and where it is
+	ast <file>        print the parsed program
+	check <file...>   look for errors without running anything
+	run <file>        run the program, starting at main
+	c <file>          print the program as C99, for `cc -std=c99 -lm`
+	wat <file>        print the program as WebAssembly text
+	fmt <file...>     format the files in place
+	html <file>       print the code as highlighted HTML
+	repl              evaluate Ro code interactively
+
+tokens and ast options:
+	--format sexp         print S-expressions (only for ast)
+	--format json         print a JSON document, if built with `serde`
+
+fmt options:
+	--check               list unformatted files instead of changing them
+	--spaces <n>          indent with n spaces instead of tabs
+	--space-before-colon  put a space before each `:`
+	--tight-arrows        don't put spaces around `=>`";
+
+// programs can make `MAX_DEPTH` calls before they're stopped, which needs
+// more stack than the main thread has in a debug build
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+fn main() {
+	let args: Vec<String> = env::args().skip(1).collect();
+	let thread = thread::Builder::new()
+		.stack_size(STACK_SIZE)
+		.spawn(move || command(&args))
+		.expect("couldn't start the main thread");
+	let result = match thread.join() {
+		Ok(result) => result,
+		Err(panic) => std::panic::resume_unwind(panic),
+	};
+
+	match result {
+		Ok(true) => (),
+		Ok(false) => process::exit(1),
+		Err(error) => {
+			eprintln!("error: {}", error);
+			process::exit(1);
+		}
+	}
+}
+
+/** Runs the command in the arguments */
+fn command(args: &[String]) -> io::Result<bool> {
+	let files = &args[args.len().min(1)..];
+	match (args.first().map(String::as_str), files.len()) {
+		(Some("tokens"), 1 | 3) => {
+			format_option("tokens", files, &["text", "json"]).and_then(tokens)
+		}
+		(Some("ast"), 1 | 3) => {
+			format_option("ast", files, &["text", "sexp", "json"]).and_then(ast)
+		}
+		(Some("check"), n) if n > 0 => check(files),
+		(Some("run"), 1) => run(&files[0]),
+		(Some("c"), 1) => compile(&files[0], c::program),
+		(Some("wat"), 1) => compile(&files[0], wasm::program),
+		(Some("fmt"), n) if n > 0 => fmt(files),
+		(Some("html"), 1) => html(&files[0]),
+		(Some("repl"), 0) => repl(),
+		_ => {
+			eprintln!("{}", USAGE);
+			process::exit(2);
+		}
+	}
+}
+
+/** Reads a file, mentioning its name if it can't be read */
+fn read(file: &str) -> io::Result<String> {
+	fs::read_to_string(file)
+		.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", file, e)))
+}
+
+// how the tokens and ast commands print what they find
+#[derive(Clone, Copy)]
+enum Format {
+	Text,
+	Sexp,
+	#[cfg(feature = "serde")]
+	Json,
+}
+
+/**
+ * Reads a file name, which might come with a `--format`
+ * Formats which the command can't print are refused
+ */
+fn format_option<'a>(
+	command: &str,
+	args: &'a [String],
+	supported: &[&str],
+) -> io::Result<(&'a str, Format)> {
+	let mut file = None;
+	let mut format = Format::Text;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		if arg == "--format" {
+			format = match args.next().map(String::as_str) {
+				Some(name) if !supported.contains(&name) => {
+					return Err(io::Error::new(
+						io::ErrorKind::InvalidInput,
+						format!("{} can't print the {} format", command, name),
+					))
+				}
+				Some("text") => Format::Text,
+				Some("sexp") => Format::Sexp,
+				#[cfg(feature = "serde")]
+				Some("json") => Format::Json,
+				#[cfg(not(feature = "serde"))]
+				Some("json") => return Err(io::Error::new(
+					io::ErrorKind::InvalidInput,
+					"JSON output needs ro to be built with the `serde` feature",
+				)),
+				other => {
+					return Err(io::Error::new(
+						io::ErrorKind::InvalidInput,
+						format!(
+							"unknown format: {}",
+							other.unwrap_or_default()
+						),
+					))
+				}
+			};
+		} else {
+			file = Some(arg.as_str());
+		}
+	}
+
+	match file {
+		Some(file) => Ok((file, format)),
+		None => {
+			eprintln!("{}", USAGE);
+			process::exit(2);
+		}
+	}
+}
+
+/** Prints each token, along with the line and column it starts on */
+fn tokens((file, format): (&str, Format)) -> io::Result<bool> {
+	let code = read(file)?;
+	match format {
+		Format::Text => (),
+		Format::Sexp => {
+			unreachable!("tokens can't be printed as S-expressions")
+		}
+		#[cfg(feature = "serde")]
+		Format::Json => {
+			println!("{}", json::tokens_to_json(&tokenize_spanned(&code)));
+			return Ok(true);
+		}
+	}
+
+	for token in tokenize_spanned(&code) {
+		let (line, column) = token.span.line_col(&code);
+		println!("{}:{}\t{}", line, column, token.text);
+	}
+	Ok(true)
+}
+
+/** Prints the parsed program */
+fn ast((file, format): (&str, Format)) -> io::Result<bool> {
+	let code = read(file)?;
+	match check::parse(&code) {
+		Ok(program) => {
+			match format {
+				Format::Text => println!("{:#?}", program),
+				Format::Sexp => print!("{}", sexp::program(&program)),
+				#[cfg(feature = "serde")]
+				Format::Json => println!("{}", json::program_to_json(&program)),
+			}
+			Ok(true)
+		}
+		Err(diagnostics) => {
+			for diagnostic in diagnostics {
+				eprint!("{}", diagnostic.render(file, &code));
+			}
+			Ok(false)
+		}
+	}
+}
+
+/** Checks each file, printing any problems */
+fn check(files: &[String]) -> io::Result<bool> {
+	let mut ok = true;
+	for file in files {
+		let code = read(file)?;
+		for diagnostic in check::check(&code) {
+			eprint!("{}", diagnostic.render(file, &code));
+			ok = false;
+		}
+	}
+	Ok(ok)
+}
+
+/** Checks a file, then runs it if there aren't any problems */
+fn run(file: &str) -> io::Result<bool> {
+	let code = read(file)?;
+	let diagnostics = check::check(&code);
+	if !diagnostics.is_empty() {
+		for diagnostic in diagnostics {
+			eprint!("{}", diagnostic.render(file, &code));
+		}
+		return Ok(false);
+	}
+
+	let program = match check::parse(&code) {
+		Ok(program) => program,
+		Err(_) => return Ok(false),
+	};
+	let stdout = io::stdout();
+	let mut interpreter =
+		Interpreter::with_source(&program, &code, stdout.lock());
+	match interpreter.run() {
+		Ok(_) => Ok(true),
+		Err(error) => {
+			eprint!("{}", error.render(file, &code));
+			Ok(false)
+		}
+	}
+}
+
+/**
+ * Checks a file, then prints what a backend compiles it to if there aren't
+ * any problems
+ */
+fn compile(
+	file: &str,
+	backend: fn(&Program) -> Result<String, Diagnostic>,
+) -> io::Result<bool> {
+	let code = read(file)?;
+	let diagnostics = check::check(&code);
+	if !diagnostics.is_empty() {
+		for diagnostic in diagnostics {
+			eprint!("{}", diagnostic.render(file, &code));
+		}
+		return Ok(false);
+	}
+
+	let program = match check::parse(&code) {
+		Ok(program) => program,
+		Err(_) => return Ok(false),
+	};
+	match backend(&program) {
+		Ok(code) => {
+			print!("{}", code);
+			Ok(true)
+		}
+		Err(diagnostic) => {
+			eprint!("{}", diagnostic.render(file, &code));
+			Ok(false)
+		}
+	}
+}
+
+/**
+ * Formats each file in place
+ * With `--check`, unformatted files are listed instead
+ */
+fn fmt(args: &[String]) -> io::Result<bool> {
+	let mut options = FormatOptions::default();
+	let mut check = false;
+	let mut files = Vec::new();
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--check" => check = true,
+			"--space-before-colon" => options.space_before_colon = true,
+			"--tight-arrows" => options.space_around_arrow = false,
+			"--spaces" => {
+				let n = args.next().and_then(|n| n.parse().ok());
+				match n {
+					Some(n) => options.indent = Indent::Spaces(n),
+					None => {
+						eprintln!("{}", USAGE);
+						process::exit(2);
+					}
+				}
+			}
+			_ => files.push(arg),
+		}
+	}
+
+	let mut ok = true;
+	for file in files {
+		let code = read(file)?;
+
+		// code which doesn't parse could lose the parts that were skipped
+		if let Err(diagnostics) = check::parse(&code) {
+			for diagnostic in diagnostics {
+				eprint!("{}", diagnostic.render(file, &code));
+			}
+			ok = false;
+			continue;
+		}
+
+		let formatted = formatter::format(&code, &options);
+		if formatted == code {
+			continue;
+		}
+		if check {
+			println!("{} is not formatted", file);
+			ok = false;
+		} else {
+			fs::write(file, formatted)?;
+		}
+	}
+	Ok(ok)
+}
+
+/** Prints a file as HTML, with each token's highlight as its class */
+fn html(file: &str) -> io::Result<bool> {
+	let code = read(file)?;
+	print!("{}", highlight::html(&code));
+	Ok(true)
+}
+
+/** Reads lines from stdin until it ends or `:quit` is entered */
+fn repl() -> io::Result<bool> {
+	let stdin = io::stdin();
+	let stdout = io::stdout();
+	let mut stdout = stdout.lock();
+	let mut repl = Repl::new();
+	let mut line = String::new();
+	loop {
+		write!(stdout, "{}", if repl.needs_more() { ". " } else { "> " })?;
+		stdout.flush()?;
+
+		line.clear();
+		if stdin.lock().read_line(&mut line)? == 0 || line.trim() == ":quit" {
+			writeln!(stdout)?;
+			return Ok(true);
+		}
+		repl.feed(line.trim_end_matches('\n'), &mut stdout)?;
+	}
+}
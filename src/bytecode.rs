@@ -0,0 +1,459 @@
+use crate::function::CallList;
+use crate::function::CallType;
+use crate::function::Function;
+use crate::function::Operation;
+use crate::interp::Value;
+use crate::program::Program;
+use crate::result::RoResult;
+use crate::symbol::Symbol;
+
+use std::collections::HashMap;
+use std::fmt;
+
+/*
+ * A compact format for running programs, which is quicker than walking the
+ * calls of each function
+ *
+ * Each function is compiled into a chunk of instructions for a stack
+ * machine. Instructions push values onto the stack and pop them off again,
+ * so `var x = one + two` becomes:
+ *
+ * 	load 0         ; one
+ * 	load 1         ; two
+ * 	add
+ * 	store 2        ; x
+ *
+ * Variables are kept in numbered local slots, and literals are kept in a
+ * pool of constants, so neither needs to be looked up by name. Calls are
+ * found when the program is compiled, unless the name might be a variable
+ * holding a closure, in which case the variable is checked first.
+ */
+
+// the functions which are built into the interpreter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Builtin {
+	Print,
+	Int,
+	Float,
+	String,
+}
+
+// what a call runs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+	Function(usize), // a chunk in `Module::functions`
+	Builtin(Builtin),
+	Unknown(Symbol), // a function which doesn't exist, which fails when called
+}
+
+// the stack is popped from the right, so arguments are pushed in order
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction {
+	Constant(usize),
+	Load(usize),
+	Store(usize),
+	Pop,
+	Operate(Operation),
+	Call(Target, usize),             // the number of arguments
+	CallLocal(usize, Target, usize), // a closure in the local, or the target
+	Closure(usize),                  // one of `Module::closures`
+	Jump(usize),
+	JumpUnless(usize), // pops a bool, and jumps if it's false
+	Return,
+}
+
+// a compiled function
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Chunk {
+	pub name: Symbol,
+	pub implementation: Option<usize>, // which implementation of a result
+	pub parameters: Vec<usize>,        // the locals the arguments go in
+	pub locals: Vec<Symbol>,           // the name of each local
+	pub constants: Vec<Value>,
+	pub code: Vec<Instruction>,
+	pub calls: Vec<usize>, // the call each instruction was compiled from
+}
+
+// a closure which can be made while a function runs
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prototype {
+	pub chunk: Chunk,
+	pub function: Function, // the code it came from, which closures keep
+	pub this: usize,        // the local which holds the closure itself
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Module {
+	pub functions: Vec<Chunk>,
+	pub closures: Vec<Prototype>,
+	pub globals: HashMap<Symbol, Target>, // what each name calls
+}
+
+impl Builtin {
+	pub const ALL: [Builtin; 4] = [
+		Builtin::Print,
+		Builtin::Int,
+		Builtin::Float,
+		Builtin::String,
+	];
+
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"print" => Some(Builtin::Print),
+			"int" => Some(Builtin::Int),
+			"float" => Some(Builtin::Float),
+			"string" => Some(Builtin::String),
+			_ => None,
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Builtin::Print => "print",
+			Builtin::Int => "int",
+			Builtin::Float => "float",
+			Builtin::String => "string",
+		}
+	}
+}
+
+impl Chunk {
+	/** Gets the local with the given name, adding it if there isn't one */
+	pub fn local(&mut self, name: Symbol) -> usize {
+		match self.locals.iter().position(|l| *l == name) {
+			Some(local) => local,
+			None => {
+				self.locals.push(name);
+				self.locals.len() - 1
+			}
+		}
+	}
+
+	/** Adds a constant to the pool, reusing it if it's already there */
+	pub fn constant(&mut self, value: Value) -> usize {
+		match self.constants.iter().position(|c| *c == value) {
+			Some(constant) => constant,
+			None => {
+				self.constants.push(value);
+				self.constants.len() - 1
+			}
+		}
+	}
+}
+
+/**
+ * Compiles a program
+ * Results are compiled from their first implementation, like the
+ * interpreter runs them
+ */
+pub fn compile(program: &Program) -> Module {
+	let mut module = Module::default();
+
+	// free functions are found before results, and results before builtins
+	for builtin in Builtin::ALL.iter().copied() {
+		let name = Symbol::from(builtin.name());
+		module.globals.insert(name, Target::Builtin(builtin));
+	}
+	for (i, result) in program.results.iter().enumerate() {
+		let name = result.signature.name;
+		module.globals.insert(name, Target::Function(i));
+	}
+	for (i, function) in program.functions.iter().enumerate() {
+		let name = function.signature.name.unwrap_or_default();
+		let target = Target::Function(program.results.len() + i);
+		module.globals.insert(name, target);
+	}
+
+	for result in &program.results {
+		let chunk = compile_result(&mut module, result);
+		module.functions.push(chunk);
+	}
+	for function in &program.functions {
+		let mut compiler = Compiler::new(
+			&mut module,
+			function.signature.name.unwrap_or_default(),
+		);
+		compiler.parameters(&function.parameter_order);
+		compiler.calls(&function.calls);
+		let chunk = compiler.finish();
+		module.functions.push(chunk);
+	}
+	module
+}
+
+/** Compiles the first implementation of a result */
+fn compile_result(module: &mut Module, result: &RoResult) -> Chunk {
+	let mut compiler = Compiler::new(module, result.signature.name);
+	compiler.chunk.implementation = Some(0);
+	compiler.parameters(&result.parameter_order);
+	let function = match result.functions.first() {
+		Some(function) => function,
+		None => return compiler.finish(),
+	};
+
+	// renamed parameters are copied from the original
+	for parameter in &function.parameter_order {
+		let parameters = function.signature.parameters.as_ref();
+		let original = parameters.and_then(|p| p.get(parameter));
+		if let Some(original) = original
+			.filter(|o| *o != parameter && result.parameter_order.contains(o))
+		{
+			let from = compiler.chunk.local(*original);
+			let to = compiler.chunk.local(*parameter);
+			compiler.emit(Instruction::Load(from));
+			compiler.emit(Instruction::Store(to));
+		}
+	}
+	compiler.calls(&function.calls);
+	compiler.finish()
+}
+
+// compiles the calls of one function into a chunk
+struct Compiler<'a> {
+	module: &'a mut Module,
+	chunk: Chunk,
+	current: usize, // the index of the call being compiled
+}
+
+impl<'a> Compiler<'a> {
+	fn new(module: &'a mut Module, name: Symbol) -> Self {
+		Compiler {
+			module,
+			chunk: Chunk {
+				name,
+				..Chunk::default()
+			},
+			current: 0,
+		}
+	}
+
+	fn emit(&mut self, instruction: Instruction) {
+		self.chunk.code.push(instruction);
+		self.chunk.calls.push(self.current);
+	}
+
+	fn parameters(&mut self, parameters: &[Symbol]) {
+		for parameter in parameters {
+			let local = self.chunk.local(*parameter);
+			self.chunk.parameters.push(local);
+		}
+	}
+
+	/**
+	 * Pushes the value of a variable or a literal
+	 * Names which aren't variables yet are given a local anyway, which
+	 * fails if it's read before it's written to
+	 */
+	fn value(&mut self, token: Symbol) {
+		if !self.chunk.locals.contains(&token) {
+			if let Some(value) = Value::from_literal(&token) {
+				let constant = self.chunk.constant(value);
+				self.emit(Instruction::Constant(constant));
+				return;
+			}
+		}
+		let local = self.chunk.local(token);
+		self.emit(Instruction::Load(local));
+	}
+
+	/** Calls a function, a closure in a variable or a builtin */
+	fn call(&mut self, name: Symbol, arguments: &[Symbol]) {
+		for argument in arguments {
+			self.value(*argument);
+		}
+		let target = match self.module.globals.get(&name) {
+			Some(target) => *target,
+			None => Target::Unknown(name),
+		};
+		let count = arguments.len();
+		match self.chunk.locals.iter().position(|l| *l == name) {
+			Some(local) => {
+				self.emit(Instruction::CallLocal(local, target, count))
+			}
+			None => self.emit(Instruction::Call(target, count)),
+		}
+	}
+
+	fn calls(&mut self, calls: &CallList) {
+		for (index, call) in calls.iter().enumerate() {
+			self.current = index;
+			for call in CallType::sets_to_ops(vec![call.clone()]) {
+				self.call_type(call);
+			}
+		}
+		self.current = calls.len();
+	}
+
+	/** Compiles a single call, which has already been lowered */
+	fn call_type(&mut self, call: CallType) {
+		match call {
+			CallType::Init(var) => {
+				let void = self.chunk.constant(Value::Void);
+				self.emit(Instruction::Constant(void));
+				let local = self.chunk.local(var);
+				self.emit(Instruction::Store(local));
+			}
+			CallType::Move(var, value) => {
+				self.value(value);
+				let local = self.chunk.local(var);
+				self.emit(Instruction::Store(local));
+			}
+			CallType::Operate(var, left, op, right) => {
+				self.value(left);
+				self.value(right);
+				self.emit(Instruction::Operate(op));
+				let local = self.chunk.local(var);
+				self.emit(Instruction::Store(local));
+			}
+			CallType::Call(name, arguments) => {
+				self.call(name, &arguments);
+				self.emit(Instruction::Pop);
+			}
+			CallType::SetCall(var, name, arguments) => {
+				self.call(name, &arguments);
+				let local = self.chunk.local(var);
+				self.emit(Instruction::Store(local));
+			}
+			CallType::Closure(var, function) => {
+				let local = self.chunk.local(var);
+				let closure = self.closure(var, function);
+				self.emit(Instruction::Closure(closure));
+				self.emit(Instruction::Store(local));
+			}
+			CallType::Return(value) => {
+				self.value(value);
+				self.emit(Instruction::Return);
+			}
+			CallType::Set(..) => (),
+		}
+	}
+
+	/**
+	 * Compiles a closure
+	 * It starts with the same locals as this function, so that it can see
+	 * the variables which are captured when it's made
+	 */
+	fn closure(&mut self, var: Symbol, function: Function) -> usize {
+		let mut compiler = Compiler::new(self.module, var);
+		compiler.chunk.locals = self.chunk.locals.clone();
+		let this = compiler.chunk.local(var);
+		compiler.parameters(&function.parameter_order);
+		compiler.calls(&function.calls);
+		let chunk = compiler.finish();
+		self.module.closures.push(Prototype {
+			chunk,
+			function,
+			this,
+		});
+		self.module.closures.len() - 1
+	}
+
+	/** Returns void from the end of the function */
+	fn finish(mut self) -> Chunk {
+		let void = self.chunk.constant(Value::Void);
+		self.emit(Instruction::Constant(void));
+		self.emit(Instruction::Return);
+		self.chunk
+	}
+}
+
+impl fmt::Display for Target {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Target::Function(index) => write!(f, "#{}", index),
+			Target::Builtin(builtin) => write!(f, "{}", builtin.name()),
+			Target::Unknown(name) => write!(f, "{}?", name),
+		}
+	}
+}
+
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Instruction::Constant(constant) => {
+				write!(f, "constant {}", constant)
+			}
+			Instruction::Load(local) => write!(f, "load {}", local),
+			Instruction::Store(local) => write!(f, "store {}", local),
+			Instruction::Pop => write!(f, "pop"),
+			Instruction::Operate(op) => {
+				let name = match op {
+					Operation::Add => "add",
+					Operation::Sub => "sub",
+					Operation::Mult => "mul",
+					Operation::Div => "div",
+					Operation::Mod => "mod",
+				};
+				write!(f, "{}", name)
+			}
+			Instruction::Call(target, count) => {
+				write!(f, "call {} {}", target, count)
+			}
+			Instruction::CallLocal(local, target, count) => {
+				write!(f, "call-local {} {} {}", local, target, count)
+			}
+			Instruction::Closure(closure) => write!(f, "closure {}", closure),
+			Instruction::Jump(to) => write!(f, "jump {}", to),
+			Instruction::JumpUnless(to) => write!(f, "jump-unless {}", to),
+			Instruction::Return => write!(f, "return"),
+		}
+	}
+}
+
+impl fmt::Display for Chunk {
+	/**
+	 * Disassembles the chunk, with the names of locals and the values of
+	 * constants written beside the instructions which use them
+	 */
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.implementation {
+			Some(index) => {
+				writeln!(f, "{} (implementation {}):", self.name, index)?
+			}
+			None => writeln!(f, "{}:", self.name)?,
+		}
+		for (i, instruction) in self.code.iter().enumerate() {
+			let line = format!("{:04}  {}", i, instruction);
+			let comment = match instruction {
+				Instruction::Constant(constant) => {
+					match &self.constants[*constant] {
+						Value::Str(string) => format!("{:?}", string),
+						value => value.to_string(),
+					}
+				}
+				Instruction::Load(local)
+				| Instruction::Store(local)
+				| Instruction::CallLocal(local, ..) => self.locals[*local].to_string(),
+				_ => {
+					writeln!(f, "\t{}", line)?;
+					continue;
+				}
+			};
+			writeln!(f, "\t{:<24}; {}", line, comment)?;
+		}
+		Ok(())
+	}
+}
+
+impl fmt::Display for Module {
+	/**
+	 * Disassembles each function, and then each closure
+	 * Calls to functions are written as `#index`, which is the order they're
+	 * written in here
+	 */
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let chunks = self.functions.iter();
+		let closures = self.closures.iter().map(|p| &p.chunk);
+		for (i, chunk) in chunks.enumerate() {
+			if i > 0 {
+				writeln!(f)?;
+			}
+			write!(f, "#{} {}", i, chunk)?;
+		}
+		for (i, chunk) in closures.enumerate() {
+			writeln!(f)?;
+			write!(f, "closure {} {}", i, chunk)?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,734 @@
+use crate::diagnostic::Diagnostic;
+use crate::function::Operation;
+use crate::interp::Value;
+use crate::ir::BlockId;
+use crate::ir::Instruction;
+use crate::ir::IrFunction;
+use crate::ir::Operand;
+use crate::ir::Register;
+use crate::ir::Terminator;
+use crate::program::Program;
+use crate::symbol::Symbol;
+use crate::typeck::Type;
+use crate::typed;
+use crate::typed::Definition;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/*
+ * Writes programs as portable C99, which can be compiled with
+ * `cc -std=c99 program.c -lm`
+ *
+ * Each implementation of a result becomes its own function, and the result
+ * itself calls the first one, like the interpreter does. Every value needs
+ * a type which can be worked out before the program runs, so functions
+ * which take parameters without types can't be compiled. Errors, like
+ * dividing by zero, are written to stderr with the same messages as the
+ * interpreter, and the program exits with a status of 1.
+ */
+
+// the helpers which the generated code uses
+const PRELUDE: &str = r#"#include <errno.h>
+#include <inttypes.h>
+#include <math.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef int ro_void;
+
+static inline void ro_error(const char *message)
+{
+	fflush(stdout);
+	fprintf(stderr, "error: %s\n", message);
+	exit(1);
+}
+
+static inline void *ro_alloc(size_t size)
+{
+	void *memory = malloc(size);
+	if (memory == NULL) {
+		ro_error("out of memory");
+	}
+	return memory;
+}
+
+static inline int64_t ro_add_int(int64_t a, int64_t b)
+{
+	return (int64_t)((uint64_t)a + (uint64_t)b);
+}
+
+static inline int64_t ro_sub_int(int64_t a, int64_t b)
+{
+	return (int64_t)((uint64_t)a - (uint64_t)b);
+}
+
+static inline int64_t ro_mul_int(int64_t a, int64_t b)
+{
+	return (int64_t)((uint64_t)a * (uint64_t)b);
+}
+
+static inline int64_t ro_div_int(int64_t a, int64_t b)
+{
+	if (b == 0) {
+		ro_error("division by zero");
+	}
+	if (b == -1) {
+		return ro_sub_int(0, a);
+	}
+	return a / b;
+}
+
+static inline int64_t ro_mod_int(int64_t a, int64_t b)
+{
+	if (b == 0) {
+		ro_error("division by zero");
+	}
+	if (b == -1) {
+		return 0;
+	}
+	return a % b;
+}
+
+static inline double ro_add_float(double a, double b)
+{
+	return a + b;
+}
+
+static inline double ro_sub_float(double a, double b)
+{
+	return a - b;
+}
+
+static inline double ro_mul_float(double a, double b)
+{
+	return a * b;
+}
+
+static inline double ro_div_float(double a, double b)
+{
+	if (b == 0.0) {
+		ro_error("division by zero");
+	}
+	return a / b;
+}
+
+static inline double ro_mod_float(double a, double b)
+{
+	if (b == 0.0) {
+		ro_error("division by zero");
+	}
+	return fmod(a, b);
+}
+
+static inline const char *ro_concat(const char *a, const char *b)
+{
+	size_t a_length = strlen(a);
+	size_t b_length = strlen(b);
+	char *string = ro_alloc(a_length + b_length + 1);
+	memcpy(string, a, a_length);
+	memcpy(string + a_length, b, b_length + 1);
+	return string;
+}
+
+/* writes the fewest digits which read back as the same value, without an
+ * exponent, like Rust does */
+static inline const char *ro_string_float(double value)
+{
+	char scientific[40];
+	char digits[40];
+	char *exponent_start;
+	char *string;
+	char *p;
+	int precision;
+	int exponent;
+	int count = 0;
+	int i;
+	bool negative;
+
+	if (value != value) {
+		return "NaN";
+	} else if (value == HUGE_VAL) {
+		return "inf";
+	} else if (value == -HUGE_VAL) {
+		return "-inf";
+	}
+
+	for (precision = 0; precision < 17; precision++) {
+		snprintf(scientific, sizeof scientific, "%.*e", precision, value);
+		if (strtod(scientific, NULL) == value) {
+			break;
+		}
+	}
+
+	negative = scientific[0] == '-';
+	exponent_start = strchr(scientific, 'e');
+	exponent = atoi(exponent_start + 1);
+	for (p = scientific + negative; p < exponent_start; p++) {
+		if (*p != '.') {
+			digits[count++] = *p;
+		}
+	}
+	while (count > 1 && digits[count - 1] == '0') {
+		count--;
+	}
+
+	string = ro_alloc(count + abs(exponent) + 4);
+	p = string;
+	if (negative) {
+		*p++ = '-';
+	}
+	if (exponent < 0) {
+		*p++ = '0';
+		*p++ = '.';
+		for (i = -1; i > exponent; i--) {
+			*p++ = '0';
+		}
+		memcpy(p, digits, count);
+		p += count;
+	} else if (exponent + 1 >= count) {
+		memcpy(p, digits, count);
+		p += count;
+		for (i = count; i <= exponent; i++) {
+			*p++ = '0';
+		}
+	} else {
+		memcpy(p, digits, exponent + 1);
+		p += exponent + 1;
+		*p++ = '.';
+		memcpy(p, digits + exponent + 1, count - exponent - 1);
+		p += count - exponent - 1;
+	}
+	*p = '\0';
+	return string;
+}
+
+static inline const char *ro_string_int(int64_t value)
+{
+	char *string = ro_alloc(24);
+	snprintf(string, 24, "%" PRId64, value);
+	return string;
+}
+
+static inline void ro_convert_error(const char *string, const char *type)
+{
+	size_t length = strlen(string) + strlen(type) + 40;
+	char *message = ro_alloc(length);
+	snprintf(
+		message,
+		length,
+		"type mismatch: can't convert \"%s\" to %s",
+		string,
+		type
+	);
+	ro_error(message);
+}
+
+/* copies a string without the whitespace around it */
+static inline char *ro_trim(const char *string)
+{
+	const char *end = string + strlen(string);
+	char *trimmed;
+	while (*string == ' ' || (*string >= '\t' && *string <= '\r')) {
+		string++;
+	}
+	while (end > string && (end[-1] == ' '
+		|| (end[-1] >= '\t' && end[-1] <= '\r'))) {
+		end--;
+	}
+	trimmed = ro_alloc(end - string + 1);
+	memcpy(trimmed, string, end - string);
+	trimmed[end - string] = '\0';
+	return trimmed;
+}
+
+static inline int64_t ro_parse_int(const char *string)
+{
+	char *trimmed = ro_trim(string);
+	char *end;
+	long long value;
+	errno = 0;
+	value = strtoll(trimmed, &end, 10);
+	if (*trimmed == '\0' || *end != '\0' || errno == ERANGE) {
+		ro_convert_error(string, "int");
+	}
+	return (int64_t)value;
+}
+
+static inline double ro_parse_float(const char *string)
+{
+	char *trimmed = ro_trim(string);
+	char *end;
+	double value = strtod(trimmed, &end);
+	if (*trimmed == '\0' || *end != '\0' || strpbrk(trimmed, "xX") != NULL) {
+		ro_convert_error(string, "float");
+	}
+	return value;
+}
+
+static inline int64_t ro_float_to_int(double value)
+{
+	if (value != value) {
+		return 0;
+	} else if (value >= 9223372036854775807.0) {
+		return INT64_MAX;
+	} else if (value <= -9223372036854775807.0 - 1.0) {
+		return INT64_MIN;
+	}
+	return (int64_t)value;
+}
+
+static inline void ro_print_int(int64_t value)
+{
+	printf("%" PRId64, value);
+}
+
+static inline void ro_print_float(double value)
+{
+	fputs(ro_string_float(value), stdout);
+}
+
+static inline void ro_print_bool(bool value)
+{
+	fputs(value ? "true" : "false", stdout);
+}
+
+static inline void ro_print_string(const char *value)
+{
+	fputs(value, stdout);
+}
+
+static inline void ro_print_void(ro_void value)
+{
+	(void)value;
+	fputs("void", stdout);
+}
+"#;
+
+/**
+ * Writes a resolved program as C99
+ * Fails if a value's type can't be worked out, or if the program uses
+ * something which can't be compiled, like closures
+ */
+pub fn program(program: &Program) -> Result<String, Diagnostic> {
+	let module = typed::lower(program)?;
+	let definitions = &module.definitions;
+	let callees = &module.callees;
+
+	// results call their first implementation
+	let results: Vec<&Definition> = definitions
+		.iter()
+		.filter(|d| d.implementation == Some(0))
+		.collect();
+
+	let mut c = String::from(PRELUDE);
+	c.push('\n');
+	for definition in definitions {
+		let name = c_name(definition);
+		c.push_str(&format!("{};\n", prototype(&name, &definition.function)));
+	}
+	for result in &results {
+		let name = format!("ro_{}", mangle(&result.name));
+		c.push_str(&format!("{};\n", prototype(&name, &result.function)));
+	}
+
+	for definition in definitions {
+		c.push('\n');
+		write_function(&mut c, definition, definitions, callees)?;
+	}
+
+	for result in &results {
+		let name = format!("ro_{}", mangle(&result.name));
+		let parameters: Vec<String> = result
+			.function
+			.parameters
+			.iter()
+			.map(|p| register(*p))
+			.collect();
+		c.push_str(&format!(
+			"\n{}\n{{\n\treturn {}({});\n}}\n",
+			prototype(&name, &result.function),
+			c_name(result),
+			parameters.join(", ")
+		));
+	}
+
+	match definitions
+		.iter()
+		.find(|d| d.name == "main" && d.implementation.is_none())
+	{
+		Some(main) if main.function.parameters.is_empty() => {
+			c.push_str("\nint main(void)\n{\n\tro_main();\n\treturn 0;\n}\n");
+			Ok(c)
+		}
+		Some(_) => Err(error("`main` can't take any parameters")),
+		None => Err(error("there's no `main` function")),
+	}
+}
+
+/** Gets the name of the C function a definition is written as */
+fn c_name(definition: &Definition) -> String {
+	match definition.implementation {
+		Some(i) => format!("ro_{}_{}", mangle(&definition.name), i),
+		None => format!("ro_{}", mangle(&definition.name)),
+	}
+}
+
+fn error(message: &str) -> Diagnostic {
+	Diagnostic::error(String::from(message), None)
+}
+
+/**
+ * Converts a Ro name into a C identifier
+ * Letters and digits are kept, underscores are doubled, and anything else
+ * is written as its bytes in hex, like `one'` becoming `one_27`
+ */
+fn mangle(name: &str) -> String {
+	let mut mangled = String::new();
+	for byte in name.bytes() {
+		if byte.is_ascii_alphanumeric() {
+			mangled.push(byte as char);
+		} else if byte == b'_' {
+			mangled.push_str("__");
+		} else {
+			mangled.push_str(&format!("_{:02x}", byte));
+		}
+	}
+	mangled
+}
+
+fn c_type(t: Type) -> &'static str {
+	match t {
+		Type::Int => "int64_t",
+		Type::Float => "double",
+		Type::Bool => "bool",
+		Type::Str => "const char *",
+		_ => "ro_void",
+	}
+}
+
+fn register(register: Register) -> String {
+	format!("r{}", register.0)
+}
+
+/** Writes the signature of a function, without a semicolon */
+fn prototype(name: &str, function: &IrFunction) -> String {
+	let parameters: Vec<String> = function
+		.parameters
+		.iter()
+		.map(|p| {
+			let t = function.registers[p.0].value_type;
+			format!("{} {}", c_type(t), register(*p))
+		})
+		.collect();
+	let parameters = match parameters.is_empty() {
+		true => String::from("void"),
+		false => parameters.join(", "),
+	};
+	format!("{} {}({})", c_type(function.return_type), name, parameters)
+}
+
+/** Writes a constant as a C literal */
+fn constant(value: &Value) -> String {
+	match value {
+		Value::Int(i64::MIN) => String::from("INT64_MIN"),
+		Value::Int(int) if i32::try_from(*int).is_ok() => int.to_string(),
+		Value::Int(int) => format!("INT64_C({})", int),
+		Value::Float(float) if float.is_nan() => String::from("NAN"),
+		Value::Float(float) if float.is_infinite() => {
+			String::from(if *float > 0.0 {
+				"HUGE_VAL"
+			} else {
+				"-HUGE_VAL"
+			})
+		}
+		Value::Float(float) => format!("{:?}", float),
+		Value::Bool(boolean) => boolean.to_string(),
+		Value::Str(string) => {
+			let mut literal = String::from("\"");
+			for byte in string.bytes() {
+				match byte {
+					b'"' => literal.push_str("\\\""),
+					b'\\' => literal.push_str("\\\\"),
+					// so `??` can't start a trigraph
+					b'?' => literal.push_str("\\?"),
+					b'\n' => literal.push_str("\\n"),
+					b'\t' => literal.push_str("\\t"),
+					b' '..=b'~' => literal.push(byte as char),
+					_ => literal.push_str(&format!("\\{:03o}", byte)),
+				}
+			}
+			literal.push('"');
+			literal
+		}
+		Value::Void | Value::Closure(_) => String::from("0"),
+	}
+}
+
+fn operand(operand: &Operand) -> String {
+	match operand {
+		Operand::Register(r) => register(*r),
+		Operand::Constant(value) => constant(value),
+	}
+}
+
+/** Writes a function, with its registers declared at the top */
+fn write_function(
+	c: &mut String,
+	definition: &Definition,
+	definitions: &[Definition],
+	callees: &HashMap<Symbol, usize>,
+) -> Result<(), Diagnostic> {
+	let function = &definition.function;
+	c.push_str(&prototype(&c_name(definition), function));
+	c.push_str("\n{\n");
+	for (i, info) in function.registers.iter().enumerate() {
+		if !function.parameters.contains(&Register(i)) {
+			let name = info.name.map_or(String::new(), |n| {
+				format!(" /* {} */", n.replace("*/", "* /"))
+			});
+			c.push_str(&format!(
+				"\t{} {} = {};{}\n",
+				c_type(info.value_type),
+				register(Register(i)),
+				match info.value_type {
+					Type::Str => "\"\"",
+					_ => "0",
+				},
+				name
+			));
+		}
+	}
+
+	// only blocks which are jumped to need a label
+	let predecessors = function.predecessors();
+	for block in function.reverse_postorder() {
+		if !predecessors[block.0].is_empty() {
+			c.push_str(&format!("bb{}:;\n", block.0));
+		}
+		let instructions = &function.blocks[block.0].instructions;
+		for instruction in instructions {
+			if matches!(instruction, Instruction::Phi(..)) {
+				continue;
+			}
+			let line =
+				statement(definition, instruction, definitions, callees)?;
+			c.push_str(&format!("\t{}\n", line));
+		}
+
+		match &function.blocks[block.0].terminator {
+			Terminator::Jump(to) => {
+				c.push_str(&format!("\t{}\n", edge(function, block, *to)))
+			}
+			Terminator::Branch(condition, yes, no) => {
+				if function.type_of(condition) != Type::Bool {
+					return Err(error(&format!(
+						"branches need a bool, in `{}`",
+						definition.name
+					)));
+				}
+				c.push_str(&format!(
+					"\tif ({}) {{\n\t\t{}\n\t}} else {{\n\t\t{}\n\t}}\n",
+					operand(condition),
+					edge(function, block, *yes),
+					edge(function, block, *no)
+				));
+			}
+			Terminator::Return(value) => {
+				if !function.type_of(value).fits(function.return_type) {
+					return Err(error(&format!(
+						"`{}` should return {}, but this returns {}",
+						definition.name,
+						function.return_type,
+						function.type_of(value)
+					)));
+				}
+				c.push_str(&format!("\treturn {};\n", operand(value)));
+			}
+		}
+	}
+	c.push_str("}\n");
+	Ok(())
+}
+
+/**
+ * Jumps from one block to another, setting the phis at the start of it
+ * The values are copied through temporaries, in case a phi reads another
+ */
+fn edge(function: &IrFunction, from: BlockId, to: BlockId) -> String {
+	let mut copies = Vec::new();
+	for instruction in &function.blocks[to.0].instructions {
+		if let Instruction::Phi(register, values) = instruction {
+			if let Some((_, value)) = values.iter().find(|v| v.0 == from) {
+				copies.push((*register, value));
+			}
+		}
+	}
+	if copies.is_empty() {
+		return format!("goto bb{};", to.0);
+	}
+
+	let mut edge = String::from("{ ");
+	for (i, (register, value)) in copies.iter().enumerate() {
+		let t = function.registers[register.0].value_type;
+		edge.push_str(&format!("{} t{} = {}; ", c_type(t), i, operand(value)));
+	}
+	for (i, (register, _)) in copies.iter().enumerate() {
+		edge.push_str(&format!("{} = t{}; ", self::register(*register), i));
+	}
+	edge.push_str(&format!("goto bb{}; }}", to.0));
+	edge
+}
+
+/** Writes an instruction as a C statement */
+fn statement(
+	definition: &Definition,
+	instruction: &Instruction,
+	definitions: &[Definition],
+	callees: &HashMap<Symbol, usize>,
+) -> Result<String, Diagnostic> {
+	let function = &definition.function;
+	let assign = |register: &Option<Register>, value: String| match register {
+		Some(register) => format!("{} = {};", self::register(*register), value),
+		None => format!("(void){};", value),
+	};
+
+	match instruction {
+		Instruction::Move(register, value) => {
+			Ok(assign(&Some(*register), operand(value)))
+		}
+		Instruction::Operate(register, left, op, right) => {
+			let types = (function.type_of(left), function.type_of(right));
+			let name = match op {
+				Operation::Add => "add",
+				Operation::Sub => "sub",
+				Operation::Mult => "mul",
+				Operation::Div => "div",
+				Operation::Mod => "mod",
+			};
+			let value = match types {
+				(Type::Int, Type::Int) => format!(
+					"ro_{}_int({}, {})",
+					name,
+					operand(left),
+					operand(right)
+				),
+				(Type::Int | Type::Float, Type::Int | Type::Float) => format!(
+					"ro_{}_float({}, {})",
+					name,
+					operand(left),
+					operand(right)
+				),
+				(Type::Str, Type::Str) if *op == Operation::Add => {
+					format!("ro_concat({}, {})", operand(left), operand(right))
+				}
+				(left, right) => {
+					return Err(error(&format!(
+						"can't apply {} to {} and {}, in `{}`",
+						op, left, right, definition.name
+					)))
+				}
+			};
+			Ok(assign(&Some(*register), value))
+		}
+		Instruction::Call(register, name, arguments) => {
+			let types: Vec<Type> =
+				arguments.iter().map(|a| function.type_of(a)).collect();
+			let values: Vec<String> = arguments.iter().map(operand).collect();
+
+			if let Some(callee) = callees.get(name) {
+				let callee = &definitions[*callee];
+				let parameters: Vec<Type> = callee
+					.function
+					.parameters
+					.iter()
+					.map(|p| callee.function.registers[p.0].value_type)
+					.collect();
+				if parameters.len() != types.len()
+					|| types.iter().zip(&parameters).any(|(a, p)| !a.fits(*p))
+				{
+					return Err(error(&format!(
+						"`{}` can't be called with ({}), in `{}`",
+						name,
+						types
+							.iter()
+							.map(|t| t.to_string())
+							.collect::<Vec<String>>()
+							.join(", "),
+						definition.name
+					)));
+				}
+				let c_name = format!("ro_{}", mangle(name));
+				let call = format!("{}({})", c_name, values.join(", "));
+				return Ok(match register {
+					Some(_) => assign(register, call),
+					None => format!("{};", call),
+				});
+			}
+
+			if *name == "print" {
+				let mut line = String::new();
+				for (i, (t, value)) in types.iter().zip(&values).enumerate() {
+					if i > 0 {
+						line.push_str("fputs(\" \", stdout); ");
+					}
+					let print = match t {
+						Type::Int => "int",
+						Type::Float => "float",
+						Type::Bool => "bool",
+						Type::Str => "string",
+						_ => "void",
+					};
+					line.push_str(&format!("ro_print_{}({}); ", print, value));
+				}
+				line.push_str("putchar('\\n');");
+				if let Some(register) = register {
+					line.push_str(&format!(
+						" {} = 0;",
+						self::register(*register)
+					));
+				}
+				return Ok(line);
+			}
+
+			let (t, value) = match (types.as_slice(), values.as_slice()) {
+				([t], [value]) => (*t, value),
+				_ => {
+					return Err(error(&format!(
+						"`{}` takes 1 arguments but {} were given, in `{}`",
+						name,
+						values.len(),
+						definition.name
+					)))
+				}
+			};
+			let value = match (name.as_str(), t) {
+				("int", Type::Int) | ("float", Type::Float) => value.clone(),
+				("int", Type::Float) => format!("ro_float_to_int({})", value),
+				("int", Type::Str) => format!("ro_parse_int({})", value),
+				("float", Type::Int) => format!("(double){}", value),
+				("float", Type::Str) => format!("ro_parse_float({})", value),
+				("string", Type::Int) => format!("ro_string_int({})", value),
+				("string", Type::Float) => {
+					format!("ro_string_float({})", value)
+				}
+				("string", Type::Bool) => {
+					format!("({} ? \"true\" : \"false\")", value)
+				}
+				("string", Type::Str) => value.clone(),
+				("string", Type::Void) => String::from("\"void\""),
+				(_, t) => {
+					return Err(error(&format!(
+						"can't convert {} to {}, in `{}`",
+						t, name, definition.name
+					)))
+				}
+			};
+			Ok(assign(register, value))
+		}
+		Instruction::Phi(..) => Ok(String::new()),
+	}
+}
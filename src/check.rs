@@ -0,0 +1,71 @@
+use crate::diagnostic::Diagnostic;
+use crate::parser::Parser;
+use crate::program::Program;
+use crate::resolve::resolve;
+use crate::span::SourceMap;
+use crate::span::Span;
+use crate::tokenizer::tokenize_spanned;
+use crate::typeck::typecheck;
+
+/**
+ * Parses some code, turning the problems that the parser found into
+ * diagnostics if there are any
+ */
+pub fn parse(code: &str) -> Result<Program, Vec<Diagnostic>> {
+	let (program, _, diagnostics) = parse_with_source_map(code);
+	if diagnostics.is_empty() {
+		Ok(program)
+	} else {
+		Err(diagnostics)
+	}
+}
+
+/**
+ * Parses some code, also finding where everything in it came from
+ * The parser recovers from problems, so this gives a program even if
+ * there are diagnostics
+ */
+pub fn parse_with_source_map(
+	code: &str,
+) -> (Program, SourceMap, Vec<Diagnostic>) {
+	let spanned = tokenize_spanned(code);
+	let tokens: Vec<String> = spanned.iter().map(|t| t.text.clone()).collect();
+	let spans: Vec<Span> = spanned.iter().map(|t| t.span).collect();
+	let mut parser = Parser::with_spans(&tokens, &spans);
+	let program = parser.program();
+	let diagnostics = parser
+		.errors()
+		.iter()
+		.map(|(token, message)| {
+			let span = spans.get(*token).copied();
+			Diagnostic::error(message.clone(), span)
+		})
+		.collect();
+	(program, parser.into_source_map(), diagnostics)
+}
+
+/** Parses, resolves and type checks some code */
+pub fn check(code: &str) -> Vec<Diagnostic> {
+	let (program, source_map, errors) = parse_with_source_map(code);
+	check_parsed(&program, &source_map, errors)
+}
+
+/**
+ * Resolves and type checks a program which has already been parsed, given
+ * the problems that the parser found
+ */
+pub fn check_parsed(
+	program: &Program,
+	source_map: &SourceMap,
+	errors: Vec<Diagnostic>,
+) -> Vec<Diagnostic> {
+	if !errors.is_empty() {
+		return errors;
+	}
+
+	let diagnostics = resolve(program, source_map);
+	if !diagnostics.is_empty() {
+		return diagnostics;
+	}
+	typecheck(program, source_map)
+}
@@ -0,0 +1,189 @@
+use crate::check;
+use crate::diagnostic::Diagnostic;
+use crate::function::CallType;
+use crate::function::Function;
+use crate::function::Operation;
+use crate::program::Program;
+use crate::resolve::resolve;
+use crate::symbol::Symbol;
+
+use std::collections::HashSet;
+
+/**
+ * Something which code can be generated for
+ * Functions are given to it one at a time, as operations on named variables
+ * Values are either variables or literals, written as they are in the code
+ * Every variable is declared before it's first given a value, including
+ * the temporaries which expressions are broken into
+ */
+pub trait Backend {
+	type Output;
+
+	/** Starts a function, which takes the given parameters */
+	fn function(&mut self, name: &str, parameters: &[Symbol]);
+
+	/** Declares a variable in the current function */
+	fn declare(&mut self, var: Symbol);
+
+	/** Stores `left op right` in `var` */
+	fn operate(
+		&mut self,
+		var: Symbol,
+		left: Symbol,
+		op: &Operation,
+		right: Symbol,
+	);
+
+	/** Copies a value into `var` */
+	fn move_value(&mut self, var: Symbol, value: Symbol);
+
+	/** Calls a function, storing what it returns in `var` if there is one */
+	fn call(&mut self, var: Option<Symbol>, function: Symbol, args: &[Symbol]);
+
+	/** Returns a value from the current function */
+	fn ret(&mut self, value: Symbol);
+
+	/** Finishes the program, once every function has been given */
+	fn finish(self) -> Self::Output;
+}
+
+/**
+ * Gets the name that an implementation of a result is generated as
+ * This has a `.` in it, so it can't be written in Ro
+ */
+pub fn implementation_name(result: &str, index: usize) -> String {
+	format!("{}.{}", result, index)
+}
+
+/**
+ * Generates code for a resolved program
+ * Each implementation of a result becomes its own function, and the
+ * result becomes a function which calls its first implementation, like in
+ * the interpreter
+ */
+pub fn generate<B: Backend>(
+	program: &Program,
+	mut backend: B,
+) -> Result<B::Output, Diagnostic> {
+	for result in &program.results {
+		let name = &result.signature.name;
+		let parameters = &result.parameter_order;
+		for (index, function) in result.functions.iter().enumerate() {
+			let implementation = implementation_name(name, index);
+			backend.function(&implementation, parameters);
+
+			// renamed parameters are copied from the original
+			for parameter in &function.parameter_order {
+				let renames = function.signature.parameters.as_ref();
+				let original = renames.and_then(|p| p.get(parameter));
+				if let Some(original) = original.filter(|o| *o != parameter) {
+					backend.declare(*parameter);
+					backend.move_value(*parameter, *original);
+				}
+			}
+			let mut declared: HashSet<Symbol> =
+				parameters.iter().copied().collect();
+			declared.extend(&function.parameter_order);
+			generate_calls(&mut backend, &implementation, function, declared)?;
+		}
+
+		backend.function(name, parameters);
+		if result.functions.is_empty() {
+			backend.ret(Symbol::from("void"));
+		} else {
+			let value = Symbol::from("return");
+			let first = Symbol::from(implementation_name(name, 0));
+			backend.declare(value);
+			backend.call(Some(value), first, parameters);
+			backend.ret(value);
+		}
+	}
+
+	for function in &program.functions {
+		let name = function.signature.name.as_deref().unwrap_or_default();
+		backend.function(name, &function.parameter_order);
+		let declared = function.parameter_order.iter().copied().collect();
+		generate_calls(&mut backend, name, function, declared)?;
+	}
+
+	Ok(backend.finish())
+}
+
+/**
+ * Gives the calls in a function to the backend
+ * Variables which aren't in `declared` yet are declared the first time
+ * they're given a value
+ */
+fn generate_calls<B: Backend>(
+	backend: &mut B,
+	name: &str,
+	function: &Function,
+	mut declared: HashSet<Symbol>,
+) -> Result<(), Diagnostic> {
+	let calls = CallType::sets_to_ops(function.calls.clone());
+	for call in &calls {
+		let defined = match call {
+			CallType::Init(var)
+			| CallType::Move(var, _)
+			| CallType::Operate(var, ..)
+			| CallType::SetCall(var, ..) => Some(*var),
+			_ => None,
+		};
+		if let Some(var) = defined.filter(|var| declared.insert(*var)) {
+			backend.declare(var);
+		}
+
+		match call {
+			CallType::Init(_) => (),
+			CallType::Move(var, value) => backend.move_value(*var, *value),
+			CallType::Operate(var, left, op, right) => {
+				backend.operate(*var, *left, op, *right)
+			}
+			CallType::Call(func_name, parameters) => {
+				backend.call(None, *func_name, parameters)
+			}
+			CallType::SetCall(var, func_name, parameters) => {
+				backend.call(Some(*var), *func_name, parameters)
+			}
+			CallType::Return(value) => backend.ret(*value),
+			CallType::Closure(var, _) => {
+				return Err(Diagnostic::error(
+					format!(
+						"`{}` in `{}` is a closure, which can't be compiled yet",
+						var, name
+					),
+					None,
+				))
+			}
+			CallType::Set(var, _) => {
+				return Err(Diagnostic::error(
+					format!(
+						"`{}` in `{}` wasn't broken into operations",
+						var, name
+					),
+					None,
+				))
+			}
+		}
+	}
+	Ok(())
+}
+
+/**
+ * Parses, resolves and generates code for some code
+ * Nothing is generated if there are any errors
+ */
+pub fn compile<B: Backend>(
+	code: &str,
+	backend: B,
+) -> Result<B::Output, Vec<Diagnostic>> {
+	let (program, source_map, diagnostics) = check::parse_with_source_map(code);
+	if !diagnostics.is_empty() {
+		return Err(diagnostics);
+	}
+	let diagnostics = resolve(&program, &source_map);
+	if !diagnostics.is_empty() {
+		return Err(diagnostics);
+	}
+	generate(&program, backend).map_err(|d| vec![d])
+}
@@ -0,0 +1,603 @@
+use crate::span::Span;
+use crate::span::TextEdit;
+use crate::tokenizer::tokenize_spanned_with_block_comments;
+use crate::tokenizer::TokenList;
+use crate::tokenizer::OPERATORS;
+
+use std::fmt;
+use std::rc::Rc;
+
+// words which can't be used as names
+pub const KEYWORDS: [&str; 5] = ["fn", "result", "var", "ret", "return"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SyntaxKind {
+	// tokens which the parser ignores
+	Whitespace,
+	LineComment,
+	DocComment,
+	BlockComment,
+
+	// tokens which the parser uses
+	Keyword,
+	Name,
+	Number,
+	Str,
+	Operator,
+
+	// nodes
+	Root,
+	Function,
+	Result,
+	Signature,
+	Body,
+	Error,
+}
+
+/*
+ * The green tree is immutable and doesn't know where it is,
+ * so unchanged parts can be shared between edits
+ */
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GreenToken {
+	pub kind: SyntaxKind,
+	pub text: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GreenElement {
+	Node(Rc<GreenNode>),
+	Token(Rc<GreenToken>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GreenNode {
+	pub kind: SyntaxKind,
+	pub width: usize, // the length of its text in bytes
+	pub children: Vec<GreenElement>,
+}
+
+/*
+ * The red tree is created from the green tree as it's walked,
+ * and knows its parent and position
+ */
+
+#[derive(Clone, Debug)]
+pub struct SyntaxNode {
+	data: Rc<NodeData>,
+}
+
+#[derive(Debug)]
+struct NodeData {
+	green: Rc<GreenNode>,
+	parent: Option<SyntaxNode>,
+	index: usize, // its index in the parent's children
+	offset: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct SyntaxToken {
+	green: Rc<GreenToken>,
+	parent: SyntaxNode,
+	index: usize,
+	offset: usize,
+}
+
+#[derive(Clone, Debug)]
+pub enum SyntaxElement {
+	Node(SyntaxNode),
+	Token(SyntaxToken),
+}
+
+impl SyntaxKind {
+	/** Checks if the parser ignores this kind of token */
+	pub fn is_trivia(self) -> bool {
+		matches!(
+			self,
+			SyntaxKind::Whitespace
+				| SyntaxKind::LineComment
+				| SyntaxKind::DocComment
+				| SyntaxKind::BlockComment
+		)
+	}
+
+	/** Gets the kind of a token that `tokenize` would return */
+	pub fn of_token(text: &str) -> Self {
+		if KEYWORDS.contains(&text) {
+			SyntaxKind::Keyword
+		} else if OPERATORS.contains(&text) {
+			SyntaxKind::Operator
+		} else if text.starts_with('"') {
+			SyntaxKind::Str
+		} else if text.starts_with(|c: char| c.is_ascii_digit()) {
+			SyntaxKind::Number
+		} else {
+			SyntaxKind::Name
+		}
+	}
+}
+
+impl GreenElement {
+	pub fn kind(&self) -> SyntaxKind {
+		match self {
+			GreenElement::Node(node) => node.kind,
+			GreenElement::Token(token) => token.kind,
+		}
+	}
+
+	pub fn width(&self) -> usize {
+		match self {
+			GreenElement::Node(node) => node.width,
+			GreenElement::Token(token) => token.text.len(),
+		}
+	}
+}
+
+impl GreenNode {
+	pub fn new(kind: SyntaxKind, children: Vec<GreenElement>) -> Self {
+		let width = children.iter().map(GreenElement::width).sum();
+		GreenNode {
+			kind,
+			width,
+			children,
+		}
+	}
+
+	/** Creates a copy of the node with one of its children replaced */
+	pub fn replace_child(&self, index: usize, child: GreenElement) -> Self {
+		let mut children = self.children.clone();
+		children[index] = child;
+		GreenNode::new(self.kind, children)
+	}
+}
+
+impl fmt::Display for GreenNode {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for child in &self.children {
+			match child {
+				GreenElement::Node(node) => write!(f, "{}", node)?,
+				GreenElement::Token(token) => write!(f, "{}", token.text)?,
+			}
+		}
+		Ok(())
+	}
+}
+
+impl SyntaxNode {
+	/** Creates the root of a red tree */
+	pub fn new_root(green: GreenNode) -> Self {
+		SyntaxNode {
+			data: Rc::new(NodeData {
+				green: Rc::new(green),
+				parent: None,
+				index: 0,
+				offset: 0,
+			}),
+		}
+	}
+
+	pub fn kind(&self) -> SyntaxKind {
+		self.data.green.kind
+	}
+
+	pub fn green(&self) -> &GreenNode {
+		&self.data.green
+	}
+
+	pub fn parent(&self) -> Option<&SyntaxNode> {
+		self.data.parent.as_ref()
+	}
+
+	pub fn span(&self) -> Span {
+		Span::new(self.data.offset, self.data.offset + self.data.green.width)
+	}
+
+	/** Gets the exact code the node came from */
+	pub fn text(&self) -> String {
+		self.data.green.to_string()
+	}
+
+	pub fn children(&self) -> Vec<SyntaxElement> {
+		let mut offset = self.data.offset;
+		let mut children = Vec::with_capacity(self.data.green.children.len());
+		for (index, child) in self.data.green.children.iter().enumerate() {
+			children.push(match child {
+				GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode {
+					data: Rc::new(NodeData {
+						green: green.clone(),
+						parent: Some(self.clone()),
+						index,
+						offset,
+					}),
+				}),
+				GreenElement::Token(green) => {
+					SyntaxElement::Token(SyntaxToken {
+						green: green.clone(),
+						parent: self.clone(),
+						index,
+						offset,
+					})
+				}
+			});
+			offset += child.width();
+		}
+		children
+	}
+
+	/** Gets the nodes directly inside of this one */
+	pub fn child_nodes(&self) -> Vec<SyntaxNode> {
+		self.children()
+			.into_iter()
+			.filter_map(|child| match child {
+				SyntaxElement::Node(node) => Some(node),
+				SyntaxElement::Token(_) => None,
+			})
+			.collect()
+	}
+
+	/** Gets every token in the node, in order, including trivia */
+	pub fn tokens(&self) -> Vec<SyntaxToken> {
+		let mut tokens = Vec::new();
+		for child in self.children() {
+			match child {
+				SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+				SyntaxElement::Token(token) => tokens.push(token),
+			}
+		}
+		tokens
+	}
+
+	/** Gets the tokens the parser uses, like `tokenizer::tokenize` */
+	pub fn token_list(&self) -> TokenList {
+		self.tokens()
+			.into_iter()
+			.filter(|token| !token.kind().is_trivia())
+			.map(|token| String::from(token.text()))
+			.collect()
+	}
+
+	/** Finds the token which contains an offset */
+	pub fn token_at(&self, offset: usize) -> Option<SyntaxToken> {
+		self.tokens().into_iter().find(|token| {
+			let span = token.span();
+			span.start <= offset && offset < span.end
+		})
+	}
+
+	/**
+	 * Replaces this node, rebuilding its ancestors
+	 * Returns the root of the new tree
+	 */
+	pub fn replace_with(&self, green: GreenNode) -> SyntaxNode {
+		match self.parent() {
+			Some(parent) => {
+				let element = GreenElement::Node(Rc::new(green));
+				parent.replace_with(
+					parent.green().replace_child(self.data.index, element),
+				)
+			}
+			None => SyntaxNode::new_root(green),
+		}
+	}
+}
+
+impl SyntaxToken {
+	pub fn kind(&self) -> SyntaxKind {
+		self.green.kind
+	}
+
+	pub fn text(&self) -> &str {
+		&self.green.text
+	}
+
+	pub fn parent(&self) -> &SyntaxNode {
+		&self.parent
+	}
+
+	pub fn span(&self) -> Span {
+		Span::new(self.offset, self.offset + self.green.text.len())
+	}
+
+	/**
+	 * Replaces the text of this token, rebuilding its ancestors
+	 * Returns the root of the new tree
+	 */
+	pub fn with_text(&self, text: &str) -> SyntaxNode {
+		let token = GreenToken {
+			kind: self.kind(),
+			text: String::from(text),
+		};
+		let element = GreenElement::Token(Rc::new(token));
+		self.parent.replace_with(
+			self.parent.green().replace_child(self.index, element),
+		)
+	}
+}
+
+impl SyntaxElement {
+	pub fn kind(&self) -> SyntaxKind {
+		match self {
+			SyntaxElement::Node(node) => node.kind(),
+			SyntaxElement::Token(token) => token.kind(),
+		}
+	}
+
+	pub fn span(&self) -> Span {
+		match self {
+			SyntaxElement::Node(node) => node.span(),
+			SyntaxElement::Token(token) => token.span(),
+		}
+	}
+}
+
+/**
+ * Splits code into tokens without losing anything
+ * The tokens which aren't trivia are the same as `tokenizer::tokenize`'s
+ */
+pub fn lex(code: &str) -> Vec<GreenToken> {
+	let spanned = tokenize_spanned_with_block_comments(code);
+	let mut tokens = Vec::new();
+	let mut position = 0;
+
+	let mut token = 0;
+	while token < spanned.len() {
+		let span = spanned[token].span;
+		lex_gap(&code[position..span.start], &mut tokens);
+
+		let text = spanned[token].text.as_str();
+		let end = if text == "/*" {
+			// block comments swallow everything up to the end of the comment
+			while token < spanned.len() && spanned[token].text != "*/" {
+				token += 1;
+			}
+			spanned.get(token).map_or(code.len(), |t| t.span.end)
+		} else {
+			span.end
+		};
+
+		let kind = if text == "/*" || text == "*/" {
+			SyntaxKind::BlockComment
+		} else {
+			SyntaxKind::of_token(text)
+		};
+		tokens.push(GreenToken {
+			kind,
+			text: String::from(&code[span.start..end]),
+		});
+		position = end;
+		token += 1;
+	}
+	lex_gap(&code[position..], &mut tokens);
+
+	tokens
+}
+
+/** Splits the code between two tokens into whitespace and comments */
+fn lex_gap(mut gap: &str, tokens: &mut Vec<GreenToken>) {
+	while !gap.is_empty() {
+		let (kind, length) = if gap.starts_with("//") {
+			let kind = if gap.starts_with("///") && !gap.starts_with("////") {
+				SyntaxKind::DocComment
+			} else {
+				SyntaxKind::LineComment
+			};
+			(kind, gap.find('\n').unwrap_or(gap.len()))
+		} else {
+			let length =
+				gap.find(|c: char| !c.is_whitespace()).unwrap_or(gap.len());
+			if length == 0 {
+				(SyntaxKind::Error, gap.len())
+			} else {
+				(SyntaxKind::Whitespace, length)
+			}
+		};
+		tokens.push(GreenToken {
+			kind,
+			text: String::from(&gap[..length]),
+		});
+		gap = &gap[length..];
+	}
+}
+
+/** Creates a lossless syntax tree for some code */
+pub fn parse(code: &str) -> SyntaxNode {
+	let tokens: Vec<Rc<GreenToken>> =
+		lex(code).into_iter().map(Rc::new).collect();
+	SyntaxNode::new_root(GreenNode::new(SyntaxKind::Root, items(&tokens)))
+}
+
+/**
+ * Updates a tree after its code is edited, giving the same tree as `parse`
+ *
+ * Only the top-level items around the edit are lexed and parsed again,
+ * out to the whitespace on either side of them, and the other items are
+ * shared with the old tree. If the edit could change how the code after
+ * it is read, like opening a comment or removing a `}`, everything is
+ * parsed again instead.
+ */
+pub fn reparse(tree: &SyntaxNode, edit: &TextEdit) -> SyntaxNode {
+	let root = tree.green();
+	let children = tree.children();
+
+	// the items being replaced start and end with whitespace that the edit
+	// doesn't touch, so the tokens around them can't change
+	let is_whitespace =
+		|child: &SyntaxElement| child.kind() == SyntaxKind::Whitespace;
+	let first = children
+		.iter()
+		.rposition(|c| is_whitespace(c) && c.span().start < edit.span.start)
+		.unwrap_or(0);
+	let last = children
+		.iter()
+		.skip(first)
+		.position(|c| is_whitespace(c) && c.span().end > edit.span.end)
+		.map_or(children.len(), |i| first + i + 1);
+	let start = children.get(first).map_or(0, |c| c.span().start);
+
+	let old: String = root.children[first..last]
+		.iter()
+		.map(|child| match child {
+			GreenElement::Node(node) => node.to_string(),
+			GreenElement::Token(token) => token.text.clone(),
+		})
+		.collect();
+	let relative = Span::new(edit.span.start - start, edit.span.end - start);
+	let code = TextEdit::new(relative, &edit.text).apply(&old);
+
+	let tokens: Vec<Rc<GreenToken>> =
+		lex(&code).into_iter().map(Rc::new).collect();
+	let reaches_end = last == children.len();
+	let ends_cleanly = reaches_end
+		|| tokens.last().map(|t| t.kind) == Some(SyntaxKind::Whitespace);
+	let items = items(&tokens);
+	if !ends_cleanly || !items.iter().all(is_closed) {
+		return parse(&edit.apply(&tree.text()));
+	}
+
+	let mut children = root.children[..first].to_vec();
+	children.extend(items);
+	children.extend_from_slice(&root.children[last..]);
+	SyntaxNode::new_root(GreenNode::new(SyntaxKind::Root, children))
+}
+
+/** Builds the top-level items out of some tokens */
+fn items(tokens: &[Rc<GreenToken>]) -> Vec<GreenElement> {
+	let mut token = 0;
+	let mut children = Vec::new();
+	while token < tokens.len() {
+		let text = tokens[token].text.as_str();
+		if tokens[token].kind.is_trivia() {
+			children.push(GreenElement::Token(tokens[token].clone()));
+			token += 1;
+		} else if text == "fn" || text == "result" {
+			children.extend(definition(tokens, &mut token));
+		} else {
+			let error = GreenNode::new(
+				SyntaxKind::Error,
+				vec![GreenElement::Token(tokens[token].clone())],
+			);
+			children.push(GreenElement::Node(Rc::new(error)));
+			token += 1;
+		}
+	}
+	children
+}
+
+/**
+ * Checks that a definition's body is closed
+ * A definition which isn't would take in the items after it
+ */
+fn is_closed(element: &GreenElement) -> bool {
+	let node = match element {
+		GreenElement::Node(node) if node.kind != SyntaxKind::Error => node,
+		_ => return true,
+	};
+	match node.children.last() {
+		Some(GreenElement::Node(body)) if body.kind == SyntaxKind::Body => {
+			brackets(body) == 0
+		}
+		_ => false,
+	}
+}
+
+/**
+ * Counts the brackets in a body which are opened but not closed
+ * A definition inside of it which isn't closed leaves the body open too, so
+ * only the body's own tokens are counted
+ */
+fn brackets(body: &GreenNode) -> isize {
+	body.children
+		.iter()
+		.map(|child| match child {
+			GreenElement::Token(token) if token.text == "{" => 1,
+			GreenElement::Token(token) if token.text == "}" => -1,
+			_ => 0,
+		})
+		.sum()
+}
+
+/**
+ * Builds a function or result starting at its keyword
+ * Trivia after the signature is left outside of it
+ */
+fn definition(
+	tokens: &[Rc<GreenToken>],
+	token: &mut usize,
+) -> Option<GreenElement> {
+	let kind = match tokens[*token].text.as_str() {
+		"result" => SyntaxKind::Result,
+		_ => SyntaxKind::Function,
+	};
+	let mut children = vec![GreenElement::Token(tokens[*token].clone())];
+	*token += 1;
+
+	let start = *token;
+	while *token < tokens.len() && tokens[*token].text != "{" {
+		*token += 1;
+	}
+	let mut signature_end = *token;
+	while signature_end > start && tokens[signature_end - 1].kind.is_trivia() {
+		signature_end -= 1;
+	}
+	let mut signature_start = start;
+	while signature_start < signature_end
+		&& tokens[signature_start].kind.is_trivia()
+	{
+		children.push(GreenElement::Token(tokens[signature_start].clone()));
+		signature_start += 1;
+	}
+	let signature = tokens[signature_start..signature_end]
+		.iter()
+		.map(|t| GreenElement::Token(t.clone()))
+		.collect();
+	children.push(GreenElement::Node(Rc::new(GreenNode::new(
+		SyntaxKind::Signature,
+		signature,
+	))));
+	for trivia in &tokens[signature_end..*token] {
+		children.push(GreenElement::Token(trivia.clone()));
+	}
+
+	if *token < tokens.len() {
+		children.push(GreenElement::Node(Rc::new(body(
+			tokens,
+			token,
+			kind == SyntaxKind::Result,
+		))));
+	}
+
+	Some(GreenElement::Node(Rc::new(GreenNode::new(kind, children))))
+}
+
+/**
+ * Builds a body starting at its opening bracket
+ * The functions in a result's body get their own nodes
+ */
+fn body(
+	tokens: &[Rc<GreenToken>],
+	token: &mut usize,
+	result: bool,
+) -> GreenNode {
+	let mut children = Vec::new();
+	let mut brackets: usize = 0; // the number of brackets that need to be closed
+	while *token < tokens.len() {
+		let text = tokens[*token].text.as_str();
+		if result && brackets == 1 && text == "fn" {
+			children.extend(definition(tokens, token));
+			continue;
+		}
+
+		children.push(GreenElement::Token(tokens[*token].clone()));
+		*token += 1;
+		if text == "{" {
+			brackets += 1;
+		} else if text == "}" {
+			brackets -= 1;
+			if brackets == 0 {
+				break;
+			}
+		}
+	}
+	GreenNode::new(SyntaxKind::Body, children)
+}
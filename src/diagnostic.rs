@@ -0,0 +1,131 @@
+use crate::span::Span;
+
+use std::fmt::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+	Error,
+	Note,
+}
+
+// a message pointing at a piece of code
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+	pub severity: Severity,
+	pub message: String,
+	pub span: Option<Span>,
+	pub annotation: String,
+}
+
+// a problem with some code, with extra labels to explain it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+	pub primary: Label,
+	pub notes: Vec<Label>,
+}
+
+impl Severity {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Severity::Error => "error",
+			Severity::Note => "note",
+		}
+	}
+}
+
+impl Label {
+	pub fn new(
+		severity: Severity,
+		message: String,
+		span: Option<Span>,
+	) -> Self {
+		Label {
+			severity,
+			message,
+			span,
+			annotation: String::new(),
+		}
+	}
+
+	/** Renders the message, followed by a snippet of the code it points to */
+	pub fn render(&self, file: &str, code: &str) -> String {
+		let mut output =
+			format!("{}: {}\n", self.severity.name(), self.message);
+		if let Some(span) = self.span {
+			output.push_str(&render_snippet(
+				file,
+				code,
+				span,
+				&self.annotation,
+			));
+		}
+		output
+	}
+}
+
+impl Diagnostic {
+	pub fn error(message: String, span: Option<Span>) -> Self {
+		Diagnostic {
+			primary: Label::new(Severity::Error, message, span),
+			notes: Vec::new(),
+		}
+	}
+
+	/** Adds a note to the end of the diagnostic */
+	pub fn note(&mut self, message: String, span: Option<Span>) {
+		self.notes.push(Label::new(Severity::Note, message, span));
+	}
+
+	/** Renders the diagnostic and all of its notes */
+	pub fn render(&self, file: &str, code: &str) -> String {
+		let mut output = self.primary.render(file, code);
+		for note in &self.notes {
+			output.push_str(&note.render(file, code));
+		}
+		output
+	}
+}
+
+/**
+ * Renders the line of code a span starts on, with the span underlined
+ *
+ *  --> file.ro:2:5
+ *   |
+ * 2 |     ret 1 / 0
+ *   |     ^^^ annotation
+ */
+pub fn render_snippet(
+	file: &str,
+	code: &str,
+	span: Span,
+	annotation: &str,
+) -> String {
+	let (line, column) = span.line_col(code);
+	let start = span.start.min(code.len());
+	let line_start = code[..start].rfind('\n').map_or(0, |i| i + 1);
+	let line_end = code[start..].find('\n').map_or(code.len(), |i| start + i);
+	let text = code[line_start..line_end].trim_end_matches('\r');
+
+	// keep tabs so that the underline lines up with the code
+	let indent: String = code[line_start..start]
+		.chars()
+		.map(|c| if c == '\t' { '\t' } else { ' ' })
+		.collect();
+	let length = code[start..span.end.clamp(start, line_end)]
+		.chars()
+		.count()
+		.max(1);
+
+	let number = line.to_string();
+	let gutter = " ".repeat(number.len());
+	let mut output = String::new();
+	let _ = writeln!(output, "{}--> {}:{}:{}", gutter, file, line, column);
+	let _ = writeln!(output, "{} |", gutter);
+	let _ = writeln!(output, "{} | {}", number, text);
+	let _ = write!(output, "{} | {}{}", gutter, indent, "^".repeat(length));
+	if !annotation.is_empty() {
+		let _ = write!(output, " {}", annotation);
+	}
+	output.push('\n');
+	output
+}
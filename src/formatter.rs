@@ -0,0 +1,177 @@
+use crate::cst;
+use crate::cst::SyntaxKind;
+
+// what each level of indentation is made of
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Indent {
+	Tabs,
+	Spaces(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormatOptions {
+	pub indent: Indent,
+	pub space_before_colon: bool, // `one : float` instead of `one: float`
+	pub space_around_arrow: bool, // `fn => add` instead of `fn=>add`
+}
+
+impl Default for FormatOptions {
+	fn default() -> Self {
+		FormatOptions {
+			indent: Indent::Tabs,
+			space_before_colon: false,
+			space_around_arrow: true,
+		}
+	}
+}
+
+impl Indent {
+	fn text(self) -> String {
+		match self {
+			Indent::Tabs => String::from("\t"),
+			Indent::Spaces(n) => " ".repeat(n),
+		}
+	}
+}
+
+// the tokens on one line of the formatted code
+#[derive(Default)]
+struct Line<'a> {
+	blank_before: bool, // whether there was an empty line before it
+	tokens: Vec<(SyntaxKind, &'a str)>,
+}
+
+/**
+ * Formats Ro code
+ * Line breaks are kept, except that bodies always start on a new line,
+ * and comments are kept where they are
+ */
+pub fn format(code: &str, options: &FormatOptions) -> String {
+	let tree = cst::parse(code);
+	let tokens = tree.tokens();
+
+	// split the code into lines, ignoring the whitespace
+	let mut lines: Vec<Line> = Vec::new();
+	let mut line = Line::default();
+	let mut newlines = 0;
+	for token in &tokens {
+		let kind = token.kind();
+		let text = token.text();
+		if kind == SyntaxKind::Whitespace {
+			newlines += text.matches('\n').count();
+			continue;
+		}
+
+		let last = line.tokens.last().map(|(_, t)| *t);
+		let opens_body = last == Some("{") && text != "}";
+		let closes_body = text == "}" && last.is_some_and(|t| t != "{");
+		if !line.tokens.is_empty()
+			&& (newlines > 0 || opens_body || closes_body)
+		{
+			lines.push(std::mem::take(&mut line));
+		}
+		if line.tokens.is_empty() {
+			line.blank_before = newlines > 1 && !lines.is_empty();
+		}
+		line.tokens.push((kind, text.trim_end()));
+		newlines = 0;
+	}
+	if !line.tokens.is_empty() {
+		lines.push(line);
+	}
+
+	let indent = options.indent.text();
+	let mut formatted = String::new();
+	// the level of the line which each unclosed bracket is on
+	// lines inside a bracket are indented once more than that line, so a
+	// closure passed to a call is only indented once
+	let mut brackets: Vec<usize> = Vec::new();
+	for (i, line) in lines.iter().enumerate() {
+		let first = line.tokens[0].1;
+		let closes = first == "}" || first == ")";
+		let after_open = i > 0 && lines[i - 1].tokens.last().unwrap().1 == "{";
+		if line.blank_before && !closes && !after_open {
+			formatted.push('\n');
+		}
+
+		let level = match brackets.last() {
+			Some(level) if closes => *level,
+			Some(level) => level + 1,
+			None => 0,
+		};
+		formatted.push_str(&indent.repeat(level));
+		formatted.push_str(&join(&line.tokens, options));
+		formatted.push('\n');
+
+		for (kind, token) in &line.tokens {
+			if kind.is_trivia() {
+				continue;
+			}
+			match *token {
+				"{" | "(" => brackets.push(level),
+				"}" | ")" => {
+					brackets.pop();
+				}
+				_ => (),
+			}
+		}
+	}
+
+	formatted
+}
+
+/** Checks if some code is already formatted */
+pub fn is_formatted(code: &str, options: &FormatOptions) -> bool {
+	format(code, options) == code
+}
+
+/** Puts the tokens on a line together, with the right spacing */
+fn join(tokens: &[(SyntaxKind, &str)], options: &FormatOptions) -> String {
+	let mut line = String::new();
+	for (i, (kind, token)) in tokens.iter().enumerate() {
+		if i > 0 && space_between(&tokens[..i], *kind, token, options) {
+			line.push(' ');
+		}
+		line.push_str(token);
+	}
+	line
+}
+
+/** Checks if there should be a space before the next token */
+fn space_between(
+	before: &[(SyntaxKind, &str)],
+	kind: SyntaxKind,
+	next: &str,
+	options: &FormatOptions,
+) -> bool {
+	let (previous_kind, previous) = before[before.len() - 1];
+	if kind.is_trivia() || previous_kind.is_trivia() {
+		return true;
+	}
+
+	match (previous, next) {
+		("(", _) | (".", _) | ("{", "}") => false,
+		(_, ")") | (_, ",") | (_, ".") => false,
+		(_, ":") => options.space_before_colon,
+		("=>", _) | (_, "=>") => options.space_around_arrow,
+		(_, "(") => {
+			// calls and signatures are stuck to their name
+			!(previous_kind == SyntaxKind::Name
+				|| previous == "fn"
+				|| previous == ")")
+		}
+		("-", _) => !is_prefix(&before[..before.len() - 1]),
+		_ => true,
+	}
+}
+
+/** Checks if an operator after these tokens would be a prefix, like `-1` */
+fn is_prefix(before: &[(SyntaxKind, &str)]) -> bool {
+	match before.last() {
+		None => true,
+		Some((kind, token)) => {
+			*kind == SyntaxKind::Keyword
+				|| (*kind == SyntaxKind::Operator && *token != ")")
+		}
+	}
+}
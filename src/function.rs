@@ -1,6 +1,9 @@
+use crate::parser::Parser;
+use crate::symbol::Symbol;
 use crate::tokenizer::TokenList;
 
 use std::collections::HashMap;
+use std::fmt;
 
 pub type CallList = Vec<CallType>;
 
@@ -11,20 +14,28 @@ pub struct FuncParser {
 }
 
 #[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FuncSig {
-	pub name: Option<String>,
-	pub parameters: Option<HashMap<String, String>>,
-	pub return_type: Option<String>,
-	pub result: Option<String>,
+	pub name: Option<Symbol>,
+	#[cfg_attr(
+		feature = "serde",
+		serde(serialize_with = "crate::json::sorted_option")
+	)]
+	pub parameters: Option<HashMap<Symbol, Symbol>>,
+	pub return_type: Option<Symbol>,
+	pub result: Option<Symbol>,
 }
 
 #[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Function {
-	signature: FuncSig,
-	calls: CallList,
+	pub signature: FuncSig,
+	pub calls: CallList,
+	pub parameter_order: Vec<Symbol>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Operation {
 	Add,
 	Sub,
@@ -34,7 +45,7 @@ pub enum Operation {
 }
 
 impl Operation {
-	fn from_str(s: String) -> std::io::Result<Self> {
+	pub(crate) fn from_str(s: String) -> std::io::Result<Self> {
 		if s == "+" {
 			Ok(Operation::Add)
 		} else if s == "-" {
@@ -52,179 +63,407 @@ impl Operation {
 			))
 		}
 	}
+
+	/** Gets how tightly the operation binds, from 1 (loosest) to 2 */
+	fn precedence(&self) -> usize {
+		match self {
+			Operation::Add | Operation::Sub => 1,
+			Operation::Mult | Operation::Div | Operation::Mod => 2,
+		}
+	}
+}
+
+impl fmt::Display for Operation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let symbol = match self {
+			Operation::Add => "+",
+			Operation::Sub => "-",
+			Operation::Mult => "*",
+			Operation::Div => "/",
+			Operation::Mod => "%",
+		};
+		write!(f, "{}", symbol)
+	}
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CallType {
-	Return(String),
-	Init(String),
-	Set(String, TokenList),
-	Call(String, Vec<String>),
-	Move(String, String),
-	Operate(String, String, Operation, String),
+	Return(Symbol),
+	Init(Symbol),
+	Set(Symbol, TokenList),
+	Call(Symbol, Vec<Symbol>),
+	SetCall(Symbol, Symbol, Vec<Symbol>),
+	Move(Symbol, Symbol),
+	Operate(Symbol, Symbol, Operation, Symbol),
+	Closure(Symbol, Function), // a local function, stored in a variable
 }
 
 impl CallType {
-	/** Creates a list of calls from a token list */
+	/**
+	 * Creates a list of calls from a token list
+	 * Expressions are kept as Set calls, with the tokens they were written as
+	 */
 	pub fn vec_from_tokens(tokens: TokenList) -> Vec<CallType> {
-		let mut token: usize = 0;
-		let mut calls: Vec<CallType> = Vec::new();
-		while token < tokens.len() {
-			if tokens[token] == "ret" {
-				token += 1;
-				calls.push(CallType::Return(tokens[token].clone()));
-			} else if tokens[token] == "var" {
-				token += 1;
-				let var_name = tokens[token].clone();
-				calls.push(CallType::Init(var_name.clone()));
-				if tokens.len() > token + 1 && tokens[token + 1] == "=" {
-					token += 2;
-					let mut set: TokenList = Vec::with_capacity(1);
-					set.push(tokens[token].clone());
-					/*if tokens.len() > token + 1
-						&& crate::tokenizer::OPERATORS
-							.contains(&tokens[token + 1].as_str())
-					{
-						token += 1;
-						set.push(tokens[token].clone());
-					}*/
-					while tokens.len() > token + 1
-						&& crate::tokenizer::OPERATORS
-							.contains(&tokens[token + 1].as_str())
-					{
-						token += 1;
-						if tokens.len() > token
-							&& crate::tokenizer::OPERATORS
-								.contains(&tokens[token].as_str())
-						{
-							//token += 1;
-							set.push(tokens[token].clone());
-							token += 1;
-							set.push(tokens[token].clone());
-						}
+		Parser::new(&tokens).without_lowering().statements()
+	}
 
-						/*if tokens.len() > token + 1 {
-							token += 1;
-							set.push(tokens[token].clone());
-						}*/
-					}
-					calls.push(CallType::Set(var_name, set));
-				}
-			} else if tokens.len() > token + 1 && tokens[token + 1] == "=" {
-				let var_name = tokens[token].clone();
-				token += 2;
-				let mut set: TokenList = Vec::with_capacity(1);
-				set.push(tokens[token].clone());
-				/*if tokens.len() > token + 1
-					&& crate::tokenizer::OPERATORS
-						.contains(&tokens[token + 1].as_str())
-				{
-					token += 1;
-					set.push(tokens[token].clone());
-				}*/
-				while tokens.len() > token + 1
-					&& crate::tokenizer::OPERATORS
-						.contains(&tokens[token + 1].as_str())
-				{
-					token += 1;
-					if tokens.len() > token
-						&& crate::tokenizer::OPERATORS
-							.contains(&tokens[token].as_str())
-					{
-						//token += 1;
-						set.push(tokens[token].clone());
-						token += 1;
-						set.push(tokens[token].clone());
-					}
+	/**
+	 * Converts Set calls to Operate, Move and SetCall calls
+	 * Nested expressions are broken up using temporary variables
+	 * Every other call is kept as it is
+	 */
+	pub fn sets_to_ops(calls: CallList) -> CallList {
+		let mut new_calls: CallList = vec![];
 
-					/*if tokens.len() > token + 1 {
-						token += 1;
-						set.push(tokens[token].clone());
-					}*/
-				}
-				calls.push(CallType::Set(var_name, set));
-			} else if tokens.len() > 1 && tokens[token + 1] == "(" {
-				let func_name = tokens[token].clone();
-				token += 2;
-				let mut parameters: Vec<String> = vec![];
-				while tokens.len() > token && tokens[token] != ")" {
-					parameters.push(tokens[token].clone());
-					token += 1;
-					if tokens[token] == "," {
-						token += 1;
-					}
-				}
+		for call in calls {
+			if let CallType::Set(var, tokens) = call {
+				let expression = Parser::new(&tokens).expression();
+				let mut temps = 0;
+				expression.lower(var, &var, &mut temps, &mut new_calls);
+			} else if let CallType::Closure(var, mut function) = call {
+				function.calls = CallType::sets_to_ops(function.calls);
+				new_calls.push(CallType::Closure(var, function));
+			} else {
+				new_calls.push(call);
+			}
+		}
+
+		new_calls
+	}
+}
+
+/**
+ * Checks if a variable was made by `vec_from_tokens` or `sets_to_ops`
+ * These have names which can't be written in Ro
+ */
+pub fn is_temporary(name: &str) -> bool {
+	name == "return" || name.contains('.') || name.contains('(')
+}
 
-				calls.push(CallType::Call(func_name, parameters));
+/** Checks if a list of tokens is exactly one expression, like `one + two` */
+pub fn is_expression(tokens: &[String]) -> bool {
+	let mut parser = Parser::new(tokens);
+	!tokens.is_empty()
+		&& !["var", "ret", "return"].contains(&tokens[0].as_str())
+		&& {
+			parser.expression();
+			parser.is_done()
+		}
+}
+
+// an expression which hasn't been broken into calls yet
+pub(crate) enum Expression {
+	Value(Symbol),
+	Call(Symbol, Vec<Expression>),
+	Operate(Box<Expression>, Operation, Box<Expression>),
+	Bracket(Box<Expression>), // only made when printing
+	Closure(Function),
+}
+
+impl Expression {
+	/**
+	 * Breaks the expression into calls which store it in `var`
+	 * Temporary variables are named after `base`
+	 */
+	pub(crate) fn lower(
+		self,
+		var: Symbol,
+		base: &str,
+		temps: &mut usize,
+		calls: &mut CallList,
+	) {
+		match self {
+			Expression::Value(value) => calls.push(CallType::Move(var, value)),
+			Expression::Call(func_name, arguments) => {
+				let parameters = arguments
+					.into_iter()
+					.map(|argument| argument.operand(base, temps, calls))
+					.collect();
+				calls.push(CallType::SetCall(var, func_name, parameters));
+			}
+			Expression::Operate(left, op, right) => {
+				let left = left.operand(base, temps, calls);
+				let right = right.operand(base, temps, calls);
+				calls.push(CallType::Operate(var, left, op, right));
+			}
+			Expression::Bracket(expression) => {
+				expression.lower(var, base, temps, calls)
+			}
+			Expression::Closure(function) => {
+				calls.push(CallType::Closure(var, function))
 			}
+		}
+	}
 
-			token += 1;
+	/** Gets a single value which can be used as an operand */
+	fn operand(
+		self,
+		base: &str,
+		temps: &mut usize,
+		calls: &mut CallList,
+	) -> Symbol {
+		if let Expression::Value(value) = self {
+			value
+		} else if let Expression::Bracket(expression) = self {
+			expression.operand(base, temps, calls)
+		} else {
+			let temp = Symbol::from(format!("{}.{}", base, temps));
+			*temps += 1;
+			self.lower(temp, base, temps, calls);
+			temp
 		}
+	}
+}
 
-		calls
+impl Expression {
+	/** Gets how tightly the expression binds, so it's bracketed correctly */
+	fn precedence(&self) -> usize {
+		match self {
+			Expression::Operate(_, op, _) => op.precedence(),
+			_ => usize::MAX,
+		}
 	}
+}
 
+impl Expression {
 	/**
-	 * Converts Set calls to Operate and Move calls
+	 * Writes the expression as Ro code
+	 * Closures are indented by some number of tabs, to line up with the
+	 * statement that they're in
 	 */
-	pub fn sets_to_ops(calls: CallList) -> CallList {
-		let mut new_calls: CallList = vec![];
+	fn write(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+		match self {
+			Expression::Value(value) => write!(f, "{}", value),
+			Expression::Call(func_name, arguments) => {
+				write!(f, "{}(", func_name)?;
+				write_expressions(f, arguments, indent)?;
+				write!(f, ")")
+			}
+			Expression::Operate(left, op, right) => {
+				// operations are grouped from the left
+				let bracketed = left.precedence() < op.precedence();
+				left.write_bracketed(f, indent, bracketed)?;
+				write!(f, " {} ", op)?;
+				let bracketed = right.precedence() <= op.precedence();
+				right.write_bracketed(f, indent, bracketed)
+			}
+			Expression::Bracket(expression) => {
+				expression.write_bracketed(f, indent, true)
+			}
+			Expression::Closure(function) => {
+				function.write_inline(f, indent, colon)
+			}
+		}
+	}
 
-		for call in calls.clone() {
-			if let CallType::Set(var, tokens) = call {
-				if tokens.len() == 1 {
-					new_calls.push(CallType::Move(var, tokens[0].clone()));
-				} else if tokens.len() == 3 {
-					new_calls.push(CallType::Operate(
-						var,
-						tokens[0].clone(),
-						Operation::from_str(tokens[1].clone()).unwrap(),
-						tokens[2].clone(),
-					));
+	fn write_bracketed(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+		indent: usize,
+		bracketed: bool,
+	) -> fmt::Result {
+		if bracketed {
+			write!(f, "(")?;
+			self.write(f, indent)?;
+			write!(f, ")")
+		} else {
+			self.write(f, indent)
+		}
+	}
+}
+
+impl fmt::Display for Expression {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.write(f, 0)
+	}
+}
+
+// local functions are always written with `:` between their parameters and
+// types, like free functions
+fn colon(_: &Symbol) -> &'static str {
+	":"
+}
+
+/** Writes a list of expressions separated by commas */
+fn write_expressions(
+	f: &mut fmt::Formatter<'_>,
+	expressions: &[Expression],
+	indent: usize,
+) -> fmt::Result {
+	for (i, expression) in expressions.iter().enumerate() {
+		if i > 0 {
+			write!(f, ", ")?;
+		}
+		expression.write(f, indent)?;
+	}
+	Ok(())
+}
+
+/** Writes a list of items separated by commas */
+fn write_list<T: fmt::Display>(
+	f: &mut fmt::Formatter<'_>,
+	items: &[T],
+) -> fmt::Result {
+	for (i, item) in items.iter().enumerate() {
+		if i > 0 {
+			write!(f, ", ")?;
+		}
+		write!(f, "{}", item)?;
+	}
+	Ok(())
+}
+
+// a line of Ro code, rebuilt from a list of calls
+enum Statement {
+	Var(Symbol),
+	Assign(Symbol, Expression),
+	Call(Symbol, Vec<Expression>),
+	Return(Expression),
+	Set(Symbol, TokenList),
+	Define(Function),
+}
+
+/**
+ * Rebuilds the statements which a list of calls was parsed from
+ * Temporary variables are put back into the expressions they came from
+ */
+fn statements(calls: &[CallType]) -> Vec<Statement> {
+	let mut temps: HashMap<Symbol, Expression> = HashMap::new();
+	let mut statements = Vec::new();
+
+	// gets the expression stored in a temporary, or the value itself
+	let take = |temps: &mut HashMap<Symbol, Expression>, value: &Symbol| {
+		temps.remove(value).unwrap_or(Expression::Value(*value))
+	};
+
+	for call in calls {
+		let (var, expression) = match call {
+			CallType::Init(var) => {
+				// temporaries are declared by being given their value
+				if !is_temporary(var) {
+					statements.push(Statement::Var(*var));
+				}
+				continue;
+			}
+			CallType::Set(var, tokens) => {
+				statements.push(Statement::Set(*var, tokens.clone()));
+				continue;
+			}
+			CallType::Call(func_name, parameters) => {
+				let arguments =
+					parameters.iter().map(|p| take(&mut temps, p)).collect();
+				statements.push(Statement::Call(*func_name, arguments));
+				continue;
+			}
+			CallType::Return(value) => {
+				statements.push(Statement::Return(take(&mut temps, value)));
+				continue;
+			}
+			CallType::Closure(var, function) => {
+				// a local function is written where it's defined
+				if function.signature.name == Some(*var) {
+					statements.push(Statement::Define(function.clone()));
+					continue;
 				}
+				(var, Expression::Closure(function.clone()))
 			}
+			CallType::Move(var, value) => (var, take(&mut temps, value)),
+			CallType::Operate(var, left, op, right) => (
+				var,
+				Expression::Operate(
+					Box::new(take(&mut temps, left)),
+					op.clone(),
+					Box::new(take(&mut temps, right)),
+				),
+			),
+			CallType::SetCall(var, func_name, parameters) => (
+				var,
+				Expression::Call(
+					*func_name,
+					parameters.iter().map(|p| take(&mut temps, p)).collect(),
+				),
+			),
+		};
+
+		if is_temporary(var) {
+			// a single value is only stored in a temporary if it was bracketed
+			let expression = match expression {
+				Expression::Value(_) => {
+					Expression::Bracket(Box::new(expression))
+				}
+				expression => expression,
+			};
+			temps.insert(*var, expression);
+		} else {
+			statements.push(Statement::Assign(*var, expression));
 		}
+	}
 
-		new_calls
+	statements
+}
+
+/**
+ * Lists the names of some parameters in the given order
+ * Any which aren't in the order come after, in alphabetical order
+ */
+pub(crate) fn ordered_parameters(
+	parameters: &HashMap<Symbol, Symbol>,
+	order: &[Symbol],
+) -> Vec<Symbol> {
+	let mut names: Vec<Symbol> = order
+		.iter()
+		.filter(|p| parameters.contains_key(p))
+		.copied()
+		.collect();
+	let mut rest: Vec<Symbol> = parameters
+		.keys()
+		.filter(|p| !order.contains(p))
+		.copied()
+		.collect();
+	rest.sort();
+	names.extend(rest);
+	names
+}
+
+/** Writes the parameters of a signature, in the given order */
+pub(crate) fn write_parameters(
+	f: &mut fmt::Formatter<'_>,
+	parameters: &HashMap<Symbol, Symbol>,
+	order: &[Symbol],
+	separator: impl Fn(&Symbol) -> &'static str,
+) -> fmt::Result {
+	let names = ordered_parameters(parameters, order);
+	write!(f, "(")?;
+	for (i, name) in names.into_iter().enumerate() {
+		if i > 0 {
+			write!(f, ", ")?;
+		}
+		let value = &parameters[&name];
+		match separator(value) {
+			":" => write!(f, "{}: {}", name, value)?,
+			separator => write!(f, "{} {} {}", name, separator, value)?,
+		}
 	}
+	write!(f, ")")
 }
 
 impl FuncParser {
-	/** Creates a function parser from a tokenlist */
+	/** Creates a function parser for each function in a tokenlist */
 	pub fn vec_from_tokens(tokens: TokenList) -> Vec<FuncParser> {
 		let mut funcs = Vec::new();
-
-		let mut token = 0;
-		while token < tokens.len() {
-			if tokens[token] == "fn" {
-				let mut signature = TokenList::new();
-				token += 1;
-				while tokens[token] != "{" {
-					signature.push(tokens[token].clone());
-					token += 1;
-				}
-
-				let mut code = TokenList::new();
-				let mut brackets: usize = 1; // the number of brackets that need to be closed
-				loop {
-					token += 1;
-					if tokens[token] == "{" {
-						brackets += 1;
-					}
-					if tokens[token] == "}" {
-						brackets -= 1;
-					}
-					if brackets == 0 {
-						break;
-					}
-					code.push(tokens[token].clone());
-				}
-
-				funcs.push(FuncParser { signature, code })
+		let mut parser = Parser::new(&tokens);
+		while let Some(token) = parser.peek() {
+			if token == "fn" {
+				let (signature, code) = parser.definition();
+				funcs.push(FuncParser {
+					signature: signature.to_vec(),
+					code: code.to_vec(),
+				});
+			} else {
+				parser.skip();
 			}
-			token += 1;
 		}
-
 		funcs
 	}
 
@@ -234,11 +473,11 @@ impl FuncParser {
 	}
 
 	pub fn parse_signature(&self) -> FuncSig {
-		FuncSig::from_tokens(self.signature.clone())
+		FuncSig::from_slice(&self.signature)
 	}
 
 	pub fn parse_calls(&self) -> CallList {
-		CallType::sets_to_ops(CallType::vec_from_tokens(self.code.clone()))
+		Parser::new(&self.code).statements()
 	}
 
 	pub fn parse(self) -> Function {
@@ -251,84 +490,223 @@ impl FuncSig {
 	 * Parses a set of tokens into a signature for a function
 	 */
 	pub fn from_tokens(tokens: TokenList) -> Self {
-		let mut token: usize = 0;
-		let mut signature = FuncSig::default();
-
-		if token < tokens.len()
-			&& tokens[token] != "("
-			&& tokens[token] != ":"
-			&& tokens[token] != "=>"
-		{
-			signature.name = Some(tokens[token].clone());
-			token += 1;
-		} else {
-			signature.name = None;
-		}
+		Self::from_slice(&tokens)
+	}
 
-		if token < tokens.len() && tokens[token] == "(" {
-			token += 1;
-			signature.parameters = Some(HashMap::new());
-			while token < tokens.len() - 3 && tokens[token] != ")" {
-				if tokens[token] == "," {
-					token += 1;
-					continue;
-				}
-				let parameter_name = tokens[token].clone();
-				let parameter_type = tokens[token + 2].clone();
-				signature
-					.parameters
-					.as_mut()
-					.unwrap()
-					.insert(parameter_name, parameter_type);
-				token += 3;
+	/** Parses a signature without taking ownership of its tokens */
+	pub fn from_slice(tokens: &[String]) -> Self {
+		Parser::new(tokens).signature().0
+	}
+
+	pub fn from_func_parser(parser: FuncParser) -> Self {
+		parser.parse_signature()
+	}
+}
+
+impl fmt::Display for CallType {
+	/**
+	 * Writes the call as a line of Ro
+	 * Temporary variables are written with their names, like `x.0`, which
+	 * can't be read back in, so a function should be printed as a whole to
+	 * put them back into their expressions
+	 */
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			CallType::Return(value) => write!(f, "return {}", value),
+			CallType::Init(var) => write!(f, "var {}", var),
+			CallType::Set(var, tokens) => {
+				write!(f, "{} = {}", var, tokens.join(" "))
 			}
-			while tokens[token] != ")" {
-				token += 1;
+			CallType::Call(func_name, parameters) => {
+				write!(f, "{}(", func_name)?;
+				write_list(f, parameters)?;
+				write!(f, ")")
+			}
+			CallType::SetCall(var, func_name, parameters) => {
+				write!(f, "{} = {}(", var, func_name)?;
+				write_list(f, parameters)?;
+				write!(f, ")")
+			}
+			CallType::Move(var, value) => write!(f, "{} = {}", var, value),
+			CallType::Closure(var, function) => {
+				write!(f, "{} = ", var)?;
+				function.write_inline(f, 0, colon)
+			}
+			CallType::Operate(var, left, op, right) => {
+				write!(f, "{} = {} {} {}", var, left, op, right)
 			}
-			token += 1;
-		} else {
-			signature.parameters = None;
 		}
+	}
+}
 
-		if !tokens.is_empty()
-			&& token < tokens.len() - 1
-			&& tokens[token] == ":"
-		{
-			token += 1;
-			signature.return_type = Some(tokens[token].clone());
-			token += 1;
-		} else {
-			signature.return_type = None;
+impl FuncSig {
+	/**
+	 * Writes the signature, without the `fn`
+	 * `separator` gives the symbol between a parameter and its value
+	 */
+	pub(crate) fn write(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+		order: &[Symbol],
+		separator: impl Fn(&Symbol) -> &'static str,
+	) -> fmt::Result {
+		if let Some(name) = &self.name {
+			write!(f, "{}", name)?;
+		}
+		if let Some(parameters) = &self.parameters {
+			write_parameters(f, parameters, order, separator)?;
+		}
+		if let Some(return_type) = &self.return_type {
+			write!(f, ": {}", return_type)?;
+		}
+		if let Some(result) = &self.result {
+			if self.name.is_some() || self.parameters.is_some() {
+				write!(f, " ")?;
+			}
+			write!(f, "=> {}", result)?;
 		}
+		Ok(())
+	}
+}
 
-		if !tokens.is_empty()
-			&& token < tokens.len() - 1
-			&& tokens[token] == "=>"
-		{
-			token += 1;
-			signature.result = Some(tokens[token].clone());
-		} else {
-			signature.result = None;
+impl fmt::Display for FuncSig {
+	/** Writes the signature, without the `fn`, like `add(one: int): int` */
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.write(f, &[], |_| ":")
+	}
+}
+
+impl Function {
+	/**
+	 * Writes the function, indented by some number of tabs
+	 * `separator` gives the symbol between a parameter and its value
+	 */
+	pub(crate) fn write(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+		indent: usize,
+		separator: impl Fn(&Symbol) -> &'static str,
+	) -> fmt::Result {
+		write!(f, "{}", "\t".repeat(indent))?;
+		self.write_inline(f, indent, separator)
+	}
+
+	/**
+	 * Writes the function from its `fn`, without indenting the first line
+	 * The lines after it are indented by some number of tabs
+	 */
+	pub(crate) fn write_inline(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+		indent: usize,
+		separator: impl Fn(&Symbol) -> &'static str,
+	) -> fmt::Result {
+		let tabs = "\t".repeat(indent);
+		write!(f, "fn")?;
+		if self.signature.name.is_some() || self.signature.result.is_some() {
+			write!(f, " ")?;
+		}
+		self.signature.write(f, &self.parameter_order, separator)?;
+		writeln!(f, " {{")?;
+
+		let mut statements = statements(&self.calls).into_iter().peekable();
+		while let Some(statement) = statements.next() {
+			write!(f, "{}\t", tabs)?;
+			match statement {
+				Statement::Var(var) => {
+					write!(f, "var {}", var)?;
+					// the value can go on the same line
+					if let Some(Statement::Assign(next, _)) = statements.peek()
+					{
+						if *next == var {
+							if let Some(Statement::Assign(_, expression)) =
+								statements.next()
+							{
+								write!(f, " = ")?;
+								expression.write(f, indent + 1)?;
+							}
+						}
+					}
+				}
+				Statement::Assign(var, expression) => {
+					write!(f, "{} = ", var)?;
+					expression.write(f, indent + 1)?;
+				}
+				Statement::Call(func_name, arguments) => {
+					write!(f, "{}(", func_name)?;
+					write_expressions(f, &arguments, indent + 1)?;
+					write!(f, ")")?;
+				}
+				Statement::Return(expression) => {
+					write!(f, "return ")?;
+					expression.write(f, indent + 1)?;
+				}
+				Statement::Define(function) => {
+					function.write_inline(f, indent + 1, colon)?
+				}
+				Statement::Set(var, tokens) => {
+					write!(f, "{} = {}", var, tokens.join(" "))?
+				}
+			}
+			writeln!(f)?;
 		}
 
-		signature
+		write!(f, "{}}}", tabs)
 	}
+}
 
-	pub fn from_func_parser(parser: FuncParser) -> Self {
-		parser.parse_signature()
+impl fmt::Display for Function {
+	/** Writes the function as Ro code which parses back into it */
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.write(f, 0, |_| ":")
 	}
 }
 
 impl Function {
-	/** creates a new function */
+	/**
+	 * creates a new function
+	 * The signature's HashMap doesn't remember the order the parameters are
+	 * written in, so they're sorted by name. Use `with_parameter_order` to
+	 * give the order.
+	 */
 	pub fn new(signature: FuncSig, calls: CallList) -> Self {
-		Function { signature, calls }
+		let mut parameter_order: Vec<Symbol> = match &signature.parameters {
+			Some(parameters) => parameters.keys().cloned().collect(),
+			None => Vec::new(),
+		};
+		parameter_order.sort();
+		Function::with_parameter_order(signature, parameter_order, calls)
+	}
+
+	/** creates a new function whose parameters are in the order given */
+	pub fn with_parameter_order(
+		signature: FuncSig,
+		parameter_order: Vec<Symbol>,
+		calls: CallList,
+	) -> Self {
+		Function {
+			signature,
+			calls,
+			parameter_order,
+		}
 	}
 
 	/** converts a FuncParser to a Function */
 	pub fn from_parser(parser: FuncParser) -> Self {
-		let signature = parser.parse_signature();
-		let calls = parser.parse_calls();
-		Function { signature, calls }
+		let (signature, parameter_order) =
+			Parser::new(&parser.signature).signature();
+		Function {
+			signature,
+			calls: parser.parse_calls(),
+			parameter_order,
+		}
 	}
 }
+
+/**
+ * Gets the names of the parameters in a signature, in the order they're listed
+ * The HashMaps in the signatures don't remember this
+ */
+pub fn parameter_order(tokens: &[String]) -> Vec<Symbol> {
+	Parser::new(tokens).signature().1
+}
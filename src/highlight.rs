@@ -0,0 +1,167 @@
+use crate::analysis::Analysis;
+use crate::analysis::SymbolKind;
+use crate::cst;
+use crate::cst::SyntaxKind;
+use crate::cst::SyntaxNode;
+use crate::span::Span;
+
+/*
+ * Classifies the code for syntax highlighting
+ *
+ * Every token from the lossless syntax tree is classified by its kind,
+ * except for names, which are classified by what they refer to. A name
+ * after a `:` is a type. Whitespace, and names which don't refer to
+ * anything, aren't classified.
+ */
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Highlight {
+	Keyword,
+	Result,
+	Function,
+	Parameter,
+	Variable,
+	Type,
+	Operator,
+	Number,
+	String,
+	Comment,
+	DocComment,
+}
+
+impl Highlight {
+	pub const ALL: [Highlight; 11] = [
+		Highlight::Keyword,
+		Highlight::Result,
+		Highlight::Function,
+		Highlight::Parameter,
+		Highlight::Variable,
+		Highlight::Type,
+		Highlight::Operator,
+		Highlight::Number,
+		Highlight::String,
+		Highlight::Comment,
+		Highlight::DocComment,
+	];
+
+	/** Gets the name of the highlight, which is also its HTML class */
+	pub fn name(self) -> &'static str {
+		match self {
+			Highlight::Keyword => "keyword",
+			Highlight::Result => "result",
+			Highlight::Function => "function",
+			Highlight::Parameter => "parameter",
+			Highlight::Variable => "variable",
+			Highlight::Type => "type",
+			Highlight::Operator => "operator",
+			Highlight::Number => "number",
+			Highlight::String => "string",
+			Highlight::Comment => "comment",
+			Highlight::DocComment => "doc-comment",
+		}
+	}
+}
+
+/** Classifies the tokens in some code, in order */
+pub fn highlight(code: &str) -> Vec<(Span, Highlight)> {
+	highlight_with(&Analysis::unchecked(code))
+}
+
+/**
+ * Classifies the tokens in some code which has already been analysed,
+ * like a document in the language server
+ */
+pub fn highlight_with(analysis: &Analysis) -> Vec<(Span, Highlight)> {
+	highlight_tree(analysis, &cst::parse(&analysis.code))
+}
+
+/**
+ * Classifies the tokens in a syntax tree, using the analysis of its code
+ * The tree can be kept up to date with `cst::reparse` as the code changes
+ */
+pub fn highlight_tree(
+	analysis: &Analysis,
+	tree: &SyntaxNode,
+) -> Vec<(Span, Highlight)> {
+	let tokens = tree.tokens();
+	let mut highlights = Vec::new();
+	let mut previous = ""; // the last token which wasn't trivia
+	for token in &tokens {
+		let text = token.text();
+		let highlight =
+			match token.kind() {
+				SyntaxKind::LineComment => Some(Highlight::Comment),
+				SyntaxKind::DocComment => Some(Highlight::DocComment),
+				SyntaxKind::BlockComment
+					if text.starts_with("/**") && text != "/**/" =>
+				{
+					Some(Highlight::DocComment)
+				}
+				SyntaxKind::BlockComment => Some(Highlight::Comment),
+				SyntaxKind::Keyword if text == "fn" && previous == ":" => {
+					Some(Highlight::Type)
+				}
+				SyntaxKind::Keyword => Some(Highlight::Keyword),
+				SyntaxKind::Number => Some(Highlight::Number),
+				SyntaxKind::Str => Some(Highlight::String),
+				SyntaxKind::Operator => Some(Highlight::Operator),
+				SyntaxKind::Name if text == "true" || text == "false" => {
+					Some(Highlight::Keyword)
+				}
+				SyntaxKind::Name if previous == ":" => Some(Highlight::Type),
+				SyntaxKind::Name => analysis
+					.definition(token.span().start)
+					.map(|definition| match definition.kind {
+						SymbolKind::Result => Highlight::Result,
+						SymbolKind::Parameter => Highlight::Parameter,
+						SymbolKind::Variable => Highlight::Variable,
+						SymbolKind::Function
+						| SymbolKind::Implementation
+						| SymbolKind::Closure
+						| SymbolKind::Builtin => Highlight::Function,
+					}),
+				_ => None,
+			};
+
+		if let Some(highlight) = highlight {
+			highlights.push((token.span(), highlight));
+		}
+		if !token.kind().is_trivia() {
+			previous = text;
+		}
+	}
+	highlights
+}
+
+/**
+ * Creates a highlighted HTML snippet of some code
+ * Each classified token is put in a `span` whose class is `ro-` followed
+ * by the highlight's name, like `<span class="ro-keyword">fn</span>`
+ */
+pub fn html(code: &str) -> String {
+	let mut output = String::from("<pre class=\"ro\"><code>");
+	let mut position = 0;
+	for (span, highlight) in highlight(code) {
+		escape(&code[position..span.start], &mut output);
+		output.push_str(&format!("<span class=\"ro-{}\">", highlight.name()));
+		escape(&code[span.start..span.end], &mut output);
+		output.push_str("</span>");
+		position = span.end;
+	}
+	escape(&code[position..], &mut output);
+	output.push_str("</code></pre>\n");
+	output
+}
+
+/** Writes text into HTML, escaping the characters which have meanings */
+fn escape(text: &str, output: &mut String) {
+	for character in text.chars() {
+		match character {
+			'&' => output.push_str("&amp;"),
+			'<' => output.push_str("&lt;"),
+			'>' => output.push_str("&gt;"),
+			'"' => output.push_str("&quot;"),
+			_ => output.push(character),
+		}
+	}
+}
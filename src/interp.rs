@@ -0,0 +1,692 @@
+use crate::diagnostic::Diagnostic;
+use crate::function::CallList;
+use crate::function::CallType;
+use crate::function::Function;
+use crate::function::Operation;
+use crate::program::Program;
+use crate::result::RoResult;
+use crate::span::SourceMap;
+use crate::span::Span;
+use crate::symbol::Symbol;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::rc::Rc;
+
+// functions which are built into the interpreter
+pub const BUILTINS: [&str; 4] = ["print", "int", "float", "string"];
+
+// how many calls can be running at once, so that deep recursion is an error
+// instead of overflowing the stack
+pub const MAX_DEPTH: usize = 1000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+	Int(i64),
+	Float(f64),
+	Bool(bool),
+	Str(String),
+	Void,
+	Closure(Rc<Closure>),
+}
+
+/**
+ * A local function, along with the variables it could see when it was
+ * defined
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Closure {
+	pub name: Symbol, // the variable it was defined as, so it can recurse
+	pub function: Function,
+	pub captured: Variables,
+	pub span: Option<Span>, // the header of its definition, if it's known
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RuntimeError {
+	UnknownFunction(String),
+	UnknownVariable(String),
+	TypeMismatch(String),
+	DivisionByZero,
+	WrongArgumentCount(String, usize, usize),
+	Output(String),
+	StackOverflow,
+}
+
+// a call which was running when an error happened
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+	pub name: String,
+	pub implementation: Option<usize>,
+	pub span: Option<Span>,
+}
+
+// an error, along with the calls that led to it, innermost first
+#[derive(Clone, Debug, PartialEq)]
+pub struct InterpError {
+	pub error: RuntimeError,
+	pub backtrace: Vec<Frame>,
+}
+
+pub type Variables = HashMap<Symbol, Value>;
+
+pub struct Interpreter<'a, W: Write> {
+	pub program: &'a Program,
+	pub output: W,
+	pub source_map: SourceMap,
+	depth: usize, // the number of calls which are running
+	running: (Option<Span>, usize), // the function being run, and its call
+}
+
+impl Value {
+	/**
+	 * Converts a literal token into a value
+	 * Returns None if the token is the name of a variable
+	 */
+	pub fn from_literal(token: &str) -> Option<Self> {
+		if token == "true" {
+			Some(Value::Bool(true))
+		} else if token == "false" {
+			Some(Value::Bool(false))
+		} else if token == "void" {
+			Some(Value::Void)
+		} else if token.len() >= 2
+			&& token.starts_with('"')
+			&& token.ends_with('"')
+		{
+			Some(Value::Str(unescape(&token[1..token.len() - 1])))
+		} else if !token.starts_with(|c: char| c.is_ascii_digit()) {
+			None
+		} else if let Ok(int) = token.parse() {
+			Some(Value::Int(int))
+		} else if let Ok(float) = token.parse() {
+			Some(Value::Float(float))
+		} else {
+			None
+		}
+	}
+
+	/** The name of the value's type */
+	pub fn type_name(&self) -> &'static str {
+		match self {
+			Value::Int(_) => "int",
+			Value::Float(_) => "float",
+			Value::Bool(_) => "bool",
+			Value::Str(_) => "string",
+			Value::Void => "void",
+			Value::Closure(_) => "fn",
+		}
+	}
+}
+
+impl fmt::Display for Value {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Value::Int(int) => write!(f, "{}", int),
+			Value::Float(float) => write!(f, "{}", float),
+			Value::Bool(boolean) => write!(f, "{}", boolean),
+			Value::Str(string) => write!(f, "{}", string),
+			Value::Void => write!(f, "void"),
+			Value::Closure(closure) => {
+				write!(f, "fn{}", closure.function.signature)
+			}
+		}
+	}
+}
+
+impl fmt::Display for RuntimeError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			RuntimeError::UnknownFunction(name) => {
+				write!(f, "unknown function `{}`", name)
+			}
+			RuntimeError::UnknownVariable(name) => {
+				write!(f, "unknown variable `{}`", name)
+			}
+			RuntimeError::TypeMismatch(message) => {
+				write!(f, "type mismatch: {}", message)
+			}
+			RuntimeError::DivisionByZero => write!(f, "division by zero"),
+			RuntimeError::WrongArgumentCount(name, expected, found) => write!(
+				f,
+				"`{}` takes {} arguments but {} were given",
+				name, expected, found
+			),
+			RuntimeError::Output(message) => {
+				write!(f, "couldn't write output: {}", message)
+			}
+			RuntimeError::StackOverflow => write!(f, "call stack too deep"),
+		}
+	}
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl fmt::Display for Frame {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.implementation {
+			Some(index) => {
+				write!(f, "`{}` (implementation {})", self.name, index)
+			}
+			None => write!(f, "`{}`", self.name),
+		}
+	}
+}
+
+impl InterpError {
+	/** Adds a frame to the outside of the backtrace */
+	pub fn within(mut self, frame: Frame) -> Self {
+		self.backtrace.push(frame);
+		self
+	}
+
+	/**
+	 * Converts the error into a diagnostic
+	 * It points at the innermost frame, with a note for each of its callers
+	 */
+	pub fn diagnostic(&self) -> Diagnostic {
+		let mut frames = self.backtrace.iter();
+		let mut diagnostic = match frames.next() {
+			Some(frame) => {
+				let mut diagnostic =
+					Diagnostic::error(self.error.to_string(), frame.span);
+				diagnostic.primary.annotation = format!("in {}", frame);
+				diagnostic
+			}
+			None => Diagnostic::error(self.error.to_string(), None),
+		};
+		for frame in frames {
+			diagnostic.note(format!("called from {}", frame), frame.span);
+		}
+		diagnostic
+	}
+
+	/** Renders the error and its backtrace with snippets of the code */
+	pub fn render(&self, file: &str, code: &str) -> String {
+		self.diagnostic().render(file, code)
+	}
+}
+
+impl From<RuntimeError> for InterpError {
+	fn from(error: RuntimeError) -> Self {
+		InterpError {
+			error,
+			backtrace: Vec::new(),
+		}
+	}
+}
+
+impl fmt::Display for InterpError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.error)?;
+		for frame in &self.backtrace {
+			write!(f, "\n    in {}", frame)?;
+		}
+		Ok(())
+	}
+}
+
+impl std::error::Error for InterpError {}
+
+/** Applies an operation to two values */
+pub fn operate(
+	left: Value,
+	op: &Operation,
+	right: Value,
+) -> Result<Value, RuntimeError> {
+	match (left, right) {
+		(Value::Int(left), Value::Int(right)) => match op {
+			Operation::Add => Ok(Value::Int(left.wrapping_add(right))),
+			Operation::Sub => Ok(Value::Int(left.wrapping_sub(right))),
+			Operation::Mult => Ok(Value::Int(left.wrapping_mul(right))),
+			Operation::Div if right == 0 => Err(RuntimeError::DivisionByZero),
+			Operation::Div => Ok(Value::Int(left.wrapping_div(right))),
+			Operation::Mod if right == 0 => Err(RuntimeError::DivisionByZero),
+			Operation::Mod => Ok(Value::Int(left.wrapping_rem(right))),
+		},
+		(Value::Int(left), Value::Float(right)) => {
+			operate(Value::Float(left as f64), op, Value::Float(right))
+		}
+		(Value::Float(left), Value::Int(right)) => {
+			operate(Value::Float(left), op, Value::Float(right as f64))
+		}
+		(Value::Float(left), Value::Float(right)) => match op {
+			Operation::Add => Ok(Value::Float(left + right)),
+			Operation::Sub => Ok(Value::Float(left - right)),
+			Operation::Mult => Ok(Value::Float(left * right)),
+			Operation::Div | Operation::Mod if right == 0.0 => {
+				Err(RuntimeError::DivisionByZero)
+			}
+			Operation::Div => Ok(Value::Float(left / right)),
+			Operation::Mod => Ok(Value::Float(left % right)),
+		},
+		(Value::Str(left), Value::Str(right)) if *op == Operation::Add => {
+			Ok(Value::Str(left + &right))
+		}
+		(left, right) => Err(RuntimeError::TypeMismatch(format!(
+			"can't apply {} to {} and {}",
+			op,
+			left.type_name(),
+			right.type_name()
+		))),
+	}
+}
+
+impl<'a, W: Write> Interpreter<'a, W> {
+	/** Creates an interpreter which writes its output to `output` */
+	pub fn new(program: &'a Program, output: W) -> Self {
+		Interpreter {
+			program,
+			output,
+			source_map: SourceMap::default(),
+			depth: 0,
+			running: (None, 0),
+		}
+	}
+
+	/**
+	 * Creates an interpreter which knows the code the program came from,
+	 * so that its errors can point to it
+	 */
+	pub fn with_source(program: &'a Program, code: &str, output: W) -> Self {
+		Interpreter {
+			program,
+			output,
+			source_map: SourceMap::new(code),
+			depth: 0,
+			running: (None, 0),
+		}
+	}
+
+	/** Runs the program, starting at main */
+	pub fn run(&mut self) -> Result<Value, InterpError> {
+		self.call("main", Vec::new())
+	}
+
+	/** Calls a free function, a result or a built-in by its name */
+	pub fn call(
+		&mut self,
+		name: &str,
+		arguments: Vec<Value>,
+	) -> Result<Value, InterpError> {
+		let program = self.program;
+		if let Some(function) = program
+			.functions
+			.iter()
+			.find(|f| f.signature.name.as_deref() == Some(name))
+		{
+			self.call_function(function, arguments)
+		} else if let Some(result) =
+			program.results.iter().find(|r| r.signature.name == name)
+		{
+			self.call_result(result, arguments)
+		} else if BUILTINS.contains(&name) {
+			Ok(builtin(name, arguments, &mut self.output)?)
+		} else {
+			Err(RuntimeError::UnknownFunction(String::from(name)).into())
+		}
+	}
+
+	/** Binds the arguments to a function's parameters and runs it */
+	pub fn call_function(
+		&mut self,
+		function: &Function,
+		arguments: Vec<Value>,
+	) -> Result<Value, InterpError> {
+		let name = function.signature.name.unwrap_or_default();
+		let mut variables = bind(&name, &function.parameter_order, arguments)?;
+		let span = self.source_map.function(&name);
+		let outer = self.enter(span)?;
+		let value = self.execute_calls(&function.calls, &mut variables);
+		self.exit(outer);
+		value.map_err(|(index, e)| {
+			e.within(self.frame(&name, None, span, index))
+		})
+	}
+
+	/**
+	 * Runs a closure with the variables it captured
+	 * The closure can call itself by the name it was defined as
+	 */
+	pub fn call_closure(
+		&mut self,
+		closure: &Rc<Closure>,
+		arguments: Vec<Value>,
+	) -> Result<Value, InterpError> {
+		let name = closure.name;
+		let parameters = &closure.function.parameter_order;
+		let mut variables = closure.captured.clone();
+		variables.insert(name, Value::Closure(Rc::clone(closure)));
+		variables.extend(bind(&name, parameters, arguments)?);
+		let outer = self.enter(closure.span)?;
+		let value = self.execute_calls(&closure.function.calls, &mut variables);
+		self.exit(outer);
+		value.map_err(|(index, e)| {
+			e.within(self.frame(&name, None, closure.span, index))
+		})
+	}
+
+	/**
+	 * Calls a closure stored in a variable, or else the function with the
+	 * same name
+	 */
+	fn call_variable(
+		&mut self,
+		name: &Symbol,
+		arguments: Vec<Value>,
+		variables: &Variables,
+	) -> Result<Value, InterpError> {
+		match variables.get(name) {
+			Some(Value::Closure(closure)) => {
+				self.call_closure(closure, arguments)
+			}
+			_ => self.call(name, arguments),
+		}
+	}
+
+	/**
+	 * Runs the first implementation of a result
+	 * Implementations can rename the result's parameters, as in
+	 * `fn(first = one)`
+	 */
+	pub fn call_result(
+		&mut self,
+		result: &RoResult,
+		arguments: Vec<Value>,
+	) -> Result<Value, InterpError> {
+		let mut variables =
+			bind(&result.signature.name, &result.parameter_order, arguments)?;
+		let index = 0; // the first implementation is the one that runs
+		let function = match result.functions.get(index) {
+			Some(function) => function,
+			None => return Ok(Value::Void),
+		};
+
+		if let Some(parameters) = &function.signature.parameters {
+			for (name, original) in parameters {
+				if let Some(value) = variables.get(original).cloned() {
+					variables.insert(*name, value);
+				}
+			}
+		}
+
+		let name = &result.signature.name;
+		let span = self.source_map.implementation(name, index);
+		let outer = self.enter(span)?;
+		let value = self.execute_calls(&function.calls, &mut variables);
+		self.exit(outer);
+		value.map_err(|(call, e)| {
+			e.within(self.frame(name, Some(index), span, call))
+		})
+	}
+
+	/**
+	 * Starts running the function whose header is at `function`, unless too
+	 * many calls are already running
+	 * Gives back what was running before, for `exit`
+	 */
+	fn enter(
+		&mut self,
+		function: Option<Span>,
+	) -> Result<(Option<Span>, usize), RuntimeError> {
+		if self.depth >= MAX_DEPTH {
+			return Err(RuntimeError::StackOverflow);
+		}
+		self.depth += 1;
+		Ok(std::mem::replace(&mut self.running, (function, 0)))
+	}
+
+	/** Goes back to running what was running before `enter` */
+	fn exit(&mut self, outer: (Option<Span>, usize)) {
+		self.depth -= 1;
+		self.running = outer;
+	}
+
+	/**
+	 * Creates a frame for a function which failed at one of its calls
+	 * It points at the statement the call came from, or else at the
+	 * function's header
+	 */
+	fn frame(
+		&self,
+		name: &str,
+		implementation: Option<usize>,
+		function: Option<Span>,
+		call: usize,
+	) -> Frame {
+		let span = function.map(|f| self.source_map.call(f, call).unwrap_or(f));
+		Frame {
+			name: String::from(name),
+			implementation,
+			span,
+		}
+	}
+
+	/**
+	 * Runs a list of calls until one of them returns
+	 * The variables are left as they were at that point
+	 */
+	pub fn execute(
+		&mut self,
+		calls: &[CallType],
+		variables: &mut Variables,
+	) -> Result<Value, InterpError> {
+		self.execute_calls(calls, variables).map_err(|(_, e)| e)
+	}
+
+	/**
+	 * Runs a list of calls like `execute`, also giving the index of the
+	 * call that failed
+	 */
+	fn execute_calls(
+		&mut self,
+		calls: &[CallType],
+		variables: &mut Variables,
+	) -> Result<Value, (usize, InterpError)> {
+		for (index, call) in calls.iter().enumerate() {
+			self.running.1 = index;
+			match self.execute_call(call, variables) {
+				Ok(Some(value)) => return Ok(value),
+				Ok(None) => (),
+				Err(error) => return Err((index, error)),
+			}
+		}
+		Ok(Value::Void)
+	}
+
+	/** Runs a single call, giving a value if it returns */
+	fn execute_call(
+		&mut self,
+		call: &CallType,
+		variables: &mut Variables,
+	) -> Result<Option<Value>, InterpError> {
+		match call {
+			CallType::Return(value) => {
+				return Ok(Some(self.value(value, variables)?));
+			}
+			CallType::Init(var) => {
+				variables.insert(*var, Value::Void);
+			}
+			CallType::Set(..) => {
+				let lowered: CallList =
+					CallType::sets_to_ops(vec![call.clone()]);
+				for call in lowered {
+					self.step(&call, variables)?;
+				}
+			}
+			_ => self.step(call, variables)?,
+		}
+		Ok(None)
+	}
+
+	/** Runs a single call which doesn't return */
+	fn step(
+		&mut self,
+		call: &CallType,
+		variables: &mut Variables,
+	) -> Result<(), InterpError> {
+		match call {
+			CallType::Call(name, parameters) => {
+				let arguments = self.values(parameters, variables)?;
+				self.call_variable(name, arguments, variables)?;
+			}
+			CallType::SetCall(var, name, parameters) => {
+				let arguments = self.values(parameters, variables)?;
+				let value = self.call_variable(name, arguments, variables)?;
+				variables.insert(*var, value);
+			}
+			CallType::Move(var, value) => {
+				let value = self.value(value, variables)?;
+				variables.insert(*var, value);
+			}
+			CallType::Operate(var, left, op, right) => {
+				let left = self.value(left, variables)?;
+				let right = self.value(right, variables)?;
+				variables.insert(*var, operate(left, op, right)?);
+			}
+			CallType::Init(var) => {
+				variables.insert(*var, Value::Void);
+			}
+			CallType::Closure(var, function) => {
+				let (running, index) = self.running;
+				let closure = Closure {
+					name: *var,
+					function: function.clone(),
+					captured: variables.clone(),
+					span: running
+						.and_then(|f| self.source_map.closure(f, index)),
+				};
+				variables.insert(*var, Value::Closure(Rc::new(closure)));
+			}
+			CallType::Return(_) | CallType::Set(..) => (),
+		}
+		Ok(())
+	}
+
+	/** Gets the value of a literal or a variable */
+	fn value(
+		&self,
+		token: &Symbol,
+		variables: &Variables,
+	) -> Result<Value, RuntimeError> {
+		if let Some(value) = variables.get(token) {
+			Ok(value.clone())
+		} else if let Some(value) = Value::from_literal(token) {
+			Ok(value)
+		} else {
+			Err(RuntimeError::UnknownVariable(token.to_string()))
+		}
+	}
+
+	fn values(
+		&self,
+		tokens: &[Symbol],
+		variables: &Variables,
+	) -> Result<Vec<Value>, RuntimeError> {
+		tokens.iter().map(|t| self.value(t, variables)).collect()
+	}
+}
+
+/**
+ * Runs one of the built-in functions, writing anything it prints to
+ * `output`
+ */
+pub fn builtin<W: Write>(
+	name: &str,
+	arguments: Vec<Value>,
+	output: &mut W,
+) -> Result<Value, RuntimeError> {
+	if name == "print" {
+		let line: Vec<String> =
+			arguments.iter().map(|a| a.to_string()).collect();
+		writeln!(output, "{}", line.join(" "))
+			.map_err(|e| RuntimeError::Output(e.to_string()))?;
+		return Ok(Value::Void);
+	}
+
+	if arguments.len() != 1 {
+		return Err(RuntimeError::WrongArgumentCount(
+			String::from(name),
+			1,
+			arguments.len(),
+		));
+	}
+	let argument = arguments.into_iter().next().unwrap();
+	match (name, argument) {
+		("string", value) => Ok(Value::Str(value.to_string())),
+		("int", Value::Int(int)) => Ok(Value::Int(int)),
+		("int", Value::Float(float)) => Ok(Value::Int(float as i64)),
+		("float", Value::Int(int)) => Ok(Value::Float(int as f64)),
+		("float", Value::Float(float)) => Ok(Value::Float(float)),
+		("int", Value::Str(string)) => match string.trim().parse() {
+			Ok(int) => Ok(Value::Int(int)),
+			Err(_) => Err(RuntimeError::TypeMismatch(format!(
+				"can't convert \"{}\" to int",
+				string
+			))),
+		},
+		("float", Value::Str(string)) => match string.trim().parse() {
+			Ok(float) => Ok(Value::Float(float)),
+			Err(_) => Err(RuntimeError::TypeMismatch(format!(
+				"can't convert \"{}\" to float",
+				string
+			))),
+		},
+		(_, value) => Err(RuntimeError::TypeMismatch(format!(
+			"can't convert {} to {}",
+			value.type_name(),
+			name
+		))),
+	}
+}
+
+/** Creates the variables for a call from its parameters and arguments */
+fn bind(
+	name: &str,
+	parameters: &[Symbol],
+	arguments: Vec<Value>,
+) -> Result<Variables, RuntimeError> {
+	if parameters.len() != arguments.len() {
+		return Err(RuntimeError::WrongArgumentCount(
+			String::from(name),
+			parameters.len(),
+			arguments.len(),
+		));
+	}
+	Ok(parameters.iter().copied().zip(arguments).collect())
+}
+
+/**
+ * Decodes the escapes in the text of a string, from left to right so that
+ * an escaped backslash can't start another escape
+ * Escapes which don't mean anything are kept as they are
+ */
+fn unescape(text: &str) -> String {
+	let mut string = String::with_capacity(text.len());
+	let mut characters = text.chars();
+	while let Some(character) = characters.next() {
+		if character != '\\' {
+			string.push(character);
+			continue;
+		}
+		match characters.next() {
+			Some('n') => string.push('\n'),
+			Some('t') => string.push('\t'),
+			Some('"') => string.push('"'),
+			Some('\\') => string.push('\\'),
+			Some(other) => {
+				string.push('\\');
+				string.push(other);
+			}
+			None => string.push('\\'),
+		}
+	}
+	string
+}
+
+/** Runs a program, printing to stdout */
+pub fn run(program: &Program) -> Result<Value, InterpError> {
+	let stdout = std::io::stdout();
+	Interpreter::new(program, stdout.lock()).run()
+}
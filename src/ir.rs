@@ -0,0 +1,613 @@
+use crate::diagnostic::Diagnostic;
+use crate::function::CallType;
+use crate::function::Function;
+use crate::function::Operation;
+use crate::interp::Value;
+use crate::program::Program;
+use crate::result::RoResult;
+use crate::symbol::Symbol;
+use crate::typeck::call_signature;
+use crate::typeck::Type;
+
+use std::collections::HashMap;
+use std::fmt;
+
+/*
+ * The intermediate representation, which sits between the AST and the
+ * backends
+ *
+ * Each function is a list of basic blocks, starting with the first one.
+ * A block is a list of three-address instructions on virtual registers,
+ * ending with a terminator which says where to go next. Every register has
+ * a type, which is Unknown if it can't be worked out. Phis, which are only
+ * added by `ssa::construct`, pick a value based on the block before.
+ *
+ * Ro doesn't have any control flow yet, so `lower` only ever produces the
+ * first block of each function, and every other block comes from the
+ * passes which work on the IR.
+ *
+ * The dump lists the registers of a function before its blocks:
+ *
+ * fn add(%0, %1): float {
+ * 	%0 one: float
+ * 	%1 two: float
+ * 	%2 return: float
+ * bb0:
+ * 	%2 = %0 + %1
+ * 	return %2
+ * }
+ */
+
+// a virtual register, which can be written to any number of times
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Register(pub usize);
+
+// the index of a block in its function
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+// what's known about a register
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegisterInfo {
+	pub value_type: Type,
+	pub name: Option<Symbol>, // the variable it came from, if there was one
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operand {
+	Register(Register),
+	Constant(Value),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction {
+	Move(Register, Operand),
+	Operate(Register, Operand, Operation, Operand),
+	Call(Option<Register>, Symbol, Vec<Operand>),
+	Phi(Register, Vec<(BlockId, Operand)>), // the value from the block before
+}
+
+// how a block ends
+#[derive(Clone, Debug, PartialEq)]
+pub enum Terminator {
+	Jump(BlockId),
+	Branch(Operand, BlockId, BlockId), // to the first block if it's true
+	Return(Operand),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+	pub instructions: Vec<Instruction>,
+	pub terminator: Terminator,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IrFunction {
+	pub name: Symbol,
+	pub parameters: Vec<Register>,
+	pub return_type: Type,
+	pub registers: Vec<RegisterInfo>,
+	pub blocks: Vec<Block>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Module {
+	pub functions: Vec<IrFunction>,
+}
+
+impl Instruction {
+	/** Gets the register which the instruction writes to, if there is one */
+	pub fn destination(&self) -> Option<Register> {
+		match self {
+			Instruction::Move(register, _)
+			| Instruction::Operate(register, ..)
+			| Instruction::Phi(register, _) => Some(*register),
+			Instruction::Call(register, ..) => *register,
+		}
+	}
+
+	/** Gets the values which the instruction reads */
+	pub fn operands(&self) -> Vec<&Operand> {
+		match self {
+			Instruction::Move(_, value) => vec![value],
+			Instruction::Operate(_, left, _, right) => vec![left, right],
+			Instruction::Call(_, _, arguments) => arguments.iter().collect(),
+			Instruction::Phi(_, values) => {
+				values.iter().map(|v| &v.1).collect()
+			}
+		}
+	}
+
+	/** Gets the values which the instruction reads, so they can be changed */
+	pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+		match self {
+			Instruction::Move(_, value) => vec![value],
+			Instruction::Operate(_, left, _, right) => vec![left, right],
+			Instruction::Call(_, _, arguments) => {
+				arguments.iter_mut().collect()
+			}
+			Instruction::Phi(_, values) => {
+				values.iter_mut().map(|v| &mut v.1).collect()
+			}
+		}
+	}
+}
+
+impl Terminator {
+	/** Gets the blocks which could run after this one */
+	pub fn successors(&self) -> Vec<BlockId> {
+		match self {
+			Terminator::Jump(block) => vec![*block],
+			Terminator::Branch(_, yes, no) => vec![*yes, *no],
+			Terminator::Return(_) => Vec::new(),
+		}
+	}
+
+	/** Gets the value which the terminator reads */
+	pub fn operand(&self) -> Option<&Operand> {
+		match self {
+			Terminator::Jump(_) => None,
+			Terminator::Branch(condition, ..) => Some(condition),
+			Terminator::Return(value) => Some(value),
+		}
+	}
+
+	/** Gets the value which the terminator reads, so it can be changed */
+	pub fn operand_mut(&mut self) -> Option<&mut Operand> {
+		match self {
+			Terminator::Jump(_) => None,
+			Terminator::Branch(condition, ..) => Some(condition),
+			Terminator::Return(value) => Some(value),
+		}
+	}
+}
+
+impl IrFunction {
+	/** Creates a function with an empty first block */
+	pub fn new(name: Symbol, return_type: Type) -> Self {
+		IrFunction {
+			name,
+			parameters: Vec::new(),
+			return_type,
+			registers: Vec::new(),
+			blocks: vec![Block {
+				instructions: Vec::new(),
+				terminator: Terminator::Return(Operand::Constant(Value::Void)),
+			}],
+		}
+	}
+
+	/** Adds a new register */
+	pub fn register(
+		&mut self,
+		value_type: Type,
+		name: Option<Symbol>,
+	) -> Register {
+		self.registers.push(RegisterInfo { value_type, name });
+		Register(self.registers.len() - 1)
+	}
+
+	/** Adds a new block, which returns void until it's given a terminator */
+	pub fn block(&mut self) -> BlockId {
+		self.blocks.push(Block {
+			instructions: Vec::new(),
+			terminator: Terminator::Return(Operand::Constant(Value::Void)),
+		});
+		BlockId(self.blocks.len() - 1)
+	}
+
+	/** Gets the blocks which can jump to each block */
+	pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+		let mut predecessors = vec![Vec::new(); self.blocks.len()];
+		for (i, block) in self.blocks.iter().enumerate() {
+			for successor in block.terminator.successors() {
+				if !predecessors[successor.0].contains(&BlockId(i)) {
+					predecessors[successor.0].push(BlockId(i));
+				}
+			}
+		}
+		predecessors
+	}
+
+	/**
+	 * Lists the blocks which can be reached from the first one, with each
+	 * block coming before the blocks it jumps to, except for loops
+	 */
+	pub fn reverse_postorder(&self) -> Vec<BlockId> {
+		let mut order = Vec::new();
+		let mut visited = vec![false; self.blocks.len()];
+
+		// each block is visited along with the successors it has left
+		let mut stack = vec![(BlockId(0), 0)];
+		visited[0] = true;
+		while let Some((block, next)) = stack.pop() {
+			let successors = self.blocks[block.0].terminator.successors();
+			match successors.get(next) {
+				Some(successor) => {
+					stack.push((block, next + 1));
+					if !visited[successor.0] {
+						visited[successor.0] = true;
+						stack.push((*successor, 0));
+					}
+				}
+				None => order.push(block),
+			}
+		}
+
+		order.reverse();
+		order
+	}
+
+	/** Gets the type of a value */
+	pub fn type_of(&self, operand: &Operand) -> Type {
+		match operand {
+			Operand::Register(register) => {
+				self.registers[register.0].value_type
+			}
+			Operand::Constant(value) => Type::of_value(value),
+		}
+	}
+}
+
+/**
+ * Lowers a resolved program into the IR
+ * Each result becomes a function which runs its first implementation, like
+ * in the interpreter
+ */
+pub fn lower(program: &Program) -> Result<Module, Diagnostic> {
+	let mut module = Module::default();
+
+	for result in &program.results {
+		let function = result.functions.first();
+		module
+			.functions
+			.push(lower_implementation(program, result, function)?);
+	}
+
+	for function in &program.functions {
+		let signature = &function.signature;
+		let mut builder = Builder::new(
+			program,
+			signature.name.unwrap_or_default(),
+			signature.return_type.as_ref(),
+		);
+		for parameter in &function.parameter_order {
+			let t = signature
+				.parameters
+				.as_ref()
+				.and_then(|p| p.get(parameter))
+				.map_or(Type::Unknown, |t| Type::from_name(t));
+			builder.parameter(*parameter, t);
+		}
+		builder.calls(function)?;
+		module.functions.push(builder.function);
+	}
+
+	Ok(module)
+}
+
+/**
+ * Lowers one of a result's implementations into a function named after the
+ * result
+ * A result without any implementations returns void
+ */
+pub fn lower_implementation(
+	program: &Program,
+	result: &RoResult,
+	function: Option<&Function>,
+) -> Result<IrFunction, Diagnostic> {
+	let signature = &result.signature;
+	let return_type = function
+		.and_then(|f| f.signature.return_type.as_ref())
+		.or(signature.return_type.as_ref());
+	let mut builder = Builder::new(program, signature.name, return_type);
+	for parameter in &result.parameter_order {
+		let t = match signature.parameters.get(parameter) {
+			Some(t) => Type::from_name(t),
+			None => {
+				let message = format!("`{}` doesn't have a type", parameter);
+				return Err(builder.error(message));
+			}
+		};
+		builder.parameter(*parameter, t);
+	}
+
+	if let Some(function) = function {
+		// renamed parameters are copied from the original
+		for parameter in &function.parameter_order {
+			let parameters = function.signature.parameters.as_ref();
+			let original = parameters.and_then(|p| p.get(parameter));
+			if let Some(original) = original.filter(|o| *o != parameter) {
+				if !signature.parameters.contains_key(original) {
+					return Err(builder.error(format!(
+						"`{}` isn't a parameter of the result",
+						original
+					)));
+				}
+				builder.copy(*parameter, *original)?;
+			}
+		}
+		builder.calls(function)?;
+	}
+	Ok(builder.function)
+}
+
+// lowers the calls of one function into its first block
+struct Builder<'a> {
+	program: &'a Program,
+	function: IrFunction,
+	variables: HashMap<Symbol, Register>,
+	assigned: HashMap<Register, Type>, // the types written to each register
+}
+
+impl<'a> Builder<'a> {
+	fn new(
+		program: &'a Program,
+		name: Symbol,
+		return_type: Option<&Symbol>,
+	) -> Self {
+		let return_type =
+			return_type.map_or(Type::Unknown, |t| Type::from_name(t));
+		Builder {
+			program,
+			function: IrFunction::new(name, return_type),
+			variables: HashMap::new(),
+			assigned: HashMap::new(),
+		}
+	}
+
+	fn parameter(&mut self, name: Symbol, t: Type) {
+		let register = self.function.register(t, Some(name));
+		self.function.parameters.push(register);
+		self.variables.insert(name, register);
+		self.assigned.insert(register, t);
+	}
+
+	fn error(&self, message: String) -> Diagnostic {
+		Diagnostic::error(
+			format!("{}, in `{}`", message, self.function.name),
+			None,
+		)
+	}
+
+	/** Gets the register for a variable, creating it if it's new */
+	fn variable(&mut self, name: Symbol) -> Register {
+		match self.variables.get(&name) {
+			Some(register) => *register,
+			None => {
+				let register = self.function.register(Type::Void, Some(name));
+				self.variables.insert(name, register);
+				register
+			}
+		}
+	}
+
+	/** Converts a token into a constant or the register of a variable */
+	fn operand(&self, token: &Symbol) -> Result<Operand, Diagnostic> {
+		if let Some(register) = self.variables.get(token) {
+			Ok(Operand::Register(*register))
+		} else if let Some(value) = Value::from_literal(token) {
+			Ok(Operand::Constant(value))
+		} else {
+			Err(self.error(format!("unknown variable `{}`", token)))
+		}
+	}
+
+	/**
+	 * Writes a value to a variable, keeping track of its type
+	 * A register which is given values of different types is Unknown
+	 */
+	fn write(&mut self, var: Symbol, t: Type) -> Register {
+		let register = self.variable(var);
+		let t = match self.assigned.get(&register) {
+			Some(old) if *old != t => Type::Unknown,
+			_ => t,
+		};
+		self.assigned.insert(register, t);
+		self.function.registers[register.0].value_type = t;
+		register
+	}
+
+	/** Copies a constant or a variable into a variable */
+	fn copy(&mut self, var: Symbol, value: Symbol) -> Result<(), Diagnostic> {
+		let value = self.operand(&value)?;
+		let register = self.write(var, self.function.type_of(&value));
+		self.push(Instruction::Move(register, value));
+		Ok(())
+	}
+
+	fn push(&mut self, instruction: Instruction) {
+		self.function.blocks[0].instructions.push(instruction);
+	}
+
+	/** Lowers the calls in a function, up to the first return */
+	fn calls(&mut self, function: &Function) -> Result<(), Diagnostic> {
+		let calls = CallType::sets_to_ops(function.calls.clone());
+		for call in &calls {
+			match call {
+				CallType::Init(var) => {
+					let register = self.variable(*var);
+					let void = Operand::Constant(Value::Void);
+					self.push(Instruction::Move(register, void));
+				}
+				CallType::Move(var, value) => self.copy(*var, *value)?,
+				CallType::Operate(var, left, op, right) => {
+					let left = self.operand(left)?;
+					let right = self.operand(right)?;
+					let t = self
+						.function
+						.type_of(&left)
+						.operate(op, self.function.type_of(&right))
+						.unwrap_or(Type::Unknown);
+					let register = self.write(*var, t);
+					self.push(Instruction::Operate(
+						register,
+						left,
+						op.clone(),
+						right,
+					));
+				}
+				CallType::Call(name, parameters)
+				| CallType::SetCall(_, name, parameters) => {
+					if self.variables.contains_key(name) {
+						return Err(self.error(format!(
+							"`{}` is called through a variable, which can't \
+							 be compiled yet",
+							name
+						)));
+					}
+					let arguments = parameters
+						.iter()
+						.map(|p| self.operand(p))
+						.collect::<Result<_, _>>()?;
+					let register = match call {
+						CallType::SetCall(var, ..) => {
+							let t = call_signature(self.program, name)
+								.map_or(Type::Unknown, |s| s.return_type);
+							Some(self.write(*var, t))
+						}
+						_ => None,
+					};
+					self.push(Instruction::Call(register, *name, arguments));
+				}
+				CallType::Return(value) => {
+					let value = self.operand(value)?;
+					self.function.blocks[0].terminator =
+						Terminator::Return(value);
+					return Ok(());
+				}
+				CallType::Closure(var, _) => {
+					return Err(self.error(format!(
+						"`{}` is a closure, which can't be compiled yet",
+						var
+					)))
+				}
+				CallType::Set(..) => (),
+			}
+		}
+		Ok(())
+	}
+}
+
+impl fmt::Display for Register {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "%{}", self.0)
+	}
+}
+
+impl fmt::Display for BlockId {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "bb{}", self.0)
+	}
+}
+
+impl fmt::Display for Operand {
+	/** Writes the operand, with constants written as Ro literals */
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Operand::Register(register) => write!(f, "{}", register),
+			Operand::Constant(Value::Float(float)) => write!(f, "{:?}", float),
+			Operand::Constant(Value::Str(string)) => write!(
+				f,
+				"\"{}\"",
+				string
+					.replace('\\', "\\\\")
+					.replace('"', "\\\"")
+					.replace('\n', "\\n")
+					.replace('\t', "\\t")
+			),
+			Operand::Constant(value) => write!(f, "{}", value),
+		}
+	}
+}
+
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Instruction::Move(register, value) => {
+				write!(f, "{} = {}", register, value)
+			}
+			Instruction::Operate(register, left, op, right) => {
+				write!(f, "{} = {} {} {}", register, left, op, right)
+			}
+			Instruction::Call(register, name, arguments) => {
+				if let Some(register) = register {
+					write!(f, "{} = ", register)?;
+				}
+				write!(f, "call {}(", name)?;
+				for (i, argument) in arguments.iter().enumerate() {
+					if i > 0 {
+						write!(f, ", ")?;
+					}
+					write!(f, "{}", argument)?;
+				}
+				write!(f, ")")
+			}
+			Instruction::Phi(register, values) => {
+				write!(f, "{} = phi ", register)?;
+				for (i, (block, value)) in values.iter().enumerate() {
+					if i > 0 {
+						write!(f, ", ")?;
+					}
+					write!(f, "[{}: {}]", block, value)?;
+				}
+				Ok(())
+			}
+		}
+	}
+}
+
+impl fmt::Display for Terminator {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Terminator::Jump(block) => write!(f, "jump {}", block),
+			Terminator::Branch(condition, yes, no) => {
+				write!(f, "branch {}, {}, {}", condition, yes, no)
+			}
+			Terminator::Return(value) => write!(f, "return {}", value),
+		}
+	}
+}
+
+impl fmt::Display for IrFunction {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "fn {}(", self.name)?;
+		for (i, parameter) in self.parameters.iter().enumerate() {
+			if i > 0 {
+				write!(f, ", ")?;
+			}
+			write!(f, "{}", parameter)?;
+		}
+		writeln!(f, "): {} {{", self.return_type)?;
+
+		for (i, info) in self.registers.iter().enumerate() {
+			write!(f, "\t{}", Register(i))?;
+			if let Some(name) = info.name {
+				write!(f, " {}", name)?;
+			}
+			writeln!(f, ": {}", info.value_type)?;
+		}
+
+		for (i, block) in self.blocks.iter().enumerate() {
+			writeln!(f, "{}:", BlockId(i))?;
+			for instruction in &block.instructions {
+				writeln!(f, "\t{}", instruction)?;
+			}
+			writeln!(f, "\t{}", block.terminator)?;
+		}
+		write!(f, "}}")
+	}
+}
+
+impl fmt::Display for Module {
+	/** Writes the functions, with an empty line between each of them */
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for (i, function) in self.functions.iter().enumerate() {
+			if i > 0 {
+				writeln!(f)?;
+			}
+			writeln!(f, "{}", function)?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,129 @@
+use crate::program::Program;
+use crate::symbol::Symbol;
+use crate::tokenizer::Token;
+
+use serde::Deserialize;
+use serde::Serialize;
+use serde::Serializer;
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+/**
+ * The version of the JSON schema, which changes whenever the AST does
+ *
+ * Version 2 documents are objects with a `version` and one other field:
+ *
+ * ```text
+ * {"version": 2, "program": Program}
+ * {"version": 2, "tokens": [Token]}
+ *
+ * Token = {"text": string, "span": {"start": int, "end": int}}
+ * Program = {"results": [RoResult], "functions": [Function]}
+ * RoResult = {"signature": ResultSig, "functions": [Function],
+ *     "parameter_order": [string]}
+ * ResultSig = {"name": string, "return_type": string | null,
+ *     "parameters": {name: type}}
+ * Function = {"signature": FuncSig, "calls": [CallType],
+ *     "parameter_order": [string]}
+ * FuncSig = {"name": string | null, "parameters": {name: type} | null,
+ *     "return_type": string | null, "result": string | null}
+ * ```
+ *
+ * Each CallType is an object with one field, named after its kind:
+ *
+ * ```text
+ * {"Return": value}
+ * {"Init": var}
+ * {"Set": [var, [token]]}
+ * {"Call": [function, [argument]]}
+ * {"SetCall": [var, function, [argument]]}
+ * {"Move": [var, value]}
+ * {"Operate": [var, left, Operation, right]}
+ * {"Closure": [var, Function]}
+ *
+ * Operation = "Add" | "Sub" | "Mult" | "Div" | "Mod"
+ * ```
+ *
+ * Spans are byte offsets into the code. Parameters are objects, which are
+ * written sorted by name so the same program always gives the same document,
+ * and `parameter_order` gives the order they were written in.
+ */
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProgramDocument {
+	pub version: u32,
+	pub program: Program,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TokensDocument {
+	pub version: u32,
+	pub tokens: Vec<Token>,
+}
+
+// the documents which are written, borrowing what's in them
+#[derive(Serialize)]
+struct ProgramRef<'a> {
+	version: u32,
+	program: &'a Program,
+}
+
+#[derive(Serialize)]
+struct TokensRef<'a> {
+	version: u32,
+	tokens: &'a [Token],
+}
+
+/** Converts a program to a JSON document */
+pub fn program_to_json(program: &Program) -> String {
+	let document = ProgramRef {
+		version: SCHEMA_VERSION,
+		program,
+	};
+	serde_json::to_string_pretty(&document).unwrap()
+}
+
+/** Converts a list of tokens to a JSON document */
+pub fn tokens_to_json(tokens: &[Token]) -> String {
+	let document = TokensRef {
+		version: SCHEMA_VERSION,
+		tokens,
+	};
+	serde_json::to_string_pretty(&document).unwrap()
+}
+
+/** Writes a signature's parameters sorted by name */
+pub(crate) fn sorted<S: Serializer>(
+	parameters: &HashMap<Symbol, Symbol>,
+	serializer: S,
+) -> Result<S::Ok, S::Error> {
+	let sorted: BTreeMap<&Symbol, &Symbol> = parameters.iter().collect();
+	sorted.serialize(serializer)
+}
+
+/** Writes a function's parameters sorted by name, if it has any */
+pub(crate) fn sorted_option<S: Serializer>(
+	parameters: &Option<HashMap<Symbol, Symbol>>,
+	serializer: S,
+) -> Result<S::Ok, S::Error> {
+	let sorted: Option<BTreeMap<&Symbol, &Symbol>> =
+		parameters.as_ref().map(|p| p.iter().collect());
+	sorted.serialize(serializer)
+}
+
+/**
+ * Reads a program from a JSON document
+ * Fails if the document uses a different version of the schema
+ */
+pub fn program_from_json(json: &str) -> serde_json::Result<Program> {
+	let document: ProgramDocument = serde_json::from_str(json)?;
+	if document.version != SCHEMA_VERSION {
+		return Err(serde::de::Error::custom(format!(
+			"expected version {} of the schema, but found version {}",
+			SCHEMA_VERSION, document.version
+		)));
+	}
+	Ok(document.program)
+}
@@ -1,12 +1,38 @@
-#![feature(test)]
+#![cfg_attr(test, feature(test))]
 #![allow(clippy::tabs_in_doc_comments)]
 
 extern crate ro_backend;
 
+pub mod analysis;
+pub mod bytecode;
+pub mod c;
+pub mod check;
+pub mod codegen;
+pub mod cst;
+pub mod diagnostic;
+pub mod formatter;
 pub mod function;
+pub mod highlight;
+pub mod interp;
+pub mod ir;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod opt;
+pub mod parser;
 pub mod program;
+pub mod refactor;
+pub mod repl;
+pub mod resolve;
 pub mod result;
+pub mod sexp;
+pub mod span;
+pub mod ssa;
+pub mod symbol;
 pub mod tokenizer;
+pub mod typeck;
+pub mod typed;
+pub mod vm;
+pub mod wasm;
 
 #[cfg(test)]
 mod tests;
@@ -16,5 +42,15 @@ mod benches;
 
 // the main function
 pub fn parse(code: String) -> program::Program {
-	program::ProgramParser::from_tokens(tokenizer::tokenize(code)).parse()
+	parser::Parser::new(&tokenizer::tokenize(code)).program()
+}
+
+/**
+ * Parses some code into both a program and a lossless syntax tree
+ * The program is parsed from the tree's tokens, so the two always agree
+ */
+pub fn parse_with_cst(code: &str) -> (program::Program, cst::SyntaxNode) {
+	let tree = cst::parse(code);
+	let program = parser::Parser::new(&tree.token_list()).program();
+	(program, tree)
 }
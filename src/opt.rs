@@ -0,0 +1,522 @@
+use crate::function::Operation;
+use crate::interp::operate;
+use crate::interp::Value;
+use crate::ir::BlockId;
+use crate::ir::Instruction;
+use crate::ir::IrFunction;
+use crate::ir::Module;
+use crate::ir::Operand;
+use crate::ir::Register;
+use crate::ir::Terminator;
+use crate::ssa;
+use crate::typeck::Type;
+
+use std::collections::HashMap;
+use std::fmt;
+
+/*
+ * Optimisations on functions in SSA form
+ *
+ * Each pass returns whether it changed anything, and the pass manager runs
+ * its passes until none of them do. Float arithmetic is treated as exact, so
+ * `x + 1 - 1` folds to `x` even though rounding could make them differ.
+ */
+
+// the number of times the passes are run before giving up on a fixed point
+const MAX_ROUNDS: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+	FoldConstants,
+	PropagateCopies,
+	EliminateCommonSubexpressions,
+	EliminateDeadCode,
+}
+
+// runs a list of passes, in order
+#[derive(Clone, Debug, PartialEq)]
+pub struct PassManager {
+	pub passes: Vec<Pass>,
+}
+
+impl Pass {
+	pub const ALL: [Pass; 4] = [
+		Pass::FoldConstants,
+		Pass::PropagateCopies,
+		Pass::EliminateCommonSubexpressions,
+		Pass::EliminateDeadCode,
+	];
+
+	/** Runs the pass on a function, returning whether it changed anything */
+	pub fn run(self, function: &mut IrFunction) -> bool {
+		match self {
+			Pass::FoldConstants => fold_constants(function),
+			Pass::PropagateCopies => propagate_copies(function),
+			Pass::EliminateCommonSubexpressions => {
+				eliminate_common_subexpressions(function)
+			}
+			Pass::EliminateDeadCode => eliminate_dead_code(function),
+		}
+	}
+
+	/** Finds a pass by its name, like `fold-constants` */
+	pub fn from_name(name: &str) -> Option<Self> {
+		Pass::ALL.iter().copied().find(|p| p.to_string() == name)
+	}
+}
+
+impl fmt::Display for Pass {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let name = match self {
+			Pass::FoldConstants => "fold-constants",
+			Pass::PropagateCopies => "propagate-copies",
+			Pass::EliminateCommonSubexpressions => "eliminate-cse",
+			Pass::EliminateDeadCode => "eliminate-dead-code",
+		};
+		write!(f, "{}", name)
+	}
+}
+
+impl Default for PassManager {
+	/** Runs every pass */
+	fn default() -> Self {
+		PassManager {
+			passes: Pass::ALL.to_vec(),
+		}
+	}
+}
+
+impl PassManager {
+	/** Turns off a pass */
+	pub fn without(mut self, pass: Pass) -> Self {
+		self.passes.retain(|p| *p != pass);
+		self
+	}
+
+	/** Runs the passes on a function in SSA form, until nothing changes */
+	pub fn run_function(&self, function: &mut IrFunction) {
+		for _ in 0..MAX_ROUNDS {
+			let mut changed = false;
+			for pass in &self.passes {
+				changed |= pass.run(function);
+			}
+			if !changed {
+				break;
+			}
+		}
+	}
+
+	/** Converts a module to SSA form and runs the passes on it */
+	pub fn run(&self, module: &mut Module) {
+		for function in &mut module.functions {
+			ssa::construct(function);
+			self.run_function(function);
+		}
+	}
+}
+
+fn is_number(value: &Value, number: i64) -> bool {
+	match value {
+		Value::Int(int) => *int == number,
+		Value::Float(float) => *float == number as f64,
+		_ => false,
+	}
+}
+
+fn is_numeric(t: Type) -> bool {
+	t == Type::Int || t == Type::Float
+}
+
+/**
+ * Replaces operations on constants with their results, and operations which
+ * don't do anything, like `x * 1`, with their operand
+ * Sums like `(x + 1) - 1` are regrouped so that their constants meet
+ */
+pub fn fold_constants(function: &mut IrFunction) -> bool {
+	let mut sums = HashMap::new();
+	for block in &function.blocks {
+		for instruction in &block.instructions {
+			if let Instruction::Operate(
+				register,
+				left,
+				op @ (Operation::Add | Operation::Sub),
+				Operand::Constant(right),
+			) = instruction
+			{
+				sums.insert(
+					*register,
+					(left.clone(), op.clone(), right.clone()),
+				);
+			}
+		}
+	}
+
+	let mut changed = false;
+	for i in 0..function.blocks.len() {
+		for j in 0..function.blocks[i].instructions.len() {
+			let instruction = &function.blocks[i].instructions[j];
+			if let Some(folded) = fold(function, &sums, instruction) {
+				function.blocks[i].instructions[j] = folded;
+				changed = true;
+			}
+		}
+
+		// branches on a constant always go the same way
+		let block = &mut function.blocks[i];
+		if let Terminator::Branch(Operand::Constant(Value::Bool(b)), yes, no) =
+			block.terminator
+		{
+			block.terminator = Terminator::Jump(if b { yes } else { no });
+			changed = true;
+		}
+	}
+	changed
+}
+
+/** Folds a single instruction, if it can be */
+fn fold(
+	function: &IrFunction,
+	sums: &HashMap<Register, (Operand, Operation, Value)>,
+	instruction: &Instruction,
+) -> Option<Instruction> {
+	let (register, left, op, right) = match instruction {
+		Instruction::Operate(register, left, op, right) => {
+			(*register, left, op, right)
+		}
+		_ => return None,
+	};
+
+	if let (Operand::Constant(left), Operand::Constant(right)) = (left, right) {
+		let value = operate(left.clone(), op, right.clone()).ok()?;
+		return Some(Instruction::Move(register, Operand::Constant(value)));
+	}
+
+	// the operand is only kept if the operation wouldn't change its type
+	let result = function.registers[register.0].value_type;
+	let keeps = |operand: &Operand| {
+		let t = function.type_of(operand);
+		is_numeric(t) && t == result
+	};
+	let (identity, commutes) = match op {
+		Operation::Add => (0, true),
+		Operation::Sub => (0, false),
+		Operation::Mult => (1, true),
+		Operation::Div => (1, false),
+		Operation::Mod => return None,
+	};
+	match (left, right) {
+		(operand, Operand::Constant(c))
+			if is_number(c, identity) && keeps(operand) =>
+		{
+			return Some(Instruction::Move(register, operand.clone()))
+		}
+		(Operand::Constant(c), operand)
+			if commutes && is_number(c, identity) && keeps(operand) =>
+		{
+			return Some(Instruction::Move(register, operand.clone()))
+		}
+		_ => (),
+	}
+
+	// (x + a) - b is x + (a - b)
+	let (inner, outer) = match (left, op, right) {
+		(
+			Operand::Register(inner),
+			Operation::Add | Operation::Sub,
+			Operand::Constant(outer),
+		) => (inner, outer),
+		_ => return None,
+	};
+	let (x, inner_op, inner_constant) = sums.get(inner)?;
+	let numeric = |value: &Value| is_numeric(Type::of_value(value));
+	if !is_numeric(function.type_of(x))
+		|| !numeric(inner_constant)
+		|| !numeric(outer)
+	{
+		return None;
+	}
+	let constant = match inner_op {
+		Operation::Add => inner_constant.clone(),
+		_ => operate(Value::Int(0), &Operation::Sub, inner_constant.clone())
+			.ok()?,
+	};
+	let constant = operate(constant, op, outer.clone()).ok()?;
+	Some(Instruction::Operate(
+		register,
+		x.clone(),
+		Operation::Add,
+		Operand::Constant(constant),
+	))
+}
+
+/** Replaces every read of a register */
+fn replace(
+	function: &mut IrFunction,
+	replacements: &HashMap<Register, Operand>,
+) {
+	let replace = |operand: &mut Operand| {
+		if let Operand::Register(register) = operand {
+			if let Some(new) = replacements.get(register) {
+				*operand = new.clone();
+			}
+		}
+	};
+	for block in &mut function.blocks {
+		for instruction in &mut block.instructions {
+			instruction.operands_mut().into_iter().for_each(replace);
+		}
+		if let Some(operand) = block.terminator.operand_mut() {
+			replace(operand);
+		}
+	}
+}
+
+/**
+ * Reads values directly instead of through the registers they're copied
+ * into, including phis which only ever get one value
+ */
+pub fn propagate_copies(function: &mut IrFunction) -> bool {
+	let mut copies: HashMap<Register, Operand> = HashMap::new();
+	for block in &function.blocks {
+		for instruction in &block.instructions {
+			let (register, value) = match instruction {
+				Instruction::Move(register, value) => (*register, value),
+				Instruction::Phi(register, values) => {
+					// a phi which reads itself still only has one value
+					let mut values = values
+						.iter()
+						.map(|v| &v.1)
+						.filter(|v| **v != Operand::Register(*register));
+					let first = match values.next() {
+						Some(first) => first,
+						None => continue,
+					};
+					if !values.all(|v| v == first) {
+						continue;
+					}
+					(*register, first)
+				}
+				_ => continue,
+			};
+			if *value != Operand::Register(register) {
+				copies.insert(register, value.clone());
+			}
+		}
+	}
+
+	// follow chains of copies to the original value
+	let registers: Vec<Register> = copies.keys().copied().collect();
+	for register in registers {
+		let mut value = copies[&register].clone();
+		let mut steps = 0;
+		while let Operand::Register(next) = value {
+			match copies.get(&next) {
+				Some(next) if steps < copies.len() => value = next.clone(),
+				_ => break,
+			}
+			steps += 1;
+		}
+		copies.insert(register, value);
+	}
+
+	let read = |function: &IrFunction| {
+		function
+			.blocks
+			.iter()
+			.flat_map(|b| {
+				b.instructions
+					.iter()
+					.flat_map(|i| i.operands())
+					.chain(b.terminator.operand())
+			})
+			.any(
+				|o| matches!(o, Operand::Register(r) if copies.contains_key(r)),
+			)
+	};
+	if !read(function) {
+		return false;
+	}
+	replace(function, &copies);
+	true
+}
+
+/**
+ * Reuses the results of operations which have already been worked out in a
+ * block which dominates them
+ */
+pub fn eliminate_common_subexpressions(function: &mut IrFunction) -> bool {
+	let tree = ssa::dominator_tree(&ssa::immediate_dominators(function));
+	let mut available = Vec::new();
+	let mut changed = false;
+	eliminate_in(function, &tree, BlockId(0), &mut available, &mut changed);
+	changed
+}
+
+// the operations worked out so far, in the blocks above in the tree
+type Available = Vec<(Operand, Operation, Operand, Register)>;
+
+fn eliminate_in(
+	function: &mut IrFunction,
+	tree: &[Vec<BlockId>],
+	block: BlockId,
+	available: &mut Available,
+	changed: &mut bool,
+) {
+	let depth = available.len();
+	for instruction in &mut function.blocks[block.0].instructions {
+		let (register, left, op, right) = match instruction {
+			Instruction::Operate(register, left, op, right) => {
+				(register, left, op, right)
+			}
+			_ => continue,
+		};
+
+		// the order doesn't matter for sums and products
+		let commutes = matches!(op, Operation::Add | Operation::Mult);
+		let found = available.iter().find(|(l, o, r, _)| {
+			o == op
+				&& ((l == left && r == right)
+					|| (commutes && l == right && r == left))
+		});
+		match found {
+			Some((.., earlier)) => {
+				*instruction =
+					Instruction::Move(*register, Operand::Register(*earlier));
+				*changed = true;
+			}
+			None => available.push((
+				left.clone(),
+				op.clone(),
+				right.clone(),
+				*register,
+			)),
+		}
+	}
+
+	for child in &tree[block.0] {
+		eliminate_in(function, tree, *child, available, changed);
+	}
+	available.truncate(depth);
+}
+
+/** Checks if an instruction could stop the program, like dividing by zero */
+fn can_fail(function: &IrFunction, instruction: &Instruction) -> bool {
+	match instruction {
+		Instruction::Move(..) | Instruction::Phi(..) => false,
+		Instruction::Operate(_, left, op, right) => {
+			let types =
+				function.type_of(left).operate(op, function.type_of(right));
+			let divides = matches!(op, Operation::Div | Operation::Mod);
+			let nonzero =
+				matches!(right, Operand::Constant(c) if !is_number(c, 0));
+			types.is_none_or(|t| t == Type::Unknown) || (divides && !nonzero)
+		}
+		Instruction::Call(..) => true,
+	}
+}
+
+/**
+ * Removes instructions whose results are never read, like the void which
+ * an unused `var` starts with
+ * Calls are kept, but their results are dropped, and registers which are no
+ * longer used are removed
+ */
+pub fn eliminate_dead_code(function: &mut IrFunction) -> bool {
+	let mut changed = false;
+	loop {
+		let mut read = vec![false; function.registers.len()];
+		for block in &function.blocks {
+			let operands = block
+				.instructions
+				.iter()
+				.flat_map(|i| i.operands())
+				.chain(block.terminator.operand());
+			for operand in operands {
+				if let Operand::Register(register) = operand {
+					read[register.0] = true;
+				}
+			}
+		}
+
+		let mut removed = false;
+		for i in 0..function.blocks.len() {
+			let instructions =
+				std::mem::take(&mut function.blocks[i].instructions);
+			let mut kept = Vec::new();
+			for mut instruction in instructions {
+				let unread =
+					instruction.destination().is_some_and(|r| !read[r.0]);
+				if unread && !can_fail(function, &instruction) {
+					removed = true;
+					continue;
+				}
+				if let Instruction::Call(register @ Some(_), ..) =
+					&mut instruction
+				{
+					if unread {
+						*register = None;
+						removed = true;
+					}
+				}
+				kept.push(instruction);
+			}
+			function.blocks[i].instructions = kept;
+		}
+
+		if !removed {
+			break;
+		}
+		changed = true;
+	}
+
+	if changed {
+		remove_unused_registers(function);
+	}
+	changed
+}
+
+/** Renumbers the registers, leaving out any which aren't used */
+fn remove_unused_registers(function: &mut IrFunction) {
+	let mut used = vec![false; function.registers.len()];
+	for parameter in &function.parameters {
+		used[parameter.0] = true;
+	}
+	for block in &function.blocks {
+		for instruction in &block.instructions {
+			if let Some(register) = instruction.destination() {
+				used[register.0] = true;
+			}
+		}
+	}
+
+	let mut numbers = HashMap::new();
+	let mut registers = Vec::new();
+	for (i, info) in function.registers.iter().enumerate() {
+		if used[i] {
+			numbers.insert(Register(i), Register(registers.len()));
+			registers.push(info.clone());
+		}
+	}
+	function.registers = registers;
+
+	let renumber = |register: &mut Register| *register = numbers[register];
+	for parameter in &mut function.parameters {
+		renumber(parameter);
+	}
+	let replacements = numbers
+		.iter()
+		.map(|(old, new)| (*old, Operand::Register(*new)))
+		.collect();
+	replace(function, &replacements);
+	for block in &mut function.blocks {
+		for instruction in &mut block.instructions {
+			match instruction {
+				Instruction::Move(register, _)
+				| Instruction::Operate(register, ..)
+				| Instruction::Phi(register, _)
+				| Instruction::Call(Some(register), ..) => renumber(register),
+				Instruction::Call(None, ..) => (),
+			}
+		}
+	}
+}
@@ -0,0 +1,778 @@
+use crate::function::CallList;
+use crate::function::CallType;
+use crate::function::Expression;
+use crate::function::FuncSig;
+use crate::function::Function;
+use crate::function::Operation;
+use crate::program::Program;
+use crate::result::ResultSig;
+use crate::result::RoResult;
+use crate::span::SourceMap;
+use crate::span::Span;
+use crate::symbol::Symbol;
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+/**
+ * A recursive-descent parser, which builds the AST in a single pass over
+ * the tokens
+ * Code which doesn't make sense is skipped over rather than causing a panic,
+ * and is listed in `errors`, along with brackets which don't match
+ * If it knows where the tokens are, it also records where each definition,
+ * statement and closure came from as it goes
+ */
+pub struct Parser<'a> {
+	tokens: &'a [String],
+	token: usize, // the index of the next token
+	lower: bool,  // whether expressions are broken into calls as they're read
+	errors: Vec<(usize, String)>,
+	brackets: Vec<usize>, // the brackets which have been read but not closed
+	spans: &'a [Span],    // the span of each token, if they're known
+	source_map: SourceMap,
+	closures: Vec<Span>, // the headers of the closures in the current statement
+}
+
+impl<'a> Parser<'a> {
+	/** Creates a parser which starts at the first token */
+	pub fn new(tokens: &'a [String]) -> Self {
+		Parser {
+			tokens,
+			token: 0,
+			lower: true,
+			errors: Vec::new(),
+			brackets: Vec::new(),
+			spans: &[],
+			source_map: SourceMap::default(),
+			closures: Vec::new(),
+		}
+	}
+
+	/**
+	 * Creates a parser which records where everything it parses came from,
+	 * given the span of each token
+	 */
+	pub fn with_spans(tokens: &'a [String], spans: &'a [Span]) -> Self {
+		Parser {
+			spans,
+			..Parser::new(tokens)
+		}
+	}
+
+	/**
+	 * Gets where everything which has been parsed came from
+	 * This is empty unless the parser was made with `with_spans`
+	 */
+	pub fn into_source_map(self) -> SourceMap {
+		self.source_map
+	}
+
+	/**
+	 * Keeps expressions as `Set` calls, with the tokens they were written as,
+	 * like `CallType::vec_from_tokens` does
+	 */
+	pub fn without_lowering(mut self) -> Self {
+		self.lower = false;
+		self
+	}
+
+	/**
+	 * Gets the problems found so far, each with the index of the token it's
+	 * about
+	 */
+	pub fn errors(&self) -> &[(usize, String)] {
+		&self.errors
+	}
+
+	/** Gets the span from the token at `start` up to the one at `end` */
+	fn span(&self, start: usize, end: usize) -> Option<Span> {
+		let first = self.spans.get(start)?;
+		let last = self.spans.get(end.saturating_sub(1).max(start))?;
+		Some(first.to(*last))
+	}
+
+	/** Records a problem with the next token */
+	fn error(&mut self, message: String) {
+		self.error_at(self.token, message);
+	}
+
+	/** Records a problem with the token at an index */
+	fn error_at(&mut self, token: usize, message: String) {
+		self.errors.push((token, message));
+	}
+
+	/** Checks if every token has been read */
+	pub fn is_done(&self) -> bool {
+		self.token >= self.tokens.len()
+	}
+
+	/** Gets the next token without reading it */
+	pub fn peek(&self) -> Option<&'a str> {
+		self.peek_nth(0)
+	}
+
+	/** Gets the token `n` tokens after the next one */
+	pub fn peek_nth(&self, n: usize) -> Option<&'a str> {
+		self.tokens.get(self.token + n).map(String::as_str)
+	}
+
+	/**
+	 * Skips over the next token
+	 * Every token is read through here, so this is where brackets are
+	 * matched up
+	 */
+	pub fn skip(&mut self) {
+		match self.peek() {
+			Some("(" | "{") => self.brackets.push(self.token),
+			Some(closer @ (")" | "}")) => {
+				let opener = if closer == ")" { "(" } else { "{" };
+				match self.brackets.pop().map(|i| self.tokens[i].as_str()) {
+					Some(found) if found == opener => (),
+					Some(found) => self.error(format!(
+						"`{}` doesn't match the `{}` before it",
+						closer, found
+					)),
+					None => self.error(format!("unexpected `{}`", closer)),
+				}
+			}
+			_ => (),
+		}
+		self.token = (self.token + 1).min(self.tokens.len());
+	}
+
+	/** Reads the next token if it's `expected` */
+	fn eat(&mut self, expected: &str) -> bool {
+		let found = self.peek() == Some(expected);
+		if found {
+			self.skip();
+		}
+		found
+	}
+
+	/**
+	 * Reads a name, like a variable or a type
+	 * The brackets around a body are never names, so they're left alone
+	 */
+	fn name(&mut self) -> Symbol {
+		match self.peek() {
+			None | Some("{") | Some("}") => Symbol::default(),
+			Some(name) => {
+				self.skip();
+				Symbol::from(name)
+			}
+		}
+	}
+
+	/**
+	 * Skips to the next of the `stops` which isn't inside brackets
+	 * Stops at the end of the body too
+	 */
+	fn skip_to(&mut self, stops: &[&str]) {
+		let mut brackets: usize = 0; // the number of brackets that need to be closed
+		while let Some(token) = self.peek() {
+			if brackets == 0 && (stops.contains(&token) || token == "}") {
+				return;
+			}
+			if token == "(" {
+				brackets += 1;
+			} else if token == ")" {
+				brackets = brackets.saturating_sub(1);
+			}
+			self.skip();
+		}
+	}
+
+	/**
+	 * Reads a definition starting at its `fn` or `result` keyword, without
+	 * parsing it
+	 * Returns the tokens in its signature and the tokens in its body
+	 */
+	pub fn definition(&mut self) -> (&'a [String], &'a [String]) {
+		self.skip();
+		let start = self.token;
+		while self.peek().is_some_and(|t| t != "{") {
+			self.skip();
+		}
+		let signature = &self.tokens[start..self.token];
+
+		self.skip();
+		let start = self.token;
+		let mut brackets: usize = 1; // the number of brackets that need to be closed
+		while let Some(token) = self.peek() {
+			if token == "{" {
+				brackets += 1;
+			} else if token == "}" {
+				brackets -= 1;
+				if brackets == 0 {
+					break;
+				}
+			}
+			self.skip();
+		}
+		let body = &self.tokens[start..self.token];
+		self.skip();
+
+		(signature, body)
+	}
+
+	/**
+	 * Parses a whole program
+	 * Functions which implement a result are moved into it, after the ones
+	 * written inside of it
+	 */
+	pub fn program(&mut self) -> Program {
+		let mut program = Program::default();
+		let mut implementations = Vec::new();
+		let mut spans: Vec<Vec<Span>> = Vec::new(); // each result's functions
+		while let Some(token) = self.peek() {
+			if token == "fn" {
+				let start = self.token;
+				let (function, header) = self.function_with_header();
+				match (function.signature.result, function.signature.name) {
+					(Some(result), _) => {
+						// the name of the result comes after the `=>`
+						let arrow = self.tokens[start..self.token]
+							.iter()
+							.position(|t| t == "=>")
+							.map_or(start, |i| start + i + 1);
+						implementations.push((result, function, arrow, header));
+						continue;
+					}
+					(None, Some(name)) => {
+						if let Some(header) = header {
+							let functions = &mut self.source_map.functions;
+							functions.insert(name.to_string(), header);
+						}
+					}
+					(None, None) => (),
+				}
+				program.functions.push(function);
+			} else if token == "result" {
+				let (result, header, functions) = self.result_with_spans();
+				if let Some(header) = header {
+					let name = result.signature.name.to_string();
+					self.source_map.results.insert(name, header);
+				}
+				program.results.push(result);
+				spans.push(functions);
+			} else if token == ")" || token == "}" {
+				// brackets which aren't opened are reported when they're read
+				self.skip();
+			} else {
+				self.error(format!(
+					"expected `fn` or `result`, found `{}`",
+					token
+				));
+				self.skip();
+			}
+		}
+
+		// if two results have the same name, the first one gets the functions
+		let mut names: HashMap<Symbol, usize> = HashMap::new();
+		for (i, result) in program.results.iter().enumerate() {
+			names.entry(result.signature.name).or_insert(i);
+		}
+		for (result, function, token, header) in implementations {
+			match names.get(&result) {
+				Some(&i) => {
+					program.results[i].functions.push(function);
+					spans[i].extend(header);
+				}
+				None => {
+					let message =
+						format!("there's no result named `{}`", result);
+					self.error_at(token, message);
+					program.functions.push(function);
+				}
+			}
+		}
+
+		for (result, spans) in program.results.iter().zip(spans) {
+			let name = result.signature.name.to_string();
+			self.source_map.implementations.entry(name).or_insert(spans);
+		}
+
+		for bracket in std::mem::take(&mut self.brackets) {
+			let message =
+				format!("this `{}` is never closed", self.tokens[bracket]);
+			self.error_at(bracket, message);
+		}
+		program
+	}
+
+	/** Parses a function, starting at its `fn` */
+	pub fn function(&mut self) -> Function {
+		self.function_with_header().0
+	}
+
+	/** Parses a function, also giving the span of its header if it's known */
+	fn function_with_header(&mut self) -> (Function, Option<Span>) {
+		let start = self.token;
+		self.eat("fn");
+		let (signature, parameter_order) = self.signature();
+		self.skip_to(&["{"]);
+		let header = self.span(start, self.token);
+		let mut calls = CallList::new();
+		if self.eat("{") {
+			let outer = std::mem::take(&mut self.closures);
+			calls = self.body(header);
+			self.closures = outer;
+			self.eat("}");
+		} else {
+			self.error_at(start, String::from("expected a body after `fn`"));
+		}
+
+		// the statement this is in might be making it as a closure
+		self.closures.extend(header);
+		let function = Function {
+			signature,
+			calls,
+			parameter_order,
+		};
+		(function, header)
+	}
+
+	/**
+	 * Parses the statements in the body of a function, recording where each
+	 * of its calls and closures came from if the function's span is known
+	 */
+	fn body(&mut self, function: Option<Span>) -> CallList {
+		let (calls, ranges, closures) = self.statements_and_closures();
+		if let Some(function) = function {
+			let spans = ranges
+				.iter()
+				.map(|range| self.span(range.start, range.end))
+				.map(|span| span.unwrap_or(function))
+				.collect();
+			self.source_map.calls.insert(function, spans);
+			for (index, header) in closures {
+				self.source_map.closures.insert((function, index), header);
+			}
+		}
+		calls
+	}
+
+	/** Parses a result and the functions inside of it, starting at `result` */
+	pub fn result(&mut self) -> RoResult {
+		self.result_with_spans().0
+	}
+
+	/**
+	 * Parses a result, also giving the spans of its header and of the
+	 * headers of the functions inside of it, if they're known
+	 */
+	fn result_with_spans(&mut self) -> (RoResult, Option<Span>, Vec<Span>) {
+		let start = self.token;
+		self.eat("result");
+		if !self.peek().is_some_and(is_name) || self.peek_nth(1) != Some("(") {
+			let message = "expected a name and parameters after `result`";
+			self.error_at(start, String::from(message));
+		}
+		let (signature, parameter_order) = self.result_signature();
+		self.skip_to(&["{"]);
+		let header = self.span(start, self.token);
+
+		let mut functions = Vec::new();
+		let mut spans = Vec::new();
+		if !self.eat("{") {
+			let message = "expected a body after `result`";
+			self.error_at(start, String::from(message));
+			let result = RoResult {
+				signature,
+				functions,
+				parameter_order,
+			};
+			return (result, header, spans);
+		}
+		let mut brackets: usize = 0; // the number of brackets that need to be closed
+		while let Some(token) = self.peek() {
+			if token == "fn" {
+				let (function, span) = self.function_with_header();
+				functions.push(function);
+				spans.extend(span);
+				continue;
+			} else if token == "{" {
+				brackets += 1;
+			} else if token == "}" {
+				if brackets == 0 {
+					break;
+				}
+				brackets -= 1;
+			}
+			self.skip();
+		}
+		self.eat("}");
+
+		let result = RoResult {
+			signature,
+			functions,
+			parameter_order,
+		};
+		(result, header, spans)
+	}
+
+	/**
+	 * Parses the signature of a function, up to its body
+	 * Also gives the names of the parameters in the order they're written
+	 */
+	pub fn signature(&mut self) -> (FuncSig, Vec<Symbol>) {
+		let mut signature = FuncSig::default();
+		let mut order = Vec::new();
+
+		if self
+			.peek()
+			.is_some_and(|t| !["(", ":", "=>", "{"].contains(&t))
+		{
+			signature.name = Some(self.name());
+		}
+		if self.eat("(") {
+			let (parameters, names) = self.parameters();
+			signature.parameters = Some(parameters);
+			order = names;
+		}
+		if self.eat(":") {
+			signature.return_type = Some(self.name());
+		}
+		if self.eat("=>") {
+			signature.result = Some(self.name());
+		}
+
+		(signature, order)
+	}
+
+	/**
+	 * Parses the signature of a result, up to its body
+	 * Also gives the names of the parameters in the order they're written
+	 */
+	pub fn result_signature(&mut self) -> (ResultSig, Vec<Symbol>) {
+		let mut signature = ResultSig {
+			name: self.name(),
+			..Default::default()
+		};
+		let mut order = Vec::new();
+
+		if self.eat("(") {
+			let (parameters, names) = self.parameters();
+			signature.parameters = parameters;
+			order = names;
+		}
+		if self.eat(":") {
+			signature.return_type = Some(self.name());
+		}
+
+		(signature, order)
+	}
+
+	/**
+	 * Parses parameters up to the closing bracket, like `one: int, two = b`
+	 * The value after the `:` or `=` is a type or a result's parameter
+	 * A body's brackets end the parameters too, even if they aren't closed
+	 */
+	fn parameters(&mut self) -> (HashMap<Symbol, Symbol>, Vec<Symbol>) {
+		let mut parameters = HashMap::new();
+		let mut order = Vec::new();
+		while let Some(token) = self.peek() {
+			if token == ")" || token == "{" || token == "}" {
+				break;
+			} else if token == "," {
+				self.skip();
+				continue;
+			} else if !is_name(token) {
+				// anything else would never be read
+				self.error(format!("expected a parameter, found `{}`", token));
+				self.skip();
+				continue;
+			}
+
+			let name = self.name();
+			if self.eat(":") || self.eat("=") {
+				parameters.insert(name, self.name());
+				order.push(name);
+			}
+		}
+		self.eat(")");
+		(parameters, order)
+	}
+
+	/**
+	 * Parses statements up to the end of the body they're in
+	 * The closing bracket of the body isn't read
+	 */
+	pub fn statements(&mut self) -> CallList {
+		self.statements_with_ranges().0
+	}
+
+	/**
+	 * Parses statements like `statements`, also giving the range of tokens
+	 * that each call was parsed from, which is its whole statement
+	 */
+	pub fn statements_with_ranges(&mut self) -> (CallList, Vec<Range<usize>>) {
+		let (calls, ranges, _) = self.statements_and_closures();
+		(calls, ranges)
+	}
+
+	/**
+	 * Parses statements like `statements_with_ranges`, also giving the
+	 * header of the closure made by each closure call, if it's known
+	 */
+	fn statements_and_closures(
+		&mut self,
+	) -> (CallList, Vec<Range<usize>>, Vec<(usize, Span)>) {
+		let mut calls = CallList::new();
+		let mut ranges = Vec::new();
+		let mut closures = Vec::new();
+		let mut brackets: usize = 0; // the number of brackets that need to be closed
+		while let Some(token) = self.peek() {
+			if token == "{" {
+				brackets += 1;
+			} else if token == "}" {
+				if brackets == 0 {
+					break;
+				}
+				brackets -= 1;
+			} else {
+				let start = self.token;
+				let first = calls.len();
+				self.closures.clear();
+				self.statement(&mut calls);
+				ranges.resize(calls.len(), start..self.token);
+
+				// closures are made in the order they're written
+				let mut headers =
+					std::mem::take(&mut self.closures).into_iter();
+				for (index, call) in calls.iter().enumerate().skip(first) {
+					if let CallType::Closure(..) = call {
+						closures.extend(headers.next().map(|h| (index, h)));
+					}
+				}
+				continue;
+			}
+			self.skip();
+		}
+		(calls, ranges, closures)
+	}
+
+	/** Parses a single statement, adding its calls to the list */
+	fn statement(&mut self, calls: &mut CallList) {
+		let token = self.peek().unwrap_or_default();
+		let next = self.peek_nth(1);
+		if token == "ret" || token == "return" {
+			self.skip();
+			if self.peek().is_none_or(|t| t == "}") {
+				// a bare return doesn't return anything
+				calls.push(CallType::Return(Symbol::from("void")));
+				return;
+			}
+
+			// anything more complicated is stored before it is returned
+			let start = self.token;
+			let expression = self.expression();
+			match &self.tokens[start..self.token] {
+				[value] => calls.push(CallType::Return(Symbol::from(value))),
+				tokens => {
+					let var = Symbol::from("return");
+					self.store(var, expression, tokens, calls);
+					calls.push(CallType::Return(var));
+				}
+			}
+		} else if token == "var" {
+			self.skip();
+			if !self.peek().is_some_and(is_name) {
+				self.error(String::from("expected a name after `var`"));
+				if self.eat("=") {
+					self.expression();
+				}
+				return;
+			}
+			let var = self.name();
+			calls.push(CallType::Init(var));
+
+			// skip over the type of the variable
+			if self.peek() == Some(":") && self.peek_nth(1).is_some() {
+				self.skip();
+				self.skip();
+			}
+
+			if self.eat("=") {
+				self.set(var, calls);
+			}
+		} else if token == "fn" {
+			// a local function is stored in a variable with its name
+			let function = self.function();
+			if let Some(name) = function.signature.name {
+				calls.push(CallType::Closure(name, function));
+			}
+		} else if next == Some("=") {
+			let var = self.name();
+			self.skip();
+			self.set(var, calls);
+		} else if next == Some("(") {
+			self.call(calls);
+		} else if token == ")" {
+			// this is reported when it's read, if it isn't opened
+			self.skip();
+		} else {
+			// anything else is left over from the statement before
+			self.error(format!("unexpected `{}`", token));
+			self.skip();
+		}
+	}
+
+	/** Parses an expression and stores it in `var` */
+	fn set(&mut self, var: Symbol, calls: &mut CallList) {
+		let start = self.token;
+		let expression = self.expression();
+		let tokens = &self.tokens[start..self.token];
+		self.store(var, expression, tokens, calls);
+	}
+
+	/**
+	 * Stores an expression in a variable
+	 * Temporary variables are named after the variable
+	 */
+	fn store(
+		&self,
+		var: Symbol,
+		expression: Expression,
+		tokens: &[String],
+		calls: &mut CallList,
+	) {
+		if self.lower {
+			expression.lower(var, &var, &mut 0, calls);
+		} else {
+			calls.push(CallType::Set(var, tokens.to_vec()));
+		}
+	}
+
+	/**
+	 * Parses a call whose value isn't used, like `print(x)`
+	 * Arguments which aren't a single value are stored in temporaries first
+	 */
+	fn call(&mut self, calls: &mut CallList) {
+		let func_name = self.name();
+		self.skip();
+
+		let mut parameters = Vec::new();
+		let mut i = 0;
+		while let Some(token) = self.peek() {
+			if token == ")" {
+				self.skip();
+				break;
+			} else if token == "}" {
+				break;
+			} else if token == "," {
+				// an empty argument, which is skipped
+				self.skip();
+				i += 1;
+				continue;
+			}
+
+			let start = self.token;
+			let expression = self.expression();
+			self.skip_to(&[",", ")"]);
+			match &self.tokens[start..self.token] {
+				[value] => parameters.push(Symbol::from(value)),
+				tokens => {
+					let temp = Symbol::from(format!("{}({})", func_name, i));
+					self.store(temp, expression, tokens, calls);
+					parameters.push(temp);
+				}
+			}
+			self.eat(",");
+			i += 1;
+		}
+
+		calls.push(CallType::Call(func_name, parameters));
+	}
+
+	/** Parses an expression, starting with a sum, which binds the loosest */
+	pub(crate) fn expression(&mut self) -> Expression {
+		let mut expression = self.term();
+		while let Some(op @ ("+" | "-")) = self.peek() {
+			let op = Operation::from_str(String::from(op)).unwrap();
+			self.skip();
+			let right = self.term();
+			expression =
+				Expression::Operate(Box::new(expression), op, Box::new(right));
+		}
+		expression
+	}
+
+	/** Parses a product */
+	fn term(&mut self) -> Expression {
+		let mut expression = self.factor();
+		while let Some(op @ ("*" | "/" | "%")) = self.peek() {
+			let op = Operation::from_str(String::from(op)).unwrap();
+			self.skip();
+			let right = self.factor();
+			expression =
+				Expression::Operate(Box::new(expression), op, Box::new(right));
+		}
+		expression
+	}
+
+	/**
+	 * Parses a single value, call, negation, bracketed expression or
+	 * closure
+	 * A missing value, like in `f(, x)`, is void
+	 */
+	fn factor(&mut self) -> Expression {
+		let value = match self.peek() {
+			None | Some("{" | "}" | "," | ")") => {
+				return Expression::Value(Symbol::from("void"))
+			}
+			Some("fn") => return Expression::Closure(self.function()),
+			Some(value) => value,
+		};
+		self.skip();
+
+		if value == "-" {
+			let value = self.factor();
+			Expression::Operate(
+				Box::new(Expression::Value(Symbol::from("0"))),
+				Operation::Sub,
+				Box::new(value),
+			)
+		} else if value == "(" {
+			let expression = self.expression();
+			self.skip_to(&[")"]);
+			self.eat(")");
+			expression
+		} else if self.eat("(") {
+			Expression::Call(Symbol::from(value), self.arguments())
+		} else {
+			Expression::Value(Symbol::from(value))
+		}
+	}
+
+	/**
+	 * Parses the arguments of a call inside of an expression, up to the
+	 * closing bracket
+	 */
+	fn arguments(&mut self) -> Vec<Expression> {
+		let mut arguments = Vec::new();
+		while let Some(token) = self.peek() {
+			if token == ")" {
+				self.skip();
+				break;
+			} else if token == "}" {
+				break;
+			}
+
+			arguments.push(self.expression());
+			self.skip_to(&[",", ")"]);
+			self.eat(",");
+		}
+		arguments
+	}
+}
+
+/** Checks if a token can be the name of a variable */
+fn is_name(token: &str) -> bool {
+	let keywords = ["fn", "result", "var", "return", "ret", "true", "false"];
+	token
+		.chars()
+		.next()
+		.is_some_and(|c| c.is_alphabetic() || c == '_')
+		&& !keywords.contains(&token)
+}
@@ -1,9 +1,15 @@
 use crate::function::FuncParser;
 use crate::function::Function;
+use crate::parser::Parser;
 use crate::result::ResultParser;
 use crate::result::RoResult;
+use crate::symbol::Symbol;
 use crate::tokenizer::TokenList;
 
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct ProgramParser {
 	pub results: Vec<ResultParser>,
@@ -11,136 +17,101 @@ pub struct ProgramParser {
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Program {
 	pub results: Vec<RoResult>,
 	pub functions: Vec<Function>,
 }
 
 impl ProgramParser {
+	/** Splits the tokens into functions and results */
 	pub fn from_tokens(tokens: TokenList) -> ProgramParser {
 		let mut program_parser = ProgramParser::default();
-
-		let mut token = 0;
-		while token < tokens.len() {
-			if tokens[token] == "fn" {
-				let mut signature = TokenList::new();
-				token += 1;
-				while tokens[token] != "{" {
-					signature.push(tokens[token].clone());
-					token += 1;
-				}
-
-				let mut code = TokenList::new();
-				let mut brackets: usize = 1; // the number of brackets that need to be closed
-				loop {
-					token += 1;
-					if tokens[token] == "{" {
-						brackets += 1;
-					}
-					if tokens[token] == "}" {
-						brackets -= 1;
-					}
-					if brackets == 0 {
-						break;
-					}
-					code.push(tokens[token].clone());
-				}
-
-				program_parser
-					.functions
-					.push(FuncParser { signature, code })
-			}
-
-			if token > tokens.len() {
-				break;
-			} // prevents an error here
-
-			if tokens[token] == "result" {
-				let mut signature = TokenList::new();
-				token += 1;
-				while tokens[token] != "{" {
-					signature.push(tokens[token].clone());
-					token += 1;
-				}
-
-				let mut code = TokenList::new();
-				let mut brackets: usize = 1; // the number of brackets that need to be closed
-				loop {
-					token += 1;
-					if tokens[token] == "{" {
-						brackets += 1;
-					}
-					if tokens[token] == "}" {
-						brackets -= 1;
-					}
-					if brackets == 0 {
-						break;
-					}
-					code.push(tokens[token].clone());
-				}
-
-				let functions = FuncParser::vec_from_tokens(code);
+		let mut parser = Parser::new(&tokens);
+		while let Some(token) = parser.peek() {
+			if token == "fn" {
+				let (signature, code) = parser.definition();
+				program_parser.functions.push(FuncParser {
+					signature: signature.to_vec(),
+					code: code.to_vec(),
+				});
+			} else if token == "result" {
+				let (signature, code) = parser.definition();
 				program_parser.results.push(ResultParser {
-					signature,
-					functions,
+					signature: signature.to_vec(),
+					functions: FuncParser::vec_from_tokens(code.to_vec()),
 				});
+			} else {
+				parser.skip();
 			}
-
-			token += 1;
 		}
 		program_parser
 	}
 
-	/** Yes, this function is too complicated. I'm sorry
-	 * This moves functions into their respective results
-	 */
+	/** Moves functions into their respective results */
 	pub fn move_funcs(&mut self) {
-		for i in 0..self.functions.len() {
-			let functions: &mut Vec<FuncParser> = self.functions.as_mut();
-			let results: &mut Vec<ResultParser> = self.results.as_mut();
-			let func = functions.get(i).unwrap().clone();
-			if let Some(r) = func.parse_signature().result {
-				functions.remove(i);
-				let results0 = results.clone();
-				results
-					.get_mut(
-						results0
-							.clone()
-							.iter()
-							.position(|x| x.parse_signature().name == r)
-							.unwrap(),
-					)
-					.unwrap()
-					.functions
-					.push(func.clone());
+		// if two results have the same name, the first one gets the functions
+		let mut names: HashMap<Symbol, usize> = HashMap::new();
+		for (i, result) in self.results.iter().enumerate() {
+			names.entry(result.name()).or_insert(i);
+		}
+
+		// functions for results which don't exist stay where they are, like
+		// in `Parser::program`, and `check` reports them
+		for func in mem::take(&mut self.functions) {
+			let result = func.parse_signature().result;
+			match result.and_then(|r| names.get(&r)) {
+				Some(&i) => self.results[i].functions.push(func),
+				None => self.functions.push(func),
 			}
 		}
 	}
 
-	/** Creates a list of functions */
-	pub fn parse_funcs(&self) -> Vec<Function> {
-		let mut funcs = Vec::with_capacity(self.functions.len());
-		for func in self.functions.clone() {
-			funcs.push(func.parse());
-		}
-		funcs
+	/**
+	 * Creates a list of functions
+	 * The function parsers are moved out, so this can only be done once
+	 */
+	pub fn parse_funcs(&mut self) -> Vec<Function> {
+		mem::take(&mut self.functions)
+			.into_iter()
+			.map(FuncParser::parse)
+			.collect()
 	}
 
-	/** Creates a list of results */
+	/**
+	 * Creates a list of results
+	 * The result parsers are moved out, so this can only be done once
+	 */
 	pub fn parse_results(&mut self) -> Vec<RoResult> {
 		self.move_funcs();
-		let mut results = Vec::with_capacity(self.results.len());
-		for result in self.results.clone() {
-			results.push(result.parse());
-		}
-		results
+		mem::take(&mut self.results)
+			.into_iter()
+			.map(ResultParser::parse)
+			.collect()
 	}
 
-	/** Creates a Program */
+	/** Creates a Program, leaving the parser empty */
 	pub fn parse(&mut self) -> Program {
+		// the results need to take their functions first
+		let results = self.parse_results();
 		Program {
 			functions: self.parse_funcs(),
-			results: self.parse_results(),
+			results,
+		}
+	}
+}
+
+impl fmt::Display for Program {
+	/** Writes the results, and then the functions, as Ro code */
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let results = self.results.iter().map(|r| r as &dyn fmt::Display);
+		let functions = self.functions.iter().map(|f| f as &dyn fmt::Display);
+		for (i, definition) in results.chain(functions).enumerate() {
+			if i > 0 {
+				writeln!(f)?;
+			}
+			writeln!(f, "{}", definition)?;
 		}
+		Ok(())
 	}
 }
@@ -0,0 +1,166 @@
+use crate::analysis::Analysis;
+use crate::analysis::Definition;
+use crate::check;
+use crate::cst;
+use crate::cst::SyntaxKind;
+use crate::diagnostic::Diagnostic;
+use crate::interp::Value;
+use crate::span::Span;
+use crate::span::TextEdit;
+
+use std::collections::HashMap;
+
+/*
+ * Refactorings which change the code without changing what it means
+ *
+ * A reference is a name which refers to the same definition as another, so
+ * the name in a declaration is one of its references. Renaming only
+ * replaces the names themselves, so everything around them stays the same.
+ * A rename is checked by analysing the renamed code, and is refused if any
+ * name would refer to something different, or if the renamed code has
+ * errors which the code didn't.
+ */
+
+/** Checks if two definitions are the same one */
+fn same(one: &Definition, two: &Definition) -> bool {
+	one.kind == two.kind && one.span == two.span && one.name == two.name
+}
+
+/**
+ * Finds every name which refers to the same thing as the name at an offset,
+ * in order, including the one in its declaration
+ */
+pub fn references(analysis: &Analysis, offset: usize) -> Vec<Span> {
+	let definition = match analysis.definition(offset) {
+		Some(definition) => definition,
+		None => return Vec::new(),
+	};
+
+	analysis
+		.bindings()
+		.filter(|(_, d)| d.is_some_and(|d| same(d, &definition)))
+		.map(|(name, _)| name.span)
+		.collect()
+}
+
+/**
+ * Renames the thing named at an offset, and everything which refers to it
+ * Returns the edits to make, in order, or an error explaining why it can't
+ * be renamed
+ */
+pub fn rename(
+	analysis: &Analysis,
+	offset: usize,
+	name: &str,
+) -> Result<Vec<TextEdit>, Diagnostic> {
+	let token = analysis.name_at(offset).map(|t| t.span);
+	let definition = match analysis.definition(offset) {
+		Some(definition) => definition,
+		None => {
+			let message = String::from("there's nothing here to rename");
+			return Err(Diagnostic::error(message, token));
+		}
+	};
+	if definition.span.is_none() {
+		let message = format!("`{}` is built in", definition.name);
+		return Err(Diagnostic::error(message, token));
+	}
+	if !is_name(name) {
+		let message = format!("`{}` can't be used as a name", name);
+		return Err(Diagnostic::error(message, token));
+	}
+
+	let edits: Vec<TextEdit> = references(analysis, offset)
+		.into_iter()
+		.map(|span| TextEdit::new(span, name))
+		.collect();
+	check(analysis, &edits)?;
+	Ok(edits)
+}
+
+/** Checks if some text is a single name, which isn't a keyword or value */
+fn is_name(text: &str) -> bool {
+	let tokens = cst::lex(text);
+	tokens.len() == 1
+		&& tokens[0].kind == SyntaxKind::Name
+		&& Value::from_literal(&tokens[0].text).is_none()
+}
+
+/**
+ * Checks that every name still refers to the same thing after some edits,
+ * and that the edits don't cause any new errors
+ * The edits need to be in order, and can't overlap
+ */
+fn check(analysis: &Analysis, edits: &[TextEdit]) -> Result<(), Diagnostic> {
+	let mut code = analysis.code.clone();
+	for edit in edits.iter().rev() {
+		code = edit.apply(&code);
+	}
+	let renamed = Analysis::unchecked(&code);
+	let after: HashMap<usize, Option<&Definition>> = renamed
+		.bindings()
+		.map(|(name, definition)| (name.span.start, definition))
+		.collect();
+
+	// how far the code after the end of each edit has moved
+	let mut shift = 0;
+	let shifts: Vec<(usize, isize)> = edits
+		.iter()
+		.map(|edit| {
+			let removed = edit.span.end - edit.span.start;
+			shift += edit.text.len() as isize - removed as isize;
+			(edit.span.end, shift)
+		})
+		.collect();
+
+	// moves an offset in the old code to where it is in the new code
+	let moved = |offset: usize| -> usize {
+		match shifts.partition_point(|(end, _)| *end <= offset) {
+			0 => offset,
+			edited => (offset as isize + shifts[edited - 1].1) as usize,
+		}
+	};
+
+	for (name, before) in analysis.bindings() {
+		let after = after.get(&moved(name.span.start)).copied().flatten();
+		let unchanged = match (before, after) {
+			(Some(before), Some(after)) => {
+				let span = before
+					.span
+					.map(|s| Span::new(moved(s.start), moved(s.end)));
+				before.kind == after.kind && span == after.span
+			}
+			(None, None) => true,
+			_ => false,
+		};
+		if !unchanged {
+			return Err(Diagnostic::error(
+				format!(
+					"the rename would change what `{}` refers to",
+					name.text
+				),
+				Some(name.span),
+			));
+		}
+	}
+
+	// a name can refer to the same thing and still be defined twice, like
+	// when a function is renamed to `main` before the real one
+	let before = check::check(&analysis.code);
+	let errors = check::check(&code);
+	if errors.len() > before.len() {
+		let new = errors
+			.iter()
+			.find(|e| {
+				before
+					.iter()
+					.all(|b| b.primary.message != e.primary.message)
+			})
+			.unwrap_or(&errors[0]);
+		return Err(Diagnostic::error(
+			format!("the rename would cause an error: {}", new.primary.message),
+			edits.first().map(|edit| edit.span),
+		));
+	}
+	Ok(())
+}
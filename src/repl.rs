@@ -0,0 +1,173 @@
+use crate::check;
+use crate::function::is_expression;
+use crate::function::FuncSig;
+use crate::interp::Interpreter;
+use crate::interp::Value;
+use crate::interp::Variables;
+use crate::parser::Parser;
+use crate::program::Program;
+use crate::tokenizer::tokenize;
+use crate::tokenizer::TokenList;
+
+use std::io;
+use std::io::Write;
+
+// the name used for the REPL's input in error messages
+pub const REPL_FILE: &str = "<repl>";
+
+#[derive(Clone, Debug, Default)]
+pub struct Repl {
+	pub definitions: Vec<String>, // the code for each fn and result so far
+	pub variables: Variables,
+	buffer: String, // input which hasn't been evaluated yet
+}
+
+impl Repl {
+	pub fn new() -> Self {
+		Repl::default()
+	}
+
+	/** Checks if the input so far has brackets which haven't been closed */
+	pub fn needs_more(&self) -> bool {
+		let tokens = tokenize(self.buffer.clone());
+		let opened = tokens.iter().filter(|t| *t == "{").count();
+		let closed = tokens.iter().filter(|t| *t == "}").count();
+		opened > closed
+	}
+
+	/**
+	 * Adds a line of input
+	 * The input is evaluated once all of its brackets have been closed
+	 */
+	pub fn feed<W: Write>(
+		&mut self,
+		line: &str,
+		output: &mut W,
+	) -> io::Result<()> {
+		self.buffer.push_str(line);
+		self.buffer.push('\n');
+		if self.needs_more() {
+			return Ok(());
+		}
+
+		let input = std::mem::take(&mut self.buffer);
+		self.eval(&input, output)
+	}
+
+	/** Evaluates a definition or some statements */
+	pub fn eval<W: Write>(
+		&mut self,
+		input: &str,
+		output: &mut W,
+	) -> io::Result<()> {
+		let tokens = tokenize(String::from(input));
+		if tokens.is_empty() {
+			Ok(())
+		} else if tokens[0] == "fn" || tokens[0] == "result" {
+			self.define(input, &tokens, output)
+		} else {
+			self.run(tokens, output)
+		}
+	}
+
+	/** The code for all of the definitions so far */
+	pub fn source(&self) -> String {
+		self.definitions.join("\n")
+	}
+
+	/** Parses all of the definitions so far */
+	pub fn program(&self) -> Option<Program> {
+		check::parse(&self.source()).ok()
+	}
+
+	/**
+	 * Adds a definition, replacing any earlier definition with the same name
+	 * It isn't added if the definitions have any problems with it, which
+	 * are printed instead
+	 */
+	fn define<W: Write>(
+		&mut self,
+		input: &str,
+		tokens: &[String],
+		output: &mut W,
+	) -> io::Result<()> {
+		let name = definition_name(tokens);
+		let mut definitions: Vec<String> = self
+			.definitions
+			.iter()
+			.filter(|d| {
+				name.is_none()
+					|| definition_name(&tokenize((*d).clone())) != name
+			})
+			.cloned()
+			.collect();
+		definitions.push(String::from(input.trim_end()));
+
+		let source = definitions.join("\n");
+		let diagnostics = check::check(&source);
+		if diagnostics.is_empty() {
+			self.definitions = definitions;
+		}
+		for diagnostic in diagnostics {
+			write!(output, "{}", diagnostic.render(REPL_FILE, &source))?;
+		}
+		Ok(())
+	}
+
+	/**
+	 * Runs some statements, keeping any variables they create
+	 * If the input is a single expression, its value is printed
+	 */
+	fn run<W: Write>(
+		&mut self,
+		mut tokens: TokenList,
+		output: &mut W,
+	) -> io::Result<()> {
+		let program = match self.program() {
+			Some(program) => program,
+			None => {
+				return writeln!(
+					output,
+					"error: couldn't parse the definitions"
+				)
+			}
+		};
+
+		if is_expression(&tokens) {
+			tokens.insert(0, String::from("return"));
+		}
+		let calls = Parser::new(&tokens).statements();
+
+		let source = self.source();
+		let mut interpreter =
+			Interpreter::with_source(&program, &source, &mut *output);
+		let value = interpreter.execute(&calls, &mut self.variables);
+		match value {
+			Ok(Value::Void) => Ok(()),
+			Ok(value) => writeln!(output, "{}", value),
+			Err(error) => {
+				write!(output, "{}", error.render(REPL_FILE, &source))
+			}
+		}
+	}
+}
+
+/**
+ * Gets the name of a free function or result that some tokens define, along
+ * with whether it's a result
+ * Functions which implement a result don't replace anything
+ */
+fn definition_name(tokens: &[String]) -> Option<(bool, String)> {
+	let end = tokens.iter().position(|t| t == "{").unwrap_or(tokens.len());
+	if tokens.len() > 1 && tokens[0] == "result" {
+		Some((true, tokens[1].clone()))
+	} else if !tokens.is_empty() && tokens[0] == "fn" {
+		let signature = FuncSig::from_slice(&tokens[1..end]);
+		match signature.result {
+			Some(_) => None,
+			None => signature.name.map(|name| (false, name.to_string())),
+		}
+	} else {
+		None
+	}
+}
@@ -0,0 +1,443 @@
+use crate::diagnostic::Diagnostic;
+use crate::function::is_temporary;
+use crate::function::CallType;
+use crate::function::Function;
+use crate::interp::Value;
+use crate::interp::BUILTINS;
+use crate::program::Program;
+use crate::span::SourceMap;
+use crate::span::Span;
+use crate::symbol::Symbol;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+// what a called name refers to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Callee {
+	Function(usize),
+	Result(usize),
+	Builtin,
+}
+
+// the function which some calls belong to, for error messages
+#[derive(Clone, Debug, PartialEq)]
+pub struct Owner {
+	pub name: String,
+	pub implementation: Option<usize>,
+	pub span: Option<Span>,
+}
+
+impl Owner {
+	/** Finds the owner for a free function */
+	pub fn function(name: &str, source_map: &SourceMap) -> Self {
+		Owner {
+			name: String::from(name),
+			implementation: None,
+			span: source_map.function(name),
+		}
+	}
+
+	/** Finds the owner for one of a result's implementations */
+	pub fn implementation(
+		result: &str,
+		index: usize,
+		source_map: &SourceMap,
+	) -> Self {
+		Owner {
+			name: String::from(result),
+			implementation: Some(index),
+			span: source_map.implementation(result, index),
+		}
+	}
+
+	/** Creates an error pointing at the owner */
+	pub fn error(&self, message: String) -> Diagnostic {
+		let mut diagnostic = Diagnostic::error(message, self.span);
+		diagnostic.primary.annotation = match self.implementation {
+			Some(index) => {
+				format!("in `{}` (implementation {})", self.name, index)
+			}
+			None => format!("in `{}`", self.name),
+		};
+		diagnostic
+	}
+}
+
+// where a name was declared, in the body that declares it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Declared {
+	Parameter,
+	Variable(usize), // by the index of the call which declares it
+	Closure(usize),  // a local fn, by the index of the call which makes it
+}
+
+// the names declared in the body of a function or closure
+#[derive(Clone, Debug, Default)]
+struct Body {
+	header: Option<Span>,
+	parent: Option<(usize, usize)>, // the body and call which make a closure
+	parameters: Vec<Symbol>,
+	declarations: Vec<(usize, Symbol, Declared)>, // by the call which declares each one
+	closures: HashMap<usize, usize>, // the body of each closure, by its call
+}
+
+/*
+ * The names in scope in every body in a program
+ *
+ * A name can be used in a body if it's a parameter, or was declared by an
+ * earlier call. A closure also sees what its parent saw when it was made,
+ * including itself if it's a local fn. Names are looked up here both when
+ * checking a program and when an editor asks what a name refers to.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Scopes {
+	bodies: Vec<Body>,
+	headers: HashMap<Span, usize>, // the body of each function, by its header
+	functions: Vec<usize>,         // the body of each free function
+	implementations: Vec<Vec<usize>>, // the bodies of each result's functions
+}
+
+impl Scopes {
+	/** Finds the names declared in every body in a program */
+	pub fn new(program: &Program, source_map: &SourceMap) -> Self {
+		let mut scopes = Scopes::default();
+		for function in &program.functions {
+			let name = function.signature.name.as_deref().unwrap_or_default();
+			let header = source_map.function(name);
+			let parameters = function.parameter_order.clone();
+			let body =
+				scopes.add(function, header, parameters, None, source_map);
+			scopes.functions.push(body);
+		}
+
+		for result in &program.results {
+			let name = &result.signature.name;
+			let mut bodies = Vec::new();
+			for (index, function) in result.functions.iter().enumerate() {
+				// parameters either rename the result's parameters or repeat
+				// them
+				let header = source_map.implementation(name, index);
+				let mut parameters = function.parameter_order.clone();
+				parameters.extend(result.parameter_order.iter().copied());
+				bodies.push(
+					scopes.add(function, header, parameters, None, source_map),
+				);
+			}
+			scopes.implementations.push(bodies);
+		}
+		scopes
+	}
+
+	/** Adds the body of a function and the closures in it */
+	fn add(
+		&mut self,
+		function: &Function,
+		header: Option<Span>,
+		parameters: Vec<Symbol>,
+		parent: Option<(usize, usize)>,
+		source_map: &SourceMap,
+	) -> usize {
+		let body = self.bodies.len();
+		self.bodies.push(Body {
+			header,
+			parent,
+			parameters,
+			..Body::default()
+		});
+		if let Some(header) = header {
+			self.headers.insert(header, body);
+		}
+
+		for (index, call) in function.calls.iter().enumerate() {
+			match call {
+				CallType::Init(var) => {
+					let declared = Declared::Variable(index);
+					self.bodies[body]
+						.declarations
+						.push((index, *var, declared));
+				}
+				CallType::Closure(var, closure) => {
+					// a local fn declares the variable it's stored in
+					if closure.signature.name == Some(*var) {
+						let declared = Declared::Closure(index);
+						let declarations = &mut self.bodies[body].declarations;
+						declarations.push((index, *var, declared));
+					}
+					let header =
+						header.and_then(|h| source_map.closure(h, index));
+					let parameters = closure.parameter_order.clone();
+					let parent = Some((body, index));
+					let inner = self
+						.add(closure, header, parameters, parent, source_map);
+					self.bodies[body].closures.insert(index, inner);
+				}
+				_ => (),
+			}
+		}
+		body
+	}
+
+	/** Finds the body of the function or closure with a header */
+	pub fn body(&self, header: Span) -> Option<usize> {
+		self.headers.get(&header).copied()
+	}
+
+	/** Gets the header of the function or closure that a body belongs to */
+	pub fn header(&self, body: usize) -> Option<Span> {
+		self.bodies.get(body)?.header
+	}
+
+	/**
+	 * Gets the names which can be used in a body once some of its calls have
+	 * happened, with the body which declares each one
+	 * The innermost and most recent declarations come first
+	 */
+	pub fn visible(
+		&self,
+		body: usize,
+		calls: usize,
+	) -> impl Iterator<Item = (usize, Symbol, Declared)> + '_ {
+		// a closure sees its parent as it was once it was made
+		let bodies = std::iter::successors(Some((body, calls)), move |b| {
+			let (parent, call) = self.bodies[b.0].parent?;
+			Some((parent, call + 1))
+		});
+		bodies.flat_map(move |(index, calls)| {
+			let body = &self.bodies[index];
+			let declared = body
+				.declarations
+				.iter()
+				.rev()
+				.filter(move |(call, ..)| *call < calls)
+				.map(move |(_, name, declared)| (index, *name, *declared));
+			let parameters = body
+				.parameters
+				.iter()
+				.map(move |name| (index, *name, Declared::Parameter));
+			declared.chain(parameters)
+		})
+	}
+
+	/**
+	 * Finds where a name used in a body was declared, once some of the
+	 * body's calls have happened
+	 */
+	pub fn lookup(
+		&self,
+		body: usize,
+		calls: usize,
+		name: Symbol,
+	) -> Option<(usize, Declared)> {
+		self.visible(body, calls)
+			.find(|(_, visible, _)| *visible == name)
+			.map(|(body, _, declared)| (body, declared))
+	}
+}
+
+/**
+ * Finds what a called name refers to
+ * Free functions come before results, like in the interpreter
+ */
+pub fn callee(program: &Program, name: &str) -> Option<Callee> {
+	if let Some(index) = program
+		.functions
+		.iter()
+		.position(|f| f.signature.name.as_deref() == Some(name))
+	{
+		Some(Callee::Function(index))
+	} else if let Some(index) = program
+		.results
+		.iter()
+		.position(|r| r.signature.name == name)
+	{
+		Some(Callee::Result(index))
+	} else if BUILTINS.contains(&name) {
+		Some(Callee::Builtin)
+	} else {
+		None
+	}
+}
+
+/**
+ * Checks that every name in a program refers to something
+ * Returns a diagnostic for each problem
+ */
+pub fn resolve(program: &Program, source_map: &SourceMap) -> Vec<Diagnostic> {
+	let mut diagnostics = Vec::new();
+	let scopes = Scopes::new(program, source_map);
+
+	// functions and results share a namespace
+	let mut defined: HashMap<&str, usize> = HashMap::new();
+	let names = program
+		.functions
+		.iter()
+		.filter_map(|f| f.signature.name.as_deref())
+		.chain(program.results.iter().map(|r| r.signature.name.as_str()));
+	for name in names {
+		*defined.entry(name).or_default() += 1;
+	}
+	let mut duplicates: Vec<&&str> = defined
+		.iter()
+		.filter(|(_, n)| **n > 1)
+		.map(|(n, _)| n)
+		.collect();
+	duplicates.sort();
+	for name in duplicates {
+		let span = source_map
+			.function(name)
+			.or_else(|| source_map.results.get(*name).copied());
+		diagnostics.push(Diagnostic::error(
+			format!("`{}` is defined more than once", name),
+			span,
+		));
+	}
+
+	for (function, body) in program.functions.iter().zip(&scopes.functions) {
+		let name = function.signature.name.as_deref().unwrap_or_default();
+		let owner = Owner::function(name, source_map);
+		resolve_calls(
+			program,
+			&scopes,
+			*body,
+			&function.calls,
+			HashSet::new(),
+			&owner,
+			&mut diagnostics,
+		);
+	}
+
+	for (result, bodies) in program.results.iter().zip(&scopes.implementations)
+	{
+		let name = &result.signature.name;
+		for (index, function) in result.functions.iter().enumerate() {
+			let owner = Owner::implementation(name, index, source_map);
+			for (parameter, value) in
+				function.signature.parameters.iter().flatten()
+			{
+				let parameters = &result.signature.parameters;
+				if !parameters.contains_key(value)
+					&& !parameters.contains_key(parameter)
+				{
+					diagnostics.push(owner.error(format!(
+						"result `{}` has no parameter `{}`",
+						name, value
+					)));
+				}
+			}
+
+			resolve_calls(
+				program,
+				&scopes,
+				bodies[index],
+				&function.calls,
+				HashSet::new(),
+				&owner,
+				&mut diagnostics,
+			);
+		}
+	}
+
+	diagnostics
+}
+
+/**
+ * Checks the names used by the calls in a body
+ * Names which are set without being declared, or used without being known,
+ * are put in `seen` so they're only reported once
+ */
+fn resolve_calls(
+	program: &Program,
+	scopes: &Scopes,
+	body: usize,
+	calls: &[CallType],
+	mut seen: HashSet<Symbol>,
+	owner: &Owner,
+	diagnostics: &mut Vec<Diagnostic>,
+) {
+	let known = |name: &Symbol, calls: usize, seen: &HashSet<Symbol>| {
+		seen.contains(name) || scopes.lookup(body, calls, *name).is_some()
+	};
+
+	// functions are checked last, but closures in variables are only in
+	// scope after they're defined
+	let mut unknown = Vec::new();
+	for (index, call) in calls.iter().enumerate() {
+		let read = |name: &Symbol,
+		            seen: &mut HashSet<Symbol>,
+		            diagnostics: &mut Vec<Diagnostic>| {
+			if !known(name, index, seen) && Value::from_literal(name).is_none()
+			{
+				diagnostics
+					.push(owner.error(format!("unknown variable `{}`", name)));
+				seen.insert(*name);
+			}
+		};
+
+		if let CallType::Call(name, _) | CallType::SetCall(_, name, _) = call {
+			if !known(name, index, &seen) && callee(program, name).is_none() {
+				unknown.push(name);
+			}
+		}
+
+		let written = match call {
+			CallType::Init(_) => None,
+			CallType::Move(var, value) => {
+				read(value, &mut seen, diagnostics);
+				Some(var)
+			}
+			CallType::Operate(var, left, _, right) => {
+				read(left, &mut seen, diagnostics);
+				read(right, &mut seen, diagnostics);
+				Some(var)
+			}
+			CallType::Call(_, parameters) => {
+				for parameter in parameters {
+					read(parameter, &mut seen, diagnostics);
+				}
+				None
+			}
+			CallType::SetCall(var, _, parameters) => {
+				for parameter in parameters {
+					read(parameter, &mut seen, diagnostics);
+				}
+				Some(var)
+			}
+			CallType::Return(value) => {
+				read(value, &mut seen, diagnostics);
+				None
+			}
+			CallType::Closure(var, function) => {
+				// the body also sees the variable it's stored in
+				let mut inner = seen.clone();
+				inner.insert(*var);
+				resolve_calls(
+					program,
+					scopes,
+					scopes.bodies[body].closures[&index],
+					&function.calls,
+					inner,
+					owner,
+					diagnostics,
+				);
+				Some(var)
+			}
+			CallType::Set(..) => None,
+		};
+
+		// variables need to be declared before they're set
+		if let Some(var) = written {
+			if !is_temporary(var) && !known(var, index + 1, &seen) {
+				diagnostics.push(owner.error(format!(
+					"`{}` is set before it's declared with `var`",
+					var
+				)));
+			}
+			seen.insert(*var);
+		}
+	}
+
+	for name in unknown {
+		diagnostics.push(owner.error(format!("unknown function `{}`", name)));
+	}
+}
@@ -1,8 +1,12 @@
+use crate::function::write_parameters;
 use crate::function::FuncParser;
 use crate::function::Function;
+use crate::parser::Parser;
+use crate::symbol::Symbol;
 use crate::tokenizer::TokenList;
 
 use std::collections::HashMap;
+use std::fmt;
 
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct ResultParser {
@@ -11,16 +15,23 @@ pub struct ResultParser {
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ResultSig {
-	pub name: String,
-	pub return_type: Option<String>,
-	pub parameters: HashMap<String, String>,
+	pub name: Symbol,
+	pub return_type: Option<Symbol>,
+	#[cfg_attr(
+		feature = "serde",
+		serde(serialize_with = "crate::json::sorted")
+	)]
+	pub parameters: HashMap<Symbol, Symbol>,
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RoResult {
-	signature: ResultSig,
-	functions: Vec<Function>,
+	pub signature: ResultSig,
+	pub functions: Vec<Function>,
+	pub parameter_order: Vec<Symbol>,
 }
 
 impl ResultParser {
@@ -28,22 +39,26 @@ impl ResultParser {
 	 * Parses the signature for the result
 	 */
 	pub fn parse_signature(&self) -> ResultSig {
-		ResultSig::from_tokens(self.signature.clone())
+		ResultSig::from_slice(&self.signature)
+	}
+
+	/** Gets the name of the result, without parsing the rest of it */
+	pub fn name(&self) -> Symbol {
+		self.signature.first().map(Symbol::from).unwrap_or_default()
 	}
 
 	/** Creates a list of functions */
-	pub fn parse_funcs(&self) -> Vec<Function> {
-		let mut funcs = Vec::with_capacity(self.functions.len());
-		for func in self.functions.clone() {
-			funcs.push(func.parse());
-		}
-		funcs
+	pub fn parse_funcs(self) -> Vec<Function> {
+		self.functions.into_iter().map(FuncParser::parse).collect()
 	}
 
-	/** Converts to a RoResult */
-	pub fn parse(&self) -> RoResult {
+	/** Converts to a RoResult, moving the functions into it */
+	pub fn parse(self) -> RoResult {
+		let (signature, parameter_order) =
+			Parser::new(&self.signature).result_signature();
 		RoResult {
-			signature: self.parse_signature(),
+			signature,
+			parameter_order,
 			functions: self.parse_funcs(),
 		}
 	}
@@ -54,28 +69,12 @@ impl ResultSig {
 	 * Parses the signature for the result
 	 */
 	pub fn from_tokens(tokens: TokenList) -> Self {
-		let mut token: usize = 2; // the current token number
-		let mut signature = ResultSig::default();
-		signature.name = tokens[0].clone();
-		while tokens[token] != ")" && token < tokens.len() - 2 {
-			if tokens[token] == "," {
-				token += 1;
-				continue;
-			}
-			let parameter_name = tokens[token].clone();
-			let parameter_type = tokens[token + 2].clone();
-			signature.parameters.insert(parameter_name, parameter_type);
-			token += 3;
-		}
-		if tokens.len() >= 2
-			&& token < tokens.len() - 2
-			&& tokens[token + 1] == ":"
-		{
-			signature.return_type = Some(tokens[token + 2].clone());
-		} else {
-			signature.return_type = None;
-		}
-		signature
+		Self::from_slice(&tokens)
+	}
+
+	/** Parses a signature without taking ownership of its tokens */
+	pub fn from_slice(tokens: &[String]) -> Self {
+		Parser::new(tokens).result_signature().0
 	}
 
 	/**
@@ -85,3 +84,52 @@ impl ResultSig {
 		parser.parse_signature()
 	}
 }
+
+impl ResultSig {
+	/** Writes the signature, listing the parameters in the given order */
+	fn write(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+		order: &[Symbol],
+	) -> fmt::Result {
+		write!(f, "{}", self.name)?;
+		write_parameters(f, &self.parameters, order, |_| ":")?;
+		if let Some(return_type) = &self.return_type {
+			write!(f, ": {}", return_type)?;
+		}
+		Ok(())
+	}
+}
+
+impl fmt::Display for ResultSig {
+	/** Writes the signature, without the `result`, like `add(one: int)` */
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.write(f, &[])
+	}
+}
+
+impl fmt::Display for RoResult {
+	/**
+	 * Writes the result, with its implementations inside of it
+	 * Parameters which rename the result's parameters are written with `=`
+	 */
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "result ")?;
+		self.signature.write(f, &self.parameter_order)?;
+		writeln!(f, " {{")?;
+		for (i, function) in self.functions.iter().enumerate() {
+			if i > 0 {
+				writeln!(f)?;
+			}
+			function.write(f, 1, |value| {
+				if self.signature.parameters.contains_key(value) {
+					"="
+				} else {
+					":"
+				}
+			})?;
+			writeln!(f)?;
+		}
+		write!(f, "}}")
+	}
+}
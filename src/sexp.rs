@@ -0,0 +1,165 @@
+use crate::function::ordered_parameters;
+use crate::function::CallType;
+use crate::function::FuncSig;
+use crate::function::Function;
+use crate::program::Program;
+use crate::result::RoResult;
+use crate::symbol::Symbol;
+
+use std::collections::HashMap;
+
+/*
+ * Each definition is written as a list, with missing parts written as `_`
+ *
+ * (result name ((parameter type) ...) return_type functions...)
+ * (fn name ((parameter type) ...) return_type result calls...)
+ *
+ * Calls are written on their own lines, like `(operate x + one two)`
+ * Closures are written as `(closure var (fn ...))`, with the function on
+ * the lines after
+ */
+
+/** Writes a program as S-expressions, with one definition after another */
+pub fn program(program: &Program) -> String {
+	let mut sexp = String::new();
+	for result in &program.results {
+		write_result(&mut sexp, result);
+		sexp.push('\n');
+	}
+	for function in &program.functions {
+		write_function(&mut sexp, function, 0);
+		sexp.push('\n');
+	}
+	sexp
+}
+
+/** Writes a result and its implementations as an S-expression */
+pub fn result(result: &RoResult) -> String {
+	let mut sexp = String::new();
+	write_result(&mut sexp, result);
+	sexp
+}
+
+/** Writes a function and its calls as an S-expression */
+pub fn function(function: &Function) -> String {
+	let mut sexp = String::new();
+	write_function(&mut sexp, function, 0);
+	sexp
+}
+
+/** Writes a single call as an S-expression */
+pub fn call(call: &CallType) -> String {
+	match call {
+		CallType::Return(value) => format!("(return {})", atom(value)),
+		CallType::Init(var) => format!("(var {})", atom(var)),
+		CallType::Set(var, tokens) => {
+			format!("(set {} {})", atom(var), list(tokens))
+		}
+		CallType::Call(func_name, parameters) => {
+			format!("(call {} {})", atom(func_name), list(parameters))
+		}
+		CallType::SetCall(var, func_name, parameters) => format!(
+			"(set-call {} {} {})",
+			atom(var),
+			atom(func_name),
+			list(parameters)
+		),
+		CallType::Move(var, value) => {
+			format!("(move {} {})", atom(var), atom(value))
+		}
+		CallType::Operate(var, left, op, right) => format!(
+			"(operate {} {} {} {})",
+			atom(var),
+			op,
+			atom(left),
+			atom(right)
+		),
+		CallType::Closure(var, function) => {
+			let mut sexp = format!("(closure {}\n", atom(var));
+			write_function(&mut sexp, function, 1);
+			sexp.push(')');
+			sexp
+		}
+	}
+}
+
+fn write_result(sexp: &mut String, result: &RoResult) {
+	let signature = &result.signature;
+	sexp.push_str(&format!(
+		"(result {} {} {}",
+		atom(&signature.name),
+		parameters(&signature.parameters, &result.parameter_order),
+		optional(signature.return_type.as_ref())
+	));
+	for function in &result.functions {
+		sexp.push('\n');
+		write_function(sexp, function, 1);
+	}
+	sexp.push(')');
+}
+
+fn write_function(sexp: &mut String, function: &Function, indent: usize) {
+	sexp.push_str(&"  ".repeat(indent));
+	sexp.push_str(&format!("(fn {}", signature(&function.signature, function)));
+	let tabs = "  ".repeat(indent + 1);
+	for c in &function.calls {
+		sexp.push('\n');
+		sexp.push_str(&tabs);
+		// closures take up more than one line
+		sexp.push_str(&call(c).replace('\n', &format!("\n{}", tabs)));
+	}
+	sexp.push(')');
+}
+
+fn signature(signature: &FuncSig, function: &Function) -> String {
+	format!(
+		"{} {} {} {}",
+		optional(signature.name.as_ref()),
+		signature
+			.parameters
+			.as_ref()
+			.map_or(String::from("_"), |p| {
+				parameters(p, &function.parameter_order)
+			}),
+		optional(signature.return_type.as_ref()),
+		optional(signature.result.as_ref())
+	)
+}
+
+/** Writes parameters in the order they were written in */
+fn parameters(
+	parameters: &HashMap<Symbol, Symbol>,
+	order: &[Symbol],
+) -> String {
+	let pairs: Vec<String> = ordered_parameters(parameters, order)
+		.into_iter()
+		.map(|name| format!("({} {})", atom(&name), atom(&parameters[&name])))
+		.collect();
+	format!("({})", pairs.join(" "))
+}
+
+fn optional(value: Option<&Symbol>) -> String {
+	value.map_or(String::from("_"), |v| atom(v))
+}
+
+fn list<T: AsRef<str>>(values: &[T]) -> String {
+	let atoms: Vec<String> = values.iter().map(|v| atom(v.as_ref())).collect();
+	format!("({})", atoms.join(" "))
+}
+
+/**
+ * Writes a symbol, quoting it if it couldn't be read back otherwise,
+ * like temporaries such as `print(0)` and string literals
+ */
+fn atom(value: &str) -> String {
+	let plain = !value.is_empty()
+		&& value != "_"
+		&& !value.contains(|c: char| {
+			c.is_whitespace() || c == '(' || c == ')' || c == '"' || c == '\\'
+		});
+	if plain {
+		String::from(value)
+	} else {
+		format!("{:?}", value)
+	}
+}
@@ -0,0 +1,110 @@
+use crate::parser::Parser;
+use crate::tokenizer::tokenize_spanned;
+
+use std::collections::HashMap;
+
+// a range of bytes in the source code
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+	pub start: usize,
+	pub end: usize,
+}
+
+// a change to some code, replacing the bytes in a span
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextEdit {
+	pub span: Span,
+	pub text: String,
+}
+
+// remembers where each function and result was defined
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceMap {
+	pub functions: HashMap<String, Span>,
+	pub results: HashMap<String, Span>,
+	pub implementations: HashMap<String, Vec<Span>>,
+	pub calls: HashMap<Span, Vec<Span>>, // the statement of each call, by the span of its function
+	pub closures: HashMap<(Span, usize), Span>, // closures, by the function and call which make them
+}
+
+impl Span {
+	pub fn new(start: usize, end: usize) -> Self {
+		Span { start, end }
+	}
+
+	/** Creates a span which covers both spans */
+	pub fn to(self, other: Span) -> Self {
+		Span::new(self.start.min(other.start), self.end.max(other.end))
+	}
+
+	/** Gets the line and column where the span starts, counting from 1 */
+	pub fn line_col(&self, code: &str) -> (usize, usize) {
+		let before = &code[..self.start.min(code.len())];
+		let line = before.matches('\n').count() + 1;
+		let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+		let column = before[line_start..].chars().count() + 1;
+		(line, column)
+	}
+}
+
+impl TextEdit {
+	pub fn new(span: Span, text: &str) -> Self {
+		TextEdit {
+			span,
+			text: String::from(text),
+		}
+	}
+
+	/** Creates the code which results from making the edit */
+	pub fn apply(&self, code: &str) -> String {
+		let mut edited = String::from(&code[..self.span.start]);
+		edited.push_str(&self.text);
+		edited.push_str(&code[self.span.end..]);
+		edited
+	}
+}
+
+impl SourceMap {
+	/**
+	 * Finds the definitions in some code, by parsing it the same way that
+	 * the program is parsed
+	 * Implementations are listed in the same order that `Parser::program`
+	 * puts them in
+	 */
+	pub fn new(code: &str) -> Self {
+		let tokens = tokenize_spanned(code);
+		let texts: Vec<String> =
+			tokens.iter().map(|t| t.text.clone()).collect();
+		let spans: Vec<Span> = tokens.iter().map(|t| t.span).collect();
+		let mut parser = Parser::with_spans(&texts, &spans);
+		parser.program();
+		parser.into_source_map()
+	}
+
+	/** Gets the span of a free function */
+	pub fn function(&self, name: &str) -> Option<Span> {
+		self.functions.get(name).copied()
+	}
+
+	/** Gets the span of one of a result's implementations */
+	pub fn implementation(&self, result: &str, index: usize) -> Option<Span> {
+		self.implementations.get(result)?.get(index).copied()
+	}
+
+	/**
+	 * Gets the span of the statement that a call in a function came from
+	 * The function is found by its span, and the call by its index
+	 */
+	pub fn call(&self, function: Span, index: usize) -> Option<Span> {
+		self.calls.get(&function)?.get(index).copied()
+	}
+
+	/**
+	 * Gets the span of the header of the closure made by a call in a
+	 * function
+	 */
+	pub fn closure(&self, function: Span, index: usize) -> Option<Span> {
+		self.closures.get(&(function, index)).copied()
+	}
+}
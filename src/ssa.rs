@@ -0,0 +1,303 @@
+use crate::interp::Value;
+use crate::ir::BlockId;
+use crate::ir::Instruction;
+use crate::ir::IrFunction;
+use crate::ir::Module;
+use crate::ir::Operand;
+use crate::ir::Register;
+use crate::ir::RegisterInfo;
+use crate::ir::Terminator;
+
+use std::collections::HashMap;
+
+/*
+ * Converts functions to static single assignment form, where each register
+ * is written to exactly once
+ *
+ * Phi nodes are placed on the dominance frontiers of the blocks which write
+ * to a register, and then each write is given a new register while walking
+ * the dominator tree. A register which is read before anything is written
+ * to it is void, like a variable which was declared without a value.
+ */
+
+/**
+ * Finds the block which immediately dominates each block
+ * The first block and unreachable blocks have no dominator
+ */
+pub fn immediate_dominators(function: &IrFunction) -> Vec<Option<BlockId>> {
+	let order = function.reverse_postorder();
+	let predecessors = function.predecessors();
+	let mut position = vec![usize::MAX; function.blocks.len()];
+	for (i, block) in order.iter().enumerate() {
+		position[block.0] = i;
+	}
+
+	// walks up from two blocks until they meet
+	let intersect =
+		|dominators: &[Option<BlockId>], mut a: usize, mut b: usize| {
+			while a != b {
+				while position[a] > position[b] {
+					a = dominators[a].map_or(0, |d| d.0);
+				}
+				while position[b] > position[a] {
+					b = dominators[b].map_or(0, |d| d.0);
+				}
+			}
+			a
+		};
+
+	let mut dominators = vec![None; function.blocks.len()];
+	dominators[0] = Some(BlockId(0));
+	let mut changed = true;
+	while changed {
+		changed = false;
+		for block in order.iter().skip(1) {
+			let mut found = predecessors[block.0]
+				.iter()
+				.filter(|p| dominators[p.0].is_some())
+				.map(|p| p.0);
+			let first = match found.next() {
+				Some(first) => first,
+				None => continue,
+			};
+			let dominator =
+				found.fold(first, |d, p| intersect(&dominators, p, d));
+			if dominators[block.0] != Some(BlockId(dominator)) {
+				dominators[block.0] = Some(BlockId(dominator));
+				changed = true;
+			}
+		}
+	}
+
+	dominators[0] = None;
+	dominators
+}
+
+/** Lists the blocks which each block immediately dominates */
+pub fn dominator_tree(dominators: &[Option<BlockId>]) -> Vec<Vec<BlockId>> {
+	let mut children = vec![Vec::new(); dominators.len()];
+	for (block, dominator) in dominators.iter().enumerate() {
+		if let Some(dominator) = dominator {
+			children[dominator.0].push(BlockId(block));
+		}
+	}
+	children
+}
+
+/**
+ * Finds where each block's dominance ends, which is where the values
+ * written in it might meet values from other blocks
+ */
+fn dominance_frontiers(
+	function: &IrFunction,
+	dominators: &[Option<BlockId>],
+) -> Vec<Vec<BlockId>> {
+	let mut frontiers = vec![Vec::new(); function.blocks.len()];
+	for (block, predecessors) in function.predecessors().iter().enumerate() {
+		if predecessors.len() < 2 {
+			continue;
+		}
+		for predecessor in predecessors {
+			let mut runner = Some(*predecessor);
+			while let Some(current) = runner {
+				if Some(current) == dominators[block] {
+					break;
+				}
+				if !frontiers[current.0].contains(&BlockId(block)) {
+					frontiers[current.0].push(BlockId(block));
+				}
+				runner = dominators[current.0];
+			}
+		}
+	}
+	frontiers
+}
+
+/** Converts every function in a module to SSA form */
+pub fn construct_module(module: &mut Module) {
+	for function in &mut module.functions {
+		construct(function);
+	}
+}
+
+/**
+ * Converts a function to SSA form
+ * Unreachable blocks are emptied, since nothing they write can be read
+ */
+pub fn construct(function: &mut IrFunction) {
+	let order = function.reverse_postorder();
+	for (i, block) in function.blocks.iter_mut().enumerate() {
+		if !order.contains(&BlockId(i)) {
+			block.instructions.clear();
+			block.terminator =
+				Terminator::Return(Operand::Constant(Value::Void));
+		}
+	}
+
+	let dominators = immediate_dominators(function);
+	let frontiers = dominance_frontiers(function, &dominators);
+	let predecessors = function.predecessors();
+
+	// find the blocks which write to each register
+	let mut writes: Vec<Vec<BlockId>> =
+		vec![Vec::new(); function.registers.len()];
+	for parameter in &function.parameters {
+		writes[parameter.0].push(BlockId(0));
+	}
+	for (i, block) in function.blocks.iter().enumerate() {
+		for instruction in &block.instructions {
+			if let Some(register) = instruction.destination() {
+				if !writes[register.0].contains(&BlockId(i)) {
+					writes[register.0].push(BlockId(i));
+				}
+			}
+		}
+	}
+
+	// place the phi nodes, remembering which register each one is for
+	let mut phis: Vec<Vec<Register>> = vec![Vec::new(); function.blocks.len()];
+	for (register, blocks) in writes.into_iter().enumerate() {
+		let mut work = blocks.clone();
+		let mut placed: Vec<BlockId> = Vec::new();
+		while let Some(block) = work.pop() {
+			for frontier in &frontiers[block.0] {
+				if placed.contains(frontier) {
+					continue;
+				}
+				placed.push(*frontier);
+				phis[frontier.0].push(Register(register));
+				let values = predecessors[frontier.0]
+					.iter()
+					.map(|p| (*p, Operand::Constant(Value::Void)))
+					.collect();
+				function.blocks[frontier.0]
+					.instructions
+					.insert(0, Instruction::Phi(Register(register), values));
+				if !blocks.contains(frontier) {
+					work.push(*frontier);
+				}
+			}
+		}
+	}
+	for block in &mut phis {
+		block.reverse();
+	}
+
+	let mut renamer = Renamer {
+		old: std::mem::take(&mut function.registers),
+		registers: Vec::new(),
+		stacks: HashMap::new(),
+		phis,
+		tree: dominator_tree(&dominators),
+	};
+	function.parameters = function
+		.parameters
+		.iter()
+		.map(|parameter| renamer.write(*parameter))
+		.collect();
+	renamer.rename(function, BlockId(0));
+	function.registers = renamer.registers;
+}
+
+// gives each write a new register, walking down the dominator tree
+struct Renamer {
+	old: Vec<RegisterInfo>,
+	registers: Vec<RegisterInfo>,
+	stacks: HashMap<Register, Vec<Register>>, // the newest version is last
+	phis: Vec<Vec<Register>>,                 // the register each phi is for
+	tree: Vec<Vec<BlockId>>,
+}
+
+impl Renamer {
+	/** Creates a new version of a register */
+	fn write(&mut self, register: Register) -> Register {
+		self.registers.push(self.old[register.0].clone());
+		let new = Register(self.registers.len() - 1);
+		self.stacks.entry(register).or_default().push(new);
+		new
+	}
+
+	/** Gets the newest version of a register, or void if there isn't one */
+	fn read(&self, operand: &mut Operand) {
+		if let Operand::Register(register) = operand {
+			*operand = match self.stacks.get(register).and_then(|s| s.last()) {
+				Some(new) => Operand::Register(*new),
+				None => Operand::Constant(Value::Void),
+			};
+		}
+	}
+
+	fn rename(&mut self, function: &mut IrFunction, block: BlockId) {
+		let mut written = Vec::new();
+		let mut instructions =
+			std::mem::take(&mut function.blocks[block.0].instructions);
+		for instruction in &mut instructions {
+			if !matches!(instruction, Instruction::Phi(..)) {
+				for operand in instruction.operands_mut() {
+					self.read(operand);
+				}
+			}
+			let old = instruction.destination();
+			if let Some(old) = old {
+				let new = self.write(old);
+				written.push(old);
+				match instruction {
+					Instruction::Move(register, _)
+					| Instruction::Operate(register, ..)
+					| Instruction::Phi(register, _) => *register = new,
+					Instruction::Call(register, ..) => *register = Some(new),
+				}
+			}
+		}
+		function.blocks[block.0].instructions = instructions;
+		if let Some(operand) = function.blocks[block.0].terminator.operand_mut()
+		{
+			self.read(operand);
+		}
+
+		// fill in the values which the phis after this block get from it
+		for successor in function.blocks[block.0].terminator.successors() {
+			let registers = self.phis[successor.0].clone();
+			let instructions = &mut function.blocks[successor.0].instructions;
+			for (register, instruction) in registers.iter().zip(instructions) {
+				if let Instruction::Phi(_, values) = instruction {
+					for (from, value) in values.iter_mut() {
+						if *from == block {
+							*value = Operand::Register(*register);
+							self.read(value);
+						}
+					}
+				}
+			}
+		}
+
+		for child in self.tree[block.0].clone() {
+			self.rename(function, child);
+		}
+
+		for old in written {
+			if let Some(stack) = self.stacks.get_mut(&old) {
+				stack.pop();
+			}
+		}
+	}
+}
+
+/** Checks that each register is written to at most once */
+pub fn is_ssa(function: &IrFunction) -> bool {
+	let mut written = vec![false; function.registers.len()];
+	for parameter in &function.parameters {
+		written[parameter.0] = true;
+	}
+	for block in &function.blocks {
+		for instruction in &block.instructions {
+			if let Some(register) = instruction.destination() {
+				if written[register.0] {
+					return false;
+				}
+				written[register.0] = true;
+			}
+		}
+	}
+	true
+}
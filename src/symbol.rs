@@ -0,0 +1,357 @@
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::OnceLock;
+use std::sync::RwLock;
+use std::sync::RwLockReadGuard;
+use std::sync::RwLockWriteGuard;
+
+/**
+ * An interned name, like an identifier or a type
+ * Symbols are small handles, so they can be copied, compared and hashed
+ * without touching the text they stand for. The low bits say which slot the
+ * name is kept in and the high bits how many times that slot has been
+ * reused, so a symbol whose name was freed doesn't equal a newer one.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+const SLOT_BITS: u32 = 24;
+const SLOT_MASK: u32 = (1 << SLOT_BITS) - 1;
+
+/**
+ * Keeps the names which are interned while it's entered
+ * A program parsed in an arena only keeps its names in use while the arena
+ * is alive. Once every arena using a name is dropped, and the name wasn't
+ * interned outside of one, `Symbol::collect` frees it.
+ */
+pub struct Arena(usize);
+
+// where a name is kept
+struct Slot {
+	name: Option<&'static str>, // leaked until it's collected
+	generation: u32,
+	arenas: usize,   // how many arenas use the name
+	permanent: bool, // interned outside of any arena
+}
+
+// every name which is in use, shared between threads
+#[derive(Default)]
+struct Interner {
+	symbols: HashMap<&'static str, Symbol>,
+	slots: Vec<Slot>,
+	free: Vec<usize>,
+	unused: Vec<usize>, // slots which might be collected
+	arenas: HashMap<usize, HashSet<usize>>, // the slots each arena uses
+	next_arena: usize,
+}
+
+thread_local! {
+	// the arena which names are interned into on this thread
+	static ARENA: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+fn interner() -> &'static RwLock<Interner> {
+	static INTERNER: OnceLock<RwLock<Interner>> = OnceLock::new();
+	INTERNER.get_or_init(Default::default)
+}
+
+fn read() -> RwLockReadGuard<'static, Interner> {
+	interner().read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write() -> RwLockWriteGuard<'static, Interner> {
+	interner().write().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Symbol {
+	/**
+	 * Gets the symbol for a name, interning it if it hasn't been seen yet
+	 * The name is kept by the arena which is entered on this thread, or for
+	 * the rest of the program if there isn't one
+	 */
+	pub fn intern(name: &str) -> Self {
+		let arena = ARENA.with(Cell::get);
+		let interner = read();
+		if let Some(&symbol) = interner.symbols.get(name) {
+			let kept = match arena {
+				Some(arena) => interner.arenas[&arena].contains(&symbol.slot()),
+				None => interner.slots[symbol.slot()].permanent,
+			};
+			if kept {
+				return symbol;
+			}
+		}
+
+		drop(interner);
+
+		// another thread might have interned it since the lock was released
+		let mut interner = write();
+		let symbol = match interner.symbols.get(name) {
+			Some(&symbol) => symbol,
+			None => interner.insert(name),
+		};
+		let slot = symbol.slot();
+		match arena {
+			Some(arena) => {
+				if interner.arenas.get_mut(&arena).unwrap().insert(slot) {
+					interner.slots[slot].arenas += 1;
+				}
+			}
+			None => interner.slots[slot].permanent = true,
+		}
+		symbol
+	}
+
+	/**
+	 * Gets the name which the symbol stands for
+	 * Panics if the name has been collected
+	 */
+	pub fn as_str(self) -> &'static str {
+		let interner = read();
+		let slot = &interner.slots[self.slot()];
+		match slot.name {
+			Some(name) if slot.generation == self.generation() => name,
+			_ => panic!("the name of a symbol was used after it was freed"),
+		}
+	}
+
+	/** Counts the names which are interned */
+	pub fn interned() -> usize {
+		let interner = read();
+		interner.slots.len() - interner.free.len()
+	}
+
+	/**
+	 * Frees the names which are only used by arenas that have been dropped,
+	 * and gives back how many were freed
+	 *
+	 * # Safety
+	 *
+	 * Text borrowed from a freed name, through `as_str` or `Deref`, must not
+	 * be used afterwards. Symbols for freed names can still be compared, but
+	 * reading their names panics.
+	 */
+	pub unsafe fn collect() -> usize {
+		let mut interner = write();
+		let interner = &mut *interner;
+		let mut freed = 0;
+		for index in interner.unused.drain(..) {
+			let slot = &mut interner.slots[index];
+			if slot.permanent || slot.arenas > 0 {
+				continue;
+			}
+			if let Some(name) = slot.name.take() {
+				interner.symbols.remove(name);
+				interner.free.push(index);
+				// SAFETY: the name was leaked from a box when it was
+				// interned, and the caller has stopped borrowing it
+				drop(unsafe { Box::from_raw(name as *const str as *mut str) });
+				freed += 1;
+			}
+		}
+		freed
+	}
+
+	fn slot(self) -> usize {
+		(self.0 & SLOT_MASK) as usize
+	}
+
+	fn generation(self) -> u32 {
+		self.0 >> SLOT_BITS
+	}
+}
+
+impl Interner {
+	// keeps a new name, reusing the slot of a freed one if there is one
+	fn insert(&mut self, name: &str) -> Symbol {
+		let name: &'static str = Box::leak(Box::from(name));
+		let (index, generation) = match self.free.pop() {
+			Some(index) => {
+				let generation = self.slots[index].generation + 1;
+				(index, generation & (u32::MAX >> SLOT_BITS))
+			}
+			None => {
+				assert!(
+					self.slots.len() <= SLOT_MASK as usize,
+					"too many names"
+				);
+				self.slots.push(Slot {
+					name: None,
+					generation: 0,
+					arenas: 0,
+					permanent: false,
+				});
+				(self.slots.len() - 1, 0)
+			}
+		};
+		self.slots[index] = Slot {
+			name: Some(name),
+			generation,
+			arenas: 0,
+			permanent: false,
+		};
+		let symbol = Symbol(index as u32 | generation << SLOT_BITS);
+		self.symbols.insert(name, symbol);
+		symbol
+	}
+}
+
+impl Arena {
+	/** Makes an arena which doesn't keep any names yet */
+	pub fn new() -> Self {
+		let mut interner = write();
+		let arena = interner.next_arena;
+		interner.next_arena += 1;
+		interner.arenas.insert(arena, HashSet::new());
+		Arena(arena)
+	}
+
+	/** Runs `f`, keeping the names it interns on this thread in the arena */
+	pub fn enter<T>(&self, f: impl FnOnce() -> T) -> T {
+		// the outer arena is entered again even if `f` panics
+		struct Exit(Option<usize>);
+		impl Drop for Exit {
+			fn drop(&mut self) {
+				ARENA.with(|arena| arena.set(self.0));
+			}
+		}
+
+		let _exit = Exit(ARENA.with(|arena| arena.replace(Some(self.0))));
+		f()
+	}
+}
+
+impl Default for Arena {
+	fn default() -> Self {
+		Arena::new()
+	}
+}
+
+impl Drop for Arena {
+	fn drop(&mut self) {
+		let mut interner = write();
+		if let Some(slots) = interner.arenas.remove(&self.0) {
+			for index in slots {
+				let slot = &mut interner.slots[index];
+				slot.arenas -= 1;
+				if slot.arenas == 0 && !slot.permanent {
+					interner.unused.push(index);
+				}
+			}
+		}
+	}
+}
+
+impl Default for Symbol {
+	/** The empty name */
+	fn default() -> Self {
+		Symbol::intern("")
+	}
+}
+
+impl Deref for Symbol {
+	type Target = str;
+
+	fn deref(&self) -> &str {
+		self.as_str()
+	}
+}
+
+impl AsRef<str> for Symbol {
+	fn as_ref(&self) -> &str {
+		self.as_str()
+	}
+}
+
+impl From<&str> for Symbol {
+	fn from(name: &str) -> Self {
+		Symbol::intern(name)
+	}
+}
+
+impl From<&String> for Symbol {
+	fn from(name: &String) -> Self {
+		Symbol::intern(name)
+	}
+}
+
+impl From<String> for Symbol {
+	fn from(name: String) -> Self {
+		Symbol::intern(&name)
+	}
+}
+
+impl PartialEq<str> for Symbol {
+	fn eq(&self, other: &str) -> bool {
+		self.as_str() == other
+	}
+}
+
+impl PartialEq<&str> for Symbol {
+	fn eq(&self, other: &&str) -> bool {
+		self.as_str() == *other
+	}
+}
+
+impl PartialEq<String> for Symbol {
+	fn eq(&self, other: &String) -> bool {
+		self.as_str() == other
+	}
+}
+
+impl PartialOrd for Symbol {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for Symbol {
+	/**
+	 * Symbols are sorted alphabetically, so the order doesn't depend on
+	 * which one was interned first
+	 */
+	fn cmp(&self, other: &Self) -> Ordering {
+		if self == other {
+			Ordering::Equal
+		} else {
+			self.as_str().cmp(other.as_str())
+		}
+	}
+}
+
+impl fmt::Display for Symbol {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+impl fmt::Debug for Symbol {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{:?}", self.as_str())
+	}
+}
+
+// symbols are written as their names, so the JSON schema doesn't change
+#[cfg(feature = "serde")]
+impl serde::Serialize for Symbol {
+	fn serialize<S: serde::Serializer>(
+		&self,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(self.as_str())
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Symbol {
+	fn deserialize<D: serde::Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Self, D::Error> {
+		let name = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+		Ok(Symbol::intern(&name))
+	}
+}
@@ -1,8 +1,14 @@
 use crate::*;
 use function::*;
+use interp::*;
+use parser::Parser;
 use program::*;
+use repl::*;
 use result::*;
+use symbol::Arena;
+use symbol::Symbol;
 use tokenizer::*;
+use typeck::Type;
 
 use std::collections::HashMap;
 
@@ -387,6 +393,15 @@ fn parse_results_test() {
 			functions: vec![function1]
 		}
 	);
+
+	// functions for results which don't exist aren't moved
+	let code = "result add(one: int) {}
+		fn => add { ret one }
+		fn => unknown { ret 1 }";
+	let program =
+		ProgramParser::from_tokens(tokenize(String::from(code))).parse();
+	assert_eq!(program.results[0].functions.len(), 1);
+	assert_eq!(program.functions.len(), 1);
 }
 
 #[test]
@@ -401,15 +416,15 @@ fn parse_result_sig_test() {
 		"float",
 	]);
 	let mut parameters = HashMap::new();
-	let rt = Some(String::from("float"));
-	let name = String::from("add");
-	parameters.insert(String::from("one"), String::from("float"));
-	parameters.insert(String::from("two"), String::from("float"));
+	let rt = Some(Symbol::from("float"));
+	let name = Symbol::from("add");
+	parameters.insert(Symbol::from("one"), Symbol::from("float"));
+	parameters.insert(Symbol::from("two"), Symbol::from("float"));
 	assert_eq!(
 		ResultSig::from_tokens(signature),
 		ResultSig {
-			name: name.clone(),
-			return_type: rt.clone(),
+			name,
+			return_type: rt,
 			parameters: parameters.clone()
 		}
 	);
@@ -420,7 +435,7 @@ fn parse_result_sig_test() {
 	assert_eq!(
 		ResultSig::from_tokens(signature),
 		ResultSig {
-			name: name.clone(),
+			name,
 			return_type: None,
 			parameters: parameters.clone()
 		}
@@ -431,7 +446,7 @@ fn parse_result_sig_test() {
 	assert_eq!(
 		ResultSig::from_tokens(signature),
 		ResultSig {
-			name: name.clone(),
+			name,
 			return_type: rt,
 			parameters: parameters.clone()
 		}
@@ -439,7 +454,7 @@ fn parse_result_sig_test() {
 
 	// one parameter with an unnecessary comma
 	signature = string_vec(vec!["add", "(", "one", ":", "float", ",", ")"]);
-	parameters.insert(String::from("one"), String::from("float"));
+	parameters.insert(Symbol::from("one"), Symbol::from("float"));
 	assert_eq!(
 		ResultSig::from_tokens(signature),
 		ResultSig {
@@ -474,7 +489,7 @@ fn parse_fn_signature_test() {
 	assert_eq!(
 		func_parser.clone().parse_signature(),
 		FuncSig {
-			name: Some(String::from("add")),
+			name: Some(Symbol::from("add")),
 			parameters: None,
 			return_type: None,
 			result: None
@@ -486,9 +501,9 @@ fn parse_fn_signature_test() {
 	assert_eq!(
 		func_parser.clone().parse_signature(),
 		FuncSig {
-			name: Some(String::from("add")),
+			name: Some(Symbol::from("add")),
 			parameters: None,
-			return_type: Some(String::from("float")),
+			return_type: Some(Symbol::from("float")),
 			result: None
 		}
 	);
@@ -500,7 +515,7 @@ fn parse_fn_signature_test() {
 		FuncSig {
 			name: None,
 			parameters: None,
-			return_type: Some(String::from("float")),
+			return_type: Some(Symbol::from("float")),
 			result: None
 		}
 	);
@@ -509,11 +524,11 @@ fn parse_fn_signature_test() {
 	func_parser.signature =
 		string_vec(vec!["add", "(", "one", ":", "float", ")"]);
 	let mut parameters = HashMap::new();
-	parameters.insert(String::from("one"), String::from("float"));
+	parameters.insert(Symbol::from("one"), Symbol::from("float"));
 	assert_eq!(
 		func_parser.clone().parse_signature(),
 		FuncSig {
-			name: Some(String::from("add")),
+			name: Some(Symbol::from("add")),
 			parameters: Some(parameters.clone()),
 			return_type: None,
 			result: None
@@ -524,11 +539,11 @@ fn parse_fn_signature_test() {
 	func_parser.signature = string_vec(vec![
 		"add", "(", "one", ":", "float", ",", "two", ":", "float", ")",
 	]);
-	parameters.insert(String::from("two"), String::from("float"));
+	parameters.insert(Symbol::from("two"), Symbol::from("float"));
 	assert_eq!(
 		func_parser.clone().parse_signature(),
 		FuncSig {
-			name: Some(String::from("add")),
+			name: Some(Symbol::from("add")),
 			parameters: Some(parameters.clone()),
 			return_type: None,
 			result: None
@@ -543,9 +558,9 @@ fn parse_fn_signature_test() {
 	assert_eq!(
 		func_parser.clone().parse_signature(),
 		FuncSig {
-			name: Some(String::from("add")),
+			name: Some(Symbol::from("add")),
 			parameters: Some(parameters.clone()),
-			return_type: Some(String::from("float")),
+			return_type: Some(Symbol::from("float")),
 			result: None
 		}
 	);
@@ -559,7 +574,7 @@ fn parse_fn_signature_test() {
 		FuncSig {
 			name: None,
 			parameters: Some(parameters.clone()),
-			return_type: Some(String::from("float")),
+			return_type: Some(Symbol::from("float")),
 			result: None
 		}
 	);
@@ -572,9 +587,9 @@ fn parse_fn_signature_test() {
 	assert_eq!(
 		func_parser.clone().parse_signature(),
 		FuncSig {
-			name: Some(String::from("add")),
+			name: Some(Symbol::from("add")),
 			parameters: Some(parameters.clone()),
-			return_type: Some(String::from("float")),
+			return_type: Some(Symbol::from("float")),
 			result: None
 		}
 	);
@@ -587,7 +602,7 @@ fn parse_fn_signature_test() {
 			name: None,
 			parameters: None,
 			return_type: None,
-			result: Some(String::from("add"))
+			result: Some(Symbol::from("add"))
 		}
 	);
 
@@ -596,10 +611,10 @@ fn parse_fn_signature_test() {
 	assert_eq!(
 		func_parser.clone().parse_signature(),
 		FuncSig {
-			name: Some(String::from("add")),
+			name: Some(Symbol::from("add")),
 			parameters: None,
 			return_type: None,
-			result: Some(String::from("add"))
+			result: Some(Symbol::from("add"))
 		}
 	);
 
@@ -610,8 +625,8 @@ fn parse_fn_signature_test() {
 		FuncSig {
 			name: None,
 			parameters: None,
-			return_type: Some(String::from("float")),
-			result: Some(String::from("add"))
+			return_type: Some(Symbol::from("float")),
+			result: Some(Symbol::from("add"))
 		}
 	);
 
@@ -625,7 +640,7 @@ fn parse_fn_signature_test() {
 			name: None,
 			parameters: Some(parameters.clone()),
 			return_type: None,
-			result: Some(String::from("add"))
+			result: Some(Symbol::from("add"))
 		}
 	);
 
@@ -637,10 +652,10 @@ fn parse_fn_signature_test() {
 	assert_eq!(
 		func_parser.clone().parse_signature(),
 		FuncSig {
-			name: Some(String::from("add")),
+			name: Some(Symbol::from("add")),
 			parameters: Some(parameters.clone()),
 			return_type: None,
-			result: Some(String::from("add"))
+			result: Some(Symbol::from("add"))
 		}
 	);
 
@@ -652,10 +667,10 @@ fn parse_fn_signature_test() {
 	assert_eq!(
 		func_parser.clone().parse_signature(),
 		FuncSig {
-			name: Some(String::from("add")),
+			name: Some(Symbol::from("add")),
 			parameters: Some(parameters.clone()),
-			return_type: Some(String::from("float")),
-			result: Some(String::from("add"))
+			return_type: Some(Symbol::from("float")),
+			result: Some(Symbol::from("add"))
 		}
 	);
 
@@ -669,8 +684,8 @@ fn parse_fn_signature_test() {
 		FuncSig {
 			name: None,
 			parameters: Some(parameters),
-			return_type: Some(String::from("float")),
-			result: Some(String::from("add"))
+			return_type: Some(Symbol::from("float")),
+			result: Some(Symbol::from("add"))
 		}
 	);
 }
@@ -680,19 +695,22 @@ fn parse_func_test() {
 	let string_vec = |vec: Vec<&str>| -> Vec<String> {
 		vec.iter().map(|s| String::from(*s)).collect()
 	};
+	let symbol_vec = |vec: Vec<&str>| -> Vec<Symbol> {
+		vec.iter().map(|s| Symbol::from(*s)).collect()
+	};
 
 	// just a return statement
 	let mut code = string_vec(vec!["ret", "0"]);
 	assert_eq!(
 		CallType::vec_from_tokens(code),
-		vec![CallType::Return(String::from("0"))]
+		vec![CallType::Return(Symbol::from("0"))]
 	);
 
 	// just initializes a variable
 	code = string_vec(vec!["var", "var1"]);
 	assert_eq!(
 		CallType::vec_from_tokens(code),
-		vec![CallType::Init(String::from("var1"))]
+		vec![CallType::Init(Symbol::from("var1"))]
 	);
 
 	// sets a new var
@@ -700,8 +718,8 @@ fn parse_func_test() {
 	assert_eq!(
 		CallType::vec_from_tokens(code),
 		vec![
-			CallType::Init(String::from("var1")),
-			CallType::Set(String::from("var1"), string_vec(vec!["3"]))
+			CallType::Init(Symbol::from("var1")),
+			CallType::Set(Symbol::from("var1"), string_vec(vec!["3"]))
 		]
 	);
 
@@ -710,9 +728,9 @@ fn parse_func_test() {
 	assert_eq!(
 		CallType::vec_from_tokens(code),
 		vec![
-			CallType::Init(String::from("var1")),
+			CallType::Init(Symbol::from("var1")),
 			CallType::Set(
-				String::from("var1"),
+				Symbol::from("var1"),
 				string_vec(vec!["3", "+", "2", "*", "7"])
 			)
 		]
@@ -723,9 +741,9 @@ fn parse_func_test() {
 	assert_eq!(
 		CallType::vec_from_tokens(code),
 		vec![
-			CallType::Init(String::from("num")),
-			CallType::Set(String::from("num"), string_vec(vec!["5"])),
-			CallType::Return(String::from("num"))
+			CallType::Init(Symbol::from("num")),
+			CallType::Set(Symbol::from("num"), string_vec(vec!["5"])),
+			CallType::Return(Symbol::from("num"))
 		]
 	);
 
@@ -734,9 +752,9 @@ fn parse_func_test() {
 	assert_eq!(
 		CallType::vec_from_tokens(code),
 		vec![
-			CallType::Init(String::from("num")),
-			CallType::Set(String::from("num"), string_vec(vec!["5", "+", "3"])),
-			CallType::Return(String::from("num"))
+			CallType::Init(Symbol::from("num")),
+			CallType::Set(Symbol::from("num"), string_vec(vec!["5", "+", "3"])),
+			CallType::Return(Symbol::from("num"))
 		]
 	);
 
@@ -744,7 +762,7 @@ fn parse_func_test() {
 	code = string_vec(vec!["num", "=", "5"]);
 	assert_eq!(
 		CallType::vec_from_tokens(code),
-		vec![CallType::Set(String::from("num"), string_vec(vec!["5"]))]
+		vec![CallType::Set(Symbol::from("num"), string_vec(vec!["5"]))]
 	);
 
 	// sets a pre-exiting var to the result of an operation
@@ -752,7 +770,7 @@ fn parse_func_test() {
 	assert_eq!(
 		CallType::vec_from_tokens(code),
 		vec![CallType::Set(
-			String::from("num"),
+			Symbol::from("num"),
 			string_vec(vec!["5", "+", "3", "*", "7"])
 		)]
 	);
@@ -762,9 +780,9 @@ fn parse_func_test() {
 	assert_eq!(
 		CallType::vec_from_tokens(code),
 		vec![
-			CallType::Init(String::from("num")),
+			CallType::Init(Symbol::from("num")),
 			CallType::Set(
-				String::from("num"),
+				Symbol::from("num"),
 				string_vec(vec!["5", "+", "3", "*", "7"])
 			)
 		]
@@ -777,12 +795,12 @@ fn parse_func_test() {
 	assert_eq!(
 		CallType::vec_from_tokens(code),
 		vec![
-			CallType::Init(String::from("num")),
+			CallType::Init(Symbol::from("num")),
 			CallType::Set(
-				String::from("num"),
+				Symbol::from("num"),
 				string_vec(vec!["5", "+", "3", "*", "7"])
 			),
-			CallType::Return(String::from("num"))
+			CallType::Return(Symbol::from("num"))
 		]
 	);
 
@@ -790,7 +808,7 @@ fn parse_func_test() {
 	code = string_vec(vec!["print", "(", ")"]);
 	assert_eq!(
 		CallType::vec_from_tokens(code),
-		vec![CallType::Call(String::from("print"), vec![])]
+		vec![CallType::Call(Symbol::from("print"), vec![])]
 	);
 
 	// parameter function call
@@ -798,8 +816,8 @@ fn parse_func_test() {
 	assert_eq!(
 		CallType::vec_from_tokens(code),
 		vec![CallType::Call(
-			String::from("print"),
-			string_vec(vec!["num"])
+			Symbol::from("print"),
+			symbol_vec(vec!["num"])
 		)]
 	);
 
@@ -808,8 +826,8 @@ fn parse_func_test() {
 	assert_eq!(
 		CallType::vec_from_tokens(code),
 		vec![CallType::Call(
-			String::from("print"),
-			string_vec(vec!["num", "vart"])
+			Symbol::from("print"),
+			symbol_vec(vec!["num", "vart"])
 		)]
 	);
 
@@ -818,8 +836,8 @@ fn parse_func_test() {
 	assert_eq!(
 		CallType::vec_from_tokens(code),
 		vec![
-			CallType::Call(String::from("print"), string_vec(vec!["num"])),
-			CallType::Return(String::from("void"))
+			CallType::Call(Symbol::from("print"), symbol_vec(vec!["num"])),
+			CallType::Return(Symbol::from("void"))
 		]
 	);
 }
@@ -831,25 +849,2078 @@ fn test_sets_to_ops() {
 	};
 
 	let mut calls: CallList = vec![CallType::Set(
-		String::from("var1"),
+		Symbol::from("var1"),
 		string_vec(vec!["var2"]),
 	)];
 	assert_eq!(
 		CallType::sets_to_ops(calls),
-		vec![CallType::Move(String::from("var1"), String::from("var2"))]
+		vec![CallType::Move(Symbol::from("var1"), Symbol::from("var2"))]
 	);
 
 	calls = vec![CallType::Set(
-		String::from("var1"),
+		Symbol::from("var1"),
 		string_vec(vec!["vara", "+", "varb"]),
 	)];
 	assert_eq!(
 		CallType::sets_to_ops(calls),
 		vec![CallType::Operate(
-			String::from("var1"),
-			String::from("vara"),
+			Symbol::from("var1"),
+			Symbol::from("vara"),
 			Operation::Add,
-			String::from("varb")
+			Symbol::from("varb")
 		)]
 	);
 }
+
+#[test]
+fn tokenize_literals_test() {
+	// strings keep their whitespace and comments
+	let mut code = String::from("print(\"hello, // world\")");
+	let mut tokens = vec!["print", "(", "\"hello, // world\"", ")"];
+	assert_eq!(tokenize(code), tokens);
+
+	// decimal points in numbers
+	code = String::from("var x = 1.5+2.");
+	tokens = vec!["var", "x", "=", "1.5", "+", "2."];
+	assert_eq!(tokenize(code), tokens);
+}
+
+#[test]
+fn test_expression_lowering() {
+	let string_vec = |vec: Vec<&str>| -> Vec<String> {
+		vec.iter().map(|s| String::from(*s)).collect()
+	};
+	let symbol_vec = |vec: Vec<&str>| -> Vec<Symbol> {
+		vec.iter().map(|s| Symbol::from(*s)).collect()
+	};
+
+	// multiplication happens first
+	let mut calls: CallList = vec![CallType::Set(
+		Symbol::from("x"),
+		string_vec(vec!["a", "+", "b", "*", "c"]),
+	)];
+	assert_eq!(
+		CallType::sets_to_ops(calls),
+		vec![
+			CallType::Operate(
+				Symbol::from("x.0"),
+				Symbol::from("b"),
+				Operation::Mult,
+				Symbol::from("c")
+			),
+			CallType::Operate(
+				Symbol::from("x"),
+				Symbol::from("a"),
+				Operation::Add,
+				Symbol::from("x.0")
+			)
+		]
+	);
+
+	// a nested function call
+	calls = CallType::vec_from_tokens(string_vec(vec![
+		"print", "(", "add", "(", "1", ",", "2", ")", ")",
+	]));
+	assert_eq!(
+		CallType::sets_to_ops(calls),
+		vec![
+			CallType::SetCall(
+				Symbol::from("print(0)"),
+				Symbol::from("add"),
+				symbol_vec(vec!["1", "2"])
+			),
+			CallType::Call(Symbol::from("print"), symbol_vec(vec!["print(0)"]))
+		]
+	);
+
+	// returning an expression
+	calls = CallType::vec_from_tokens(string_vec(vec![
+		"return", "one", "+", "two",
+	]));
+	assert_eq!(
+		CallType::sets_to_ops(calls),
+		vec![
+			CallType::Operate(
+				Symbol::from("return"),
+				Symbol::from("one"),
+				Operation::Add,
+				Symbol::from("two")
+			),
+			CallType::Return(Symbol::from("return"))
+		]
+	);
+}
+
+#[test]
+fn interpret_test() {
+	let run = |code: &str| -> (Result<Value, InterpError>, String) {
+		let program = parse(String::from(code));
+		let mut output = Vec::new();
+		let value = Interpreter::new(&program, &mut output).run();
+		(value, String::from_utf8(output).unwrap())
+	};
+
+	// the benchmark calls a result
+	let code = std::fs::read_to_string("src/benchmark.ro").unwrap();
+	assert_eq!(run(&code), (Ok(Value::Void), String::from("3\n")));
+
+	// free functions, precedence and floats
+	let (value, output) = run("fn half(x: float): float { return x / 2.0 }
+		fn main() { var y : float = half(3) + 2 * 2 print(y) return y }");
+	assert_eq!(value, Ok(Value::Float(5.5)));
+	assert_eq!(output, "5.5\n");
+
+	// strings and built-ins
+	let (_, output) = run(
+		"fn main() { var s = \"answer: \" + string(40 + 2) print(s, true) }",
+	);
+	assert_eq!(output, "answer: 42 true\n");
+
+	// results which rename their parameters
+	let (value, _) = run(
+		"result sub(one: int, two: int): int { fn(a = one, b = two) { ret a - b } }
+		fn main() { ret sub(5, 3) }",
+	);
+	assert_eq!(value, Ok(Value::Int(2)));
+
+	// functions built by hand bind their arguments in the order they're given
+	let int = Symbol::from("int");
+	let names = ["a", "b", "c", "d", "e", "f", "g", "h"].map(Symbol::from);
+	let sub = Function::with_parameter_order(
+		FuncSig {
+			name: Some(Symbol::from("sub")),
+			parameters: Some(names.iter().map(|n| (*n, int)).collect()),
+			return_type: Some(int),
+			result: None,
+		},
+		names.to_vec(),
+		vec![
+			CallType::Operate(
+				Symbol::from("return"),
+				names[0],
+				Operation::Sub,
+				names[7],
+			),
+			CallType::Return(Symbol::from("return")),
+		],
+	);
+	let mut program = parse(String::from(
+		"fn main() { ret sub(9, 0, 0, 0, 0, 0, 0, 4) }",
+	));
+	let sorted = Function::new(sub.signature.clone(), Vec::new());
+	assert_eq!(sorted.parameter_order, names.to_vec());
+	program.functions.push(sub);
+	let value = Interpreter::new(&program, &mut Vec::new()).run();
+	assert_eq!(value, Ok(Value::Int(5)));
+
+	// errors
+	assert_eq!(
+		run("fn main() { ret 1 / 0 }").0.map_err(|e| e.error),
+		Err(RuntimeError::DivisionByZero)
+	);
+	assert_eq!(
+		run("fn main() { foo() }").0.map_err(|e| e.error),
+		Err(RuntimeError::UnknownFunction(String::from("foo")))
+	);
+
+	// recursion which never ends stops with the calls that were running,
+	// which needs a bigger stack than tests get in a debug build
+	let deep = std::thread::Builder::new().stack_size(64 << 20);
+	let code = "fn f(n: int) { f(n + 1) } fn main() { f(0) }";
+	let deep = deep.spawn(move || run(code).0.unwrap_err());
+	let error = deep.unwrap().join().unwrap();
+	assert_eq!(error.error, RuntimeError::StackOverflow);
+	assert_eq!(error.backtrace.len(), MAX_DEPTH);
+	assert_eq!(error.backtrace.last().unwrap().name, "main");
+
+	// escapes are read from left to right
+	let escaped = |token: &str| Value::from_literal(token).unwrap().to_string();
+	assert_eq!(escaped(r#""a\\nb""#), "a\\nb");
+	assert_eq!(escaped(r#""\\\"""#), "\\\"");
+	assert_eq!(escaped(r#""\t\n\q""#), "\t\n\\q");
+}
+
+#[test]
+fn backtrace_test() {
+	let code = "result div(a: int, b: int): int {
+	fn { ret a / b }
+}
+
+fn main() {
+	print(div(1, 0))
+}";
+	let program = parse(String::from(code));
+	let error = Interpreter::with_source(&program, code, Vec::new())
+		.run()
+		.unwrap_err();
+
+	// the innermost frame comes first, and each points at its statement
+	assert_eq!(error.error, RuntimeError::DivisionByZero);
+	assert_eq!(
+		error.backtrace,
+		vec![
+			Frame {
+				name: String::from("div"),
+				implementation: Some(0),
+				span: Some(span::Span::new(40, 49))
+			},
+			Frame {
+				name: String::from("main"),
+				implementation: None,
+				span: Some(span::Span::new(68, 84))
+			}
+		]
+	);
+
+	assert_eq!(
+		error.render("div.ro", code),
+		"error: division by zero
+ --> div.ro:2:7
+  |
+2 | \tfn { ret a / b }
+  | \t     ^^^^^^^^^ in `div` (implementation 0)
+note: called from `main`
+ --> div.ro:6:2
+  |
+6 | \tprint(div(1, 0))
+  | \t^^^^^^^^^^^^^^^^
+"
+	);
+
+	// even when the statement is a later one
+	let code = "fn main() {\n\tvar x = 1\n\tprint(x +)\n}";
+	let program = parse(String::from(code));
+	let error = Interpreter::with_source(&program, code, Vec::new())
+		.run()
+		.unwrap_err();
+	assert_eq!(error.backtrace[0].span, Some(span::Span::new(24, 34)));
+
+	// and in closures, in the interpreter and the VM
+	let code = "fn main() {
+	var d = 0
+	var f = fn(x: int) {
+		fn g(y: int) { return y / d }
+		var z = x
+		return g(z)
+	}
+	print(f(1))
+}";
+	let statement = |text: &str| {
+		let start = code.find(text).unwrap();
+		Some(span::Span::new(start, start + text.len()))
+	};
+	let spans = vec![
+		statement("return y / d"),
+		statement("return g(z)"),
+		statement("print(f(1))"),
+	];
+	let program = parse(String::from(code));
+	let error = Interpreter::with_source(&program, code, Vec::new())
+		.run()
+		.unwrap_err();
+	let found: Vec<_> = error.backtrace.iter().map(|f| f.span).collect();
+	assert_eq!(found, spans);
+	let module = bytecode::compile(&program);
+	let error = vm::Vm::with_source(&module, code, Vec::new())
+		.run()
+		.unwrap_err();
+	let found: Vec<_> = error.backtrace.iter().map(|f| f.span).collect();
+	assert_eq!(found, spans);
+}
+
+#[test]
+fn repl_test() {
+	let mut repl = Repl::new();
+	let mut output = Vec::new();
+	let mut feed = |line: &str| -> String {
+		output.clear();
+		repl.feed(line, &mut output).unwrap();
+		String::from_utf8(output.clone()).unwrap()
+	};
+
+	// expressions print their values, but statements don't
+	assert_eq!(feed("1 + 2 * 3"), "7\n");
+	assert_eq!(feed("var x = 4"), "");
+	assert_eq!(feed("x * 2"), "8\n");
+	assert_eq!(feed("print(x)"), "4\n");
+
+	// definitions can take more than one line
+	assert_eq!(feed("fn double(n: int): int {"), "");
+	assert_eq!(feed("return n * 2"), "");
+	assert_eq!(feed("}"), "");
+	assert_eq!(feed("double(x)"), "8\n");
+
+	// and can be replaced
+	assert_eq!(feed("fn double(n: int): int { return n + n + 1 }"), "");
+	assert_eq!(feed("double(1)"), "3\n");
+
+	// errors are reported without losing anything
+	assert_eq!(feed("x / 0"), "error: division by zero\n");
+	assert_eq!(feed("double(x)"), "9\n");
+
+	// and definitions with problems aren't kept
+	let bad = [
+		"fn f() { var = }",
+		"fn => nothing { ret 1 }",
+		"result (x) { }",
+		"fn g(: int { }",
+	];
+	for definition in bad {
+		assert!(feed(definition).starts_with("error: "), "{}", definition);
+	}
+	assert_eq!(feed("double(x)"), "9\n");
+	assert_eq!(repl.definitions.len(), 1);
+	assert!(check::check(&repl.source()).is_empty());
+}
+
+#[test]
+fn check_test() {
+	let messages = |code: &str| -> Vec<String> {
+		check::check(code)
+			.into_iter()
+			.map(|d| d.primary.message)
+			.collect()
+	};
+
+	// the benchmark is fine
+	let code = std::fs::read_to_string("src/benchmark.ro").unwrap();
+	assert!(messages(&code).is_empty());
+
+	// syntax errors
+	assert_eq!(
+		messages("fn main() { print(1 }"),
+		vec![
+			"`}` doesn't match the `(` before it",
+			"this `{` is never closed"
+		]
+	);
+	assert_eq!(
+		messages("var x fn => nothing {}"),
+		vec![
+			"expected `fn` or `result`, found `var`",
+			"expected `fn` or `result`, found `x`",
+			"there's no result named `nothing`"
+		]
+	);
+
+	// statements with something left over, and `var` without a name
+	assert_eq!(
+		messages("fn main() { var x = 1 2 3 }"),
+		vec!["unexpected `2`", "unexpected `3`"]
+	);
+	assert_eq!(
+		messages("fn main() { var = }"),
+		vec!["expected a name after `var`"]
+	);
+	let diagnostics = check::check("fn main() {\n\tvar = 1\n}");
+	assert_eq!(diagnostics[0].primary.span, Some(span::Span::new(17, 18)));
+
+	// names which don't refer to anything
+	assert_eq!(
+		messages(
+			"result add(one: int, two: int) { fn(first = three) { ret first } }
+			fn main() { x = 1 foo(y) }"
+		),
+		vec![
+			"`x` is set before it's declared with `var`",
+			"unknown variable `y`",
+			"unknown function `foo`",
+			"result `add` has no parameter `three`"
+		]
+	);
+
+	// types which don't match
+	assert_eq!(
+		messages(
+			"fn half(x: float): float { ret x / 2 }
+			fn main(): int { var s = \"a\" * 2 print(half(true)) ret half(1) }"
+		),
+		vec![
+			"can't apply * to string and int",
+			"argument 1 of `half` should be float, but it's bool",
+			"expected to return int, but this returns float"
+		]
+	);
+
+	// definitions without bodies, and brackets which aren't opened
+	assert_eq!(
+		messages("fn main() {} ) result"),
+		vec![
+			"unexpected `)`",
+			"expected a name and parameters after `result`",
+			"expected a body after `result`"
+		]
+	);
+	assert_eq!(messages("fn main()"), vec!["expected a body after `fn`"]);
+}
+
+#[test]
+fn cst_test() {
+	use cst::{SyntaxKind, SyntaxNode};
+
+	// the tree holds all of the code, including comments
+	let code = std::fs::read_to_string("src/benchmark.ro").unwrap();
+	let (program, tree) = parse_with_cst(&code);
+	assert_eq!(tree.text(), code);
+	assert_eq!(tree.span(), span::Span::new(0, code.len()));
+	assert_eq!(tree.token_list(), tokenize(code.clone()));
+	assert_eq!(program, parse(code.clone()));
+
+	let comments: Vec<(SyntaxKind, String)> = tree
+		.tokens()
+		.into_iter()
+		.filter(|t| t.kind().is_trivia() && t.kind() != SyntaxKind::Whitespace)
+		.map(|t| (t.kind(), String::from(t.text())))
+		.collect();
+	assert_eq!(
+		comments,
+		vec![
+			(
+				SyntaxKind::BlockComment,
+				String::from("/*\n * block comment\n */")
+			),
+			(
+				SyntaxKind::LineComment,
+				String::from("//* a comment that has been toggled off")
+			),
+			(SyntaxKind::BlockComment, String::from("*/")),
+			(SyntaxKind::DocComment, String::from("/// docstring")),
+			(SyntaxKind::LineComment, String::from("// comment")),
+		]
+	);
+
+	// definitions get their own nodes
+	let kinds: Vec<SyntaxKind> =
+		tree.child_nodes().iter().map(SyntaxNode::kind).collect();
+	assert_eq!(
+		kinds,
+		vec![
+			SyntaxKind::Result,
+			SyntaxKind::Function,
+			SyntaxKind::Function
+		]
+	);
+	let result = &tree.child_nodes()[0];
+	let body = &result.child_nodes()[1];
+	assert_eq!(body.kind(), SyntaxKind::Body);
+	assert_eq!(body.child_nodes().len(), 4);
+	assert_eq!(
+		result.child_nodes()[0].text(),
+		"add(one: float, two: float) : float"
+	);
+
+	// tokens know where they are
+	let token = tree.token_at(code.find("add1").unwrap()).unwrap();
+	assert_eq!(token.kind(), SyntaxKind::Name);
+	assert_eq!(&code[token.span().start..token.span().end], "add1");
+
+	// edits keep everything else the same
+	let edited = token.with_text("add_one");
+	assert_eq!(edited.text(), code.replace("add1", "add_one"));
+	assert_eq!(tree.text(), code);
+}
+
+#[test]
+fn reparse_test() {
+	use span::Span;
+	use span::TextEdit;
+	use std::rc::Rc;
+
+	let code = std::fs::read_to_string("src/benchmark.ro").unwrap();
+	let tree = cst::parse(&code);
+	let edit = |tree: &cst::SyntaxNode,
+	            start: usize,
+	            end: usize,
+	            text: &str| {
+		let edit = TextEdit::new(Span::new(start, end), text);
+		let code = edit.apply(&tree.text());
+		let reparsed = cst::reparse(tree, &edit);
+		assert_eq!(reparsed.text(), code);
+		assert_eq!(reparsed.green(), cst::parse(&code).green(), "{:?}", edit);
+		reparsed
+	};
+	let shared = |old: &cst::SyntaxNode, new: &cst::SyntaxNode, i: usize| match (
+		&old.green().children[i],
+		&new.green().children[i],
+	) {
+		(cst::GreenElement::Node(a), cst::GreenElement::Node(b)) => {
+			Rc::ptr_eq(a, b)
+		}
+		_ => false,
+	};
+
+	// editing a body only replaces the item it's in, and the whitespace
+	// around it
+	let body = code.find("one' + two").unwrap();
+	let edited = edit(&tree, body, body + 4, "one");
+	assert_eq!(edited.child_nodes().len(), 3);
+	assert!(shared(&tree, &edited, 2)); // result add
+	assert!(!shared(&tree, &edited, 4)); // fn => add
+	assert!(shared(&tree, &edited, 6)); // fn main
+
+	// edits which change how the rest of the code is read
+	let main = code.find("fn main").unwrap();
+	let end = code.len();
+	for (start, end, text) in [
+		(main, main, "fn inserted() { return 1 }\n\n"),
+		(main, main, "/* "),
+		(main, main, "\"unclosed "),
+		(main, main, "// "),
+		(body, body, "{ "),
+		(end - 1, end, ""),
+		(0, 0, "fn first() {}"),
+		(end, end, "\nfn last() { print(\"é\") }"),
+		(0, end, "fn main() {}"),
+		(main - 2, main, ""),
+	] {
+		edit(&tree, start, end, text);
+	}
+
+	// every small edit gives the same tree as parsing from scratch
+	let pieces = ["{", "}", "/*", "*/", "\"", " ", "\n", "//", "fn", "x"];
+	let mut seed: usize = 7;
+	let mut tree = tree;
+	for _ in 0..500 {
+		seed = (seed * 1103515245 + 12345) % (1 << 31);
+		let code = tree.text();
+		let mut start = seed % (code.len() + 1);
+		while !code.is_char_boundary(start) {
+			start -= 1;
+		}
+		let end = (start + seed / 7 % 3).min(code.len());
+		let end = if code.is_char_boundary(end) {
+			end
+		} else {
+			start
+		};
+		tree = edit(&tree, start, end, pieces[seed / 11 % pieces.len()]);
+	}
+}
+
+#[test]
+fn format_test() {
+	use formatter::*;
+
+	let options = FormatOptions::default();
+	let code = "fn addtwo (one:float, two: float): float {return one+two}
+fn   main( ) {
+
+
+  // comment
+  var x = - 1  /* block */
+    print( addtwo(x , 2 ) )
+
+}
+fn=>add{ret 1}";
+	let formatted = "fn addtwo(one: float, two: float): float {
+	return one + two
+}
+fn main() {
+	// comment
+	var x = -1 /* block */
+	print(addtwo(x, 2))
+}
+fn => add {
+	ret 1
+}
+";
+	assert_eq!(format(code, &options), formatted);
+	assert!(is_formatted(formatted, &options));
+	assert!(!is_formatted(code, &options));
+
+	// the options change the spacing
+	let options = FormatOptions {
+		indent: Indent::Spaces(2),
+		space_before_colon: true,
+		space_around_arrow: false,
+	};
+	assert_eq!(
+		format("fn => add(one: float) {\nret one\n}", &options),
+		"fn=>add(one : float) {\n  ret one\n}\n"
+	);
+
+	// comments survive, and only the whitespace changes
+	let code = std::fs::read_to_string("src/benchmark.ro").unwrap();
+	let formatted = format(&code, &FormatOptions::default());
+	assert!(formatted.contains("\t\t//* a comment that has been toggled off\n"));
+	assert!(formatted.contains("\t\t*/\n"));
+	assert_eq!(tokenize(formatted.clone()), tokenize(code));
+	assert!(is_formatted(&formatted, &FormatOptions::default()));
+}
+
+#[test]
+fn print_test() {
+	let round_trip = |code: &str| {
+		let program = parse(String::from(code));
+		let printed = program.to_string();
+		assert_eq!(parse(printed.clone()), program, "{}", printed);
+		printed
+	};
+
+	let code = std::fs::read_to_string("src/benchmark.ro").unwrap();
+	round_trip(&code);
+
+	// temporaries are put back into the expressions they came from
+	assert_eq!(
+		round_trip(
+			"fn main(x: int, y: float): float {
+				var z : float = -(x + 1) * y - (y - 2) / f(x % 3, (y))
+				print(z, z * 2, (z), g(h(1)))
+				z = (x)
+				ret (z)
+			}"
+		),
+		"fn main(x: int, y: float): float {
+	var z = (0 - (x + 1)) * y - (y - 2) / f(x % 3, y)
+	print(z, z * 2, (z), g(h(1)))
+	z = x
+	return (z)
+}
+"
+	);
+
+	// including calls nested in calls, in every kind of statement
+	let code = "fn main(): int {
+	var x = f(g(h(1 + 2)), k(3))
+	print(f(g(1), h(2 + 3)))
+	var l = fn(a: int) {
+		return g(h(a) * 2)
+	}
+	return l(f(x))
+}
+";
+	assert_eq!(round_trip(code), code);
+
+	// calls on their own are written with their temporaries
+	let program = parse(String::from(code));
+	let calls = &program.functions[0].calls;
+	assert_eq!(calls[0].to_string(), "var x");
+	let lowered = parse(String::from("fn main() { var x = 1 + 2 * 3 }"));
+	let lowered: Vec<String> = lowered.functions[0]
+		.calls
+		.iter()
+		.map(|call| call.to_string())
+		.collect();
+	assert_eq!(lowered, ["var x", "x.0 = 2 * 3", "x = 1 + x.0"]);
+	let mut function = program.functions[0].clone();
+	function
+		.calls
+		.insert(0, CallType::Init(Symbol::from("x.0")));
+	assert_eq!(function.to_string(), code.trim_end());
+
+	// implementations rename the result's parameters
+	assert_eq!(
+		round_trip(
+			"result add(one: int, two: int): int { fn(first = one) { ret first } }
+			fn => add { return one + two }"
+		),
+		"result add(one: int, two: int): int {
+	fn(first = one) {
+		return first
+	}
+
+	fn => add {
+		return one + two
+	}
+}
+"
+	);
+
+	// generated expressions survive being printed
+	let mut seed: u64 = 7;
+	let mut random = |n: u64| {
+		seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+		(seed >> 33) % n
+	};
+	fn expression(random: &mut dyn FnMut(u64) -> u64, depth: u32) -> String {
+		match if depth == 0 { 0 } else { random(5) } {
+			0 => ["a", "b", "1", "2.5"][random(4) as usize].to_string(),
+			1 => format!("({})", expression(random, depth - 1)),
+			2 => format!("-{}", expression(random, depth - 1)),
+			3 => format!(
+				"f({}, {})",
+				expression(random, depth - 1),
+				expression(random, depth - 1)
+			),
+			_ => format!(
+				"{} {} {}",
+				expression(random, depth - 1),
+				["+", "-", "*", "/", "%"][random(5) as usize],
+				expression(random, depth - 1)
+			),
+		}
+	}
+	for _ in 0..200 {
+		let a = expression(&mut random, 4);
+		let b = expression(&mut random, 4);
+		round_trip(&format!(
+			"fn main(a: int, b: int) {{ var x = {} g({}) ret {} }}",
+			a, b, a
+		));
+	}
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_test() {
+	use json::*;
+
+	let code = std::fs::read_to_string("src/benchmark.ro").unwrap();
+	let program = parse(code.clone());
+	let document = program_to_json(&program);
+	assert!(document.starts_with("{\n  \"version\": 2,"));
+	assert_eq!(program_from_json(&document).unwrap(), program);
+
+	// calls are tagged with their kind
+	let value: serde_json::Value = serde_json::from_str(&document).unwrap();
+	assert_eq!(
+		value["program"]["functions"][0]["calls"][0],
+		serde_json::json!({"SetCall": ["print(0)", "add", ["1", "2"]]})
+	);
+
+	let tokens: serde_json::Value =
+		serde_json::from_str(&tokens_to_json(&tokenize_spanned("fn main")))
+			.unwrap();
+	assert_eq!(
+		tokens["tokens"][1],
+		serde_json::json!({"text": "main", "span": {"start": 3, "end": 7}})
+	);
+
+	// parameters are written in the same order every time
+	let program = parse(String::from(
+		"result add(two: int, one: int): int { fn(b = two, a = one) {} }",
+	));
+	let sorted = program_to_json(&program);
+	let at = |text: &str| sorted.find(text).unwrap();
+	assert!(at("\"one\": \"int\"") < at("\"two\": \"int\""));
+	assert!(at("\"a\": \"one\"") < at("\"b\": \"two\""));
+
+	// other versions of the schema aren't read
+	let old = document.replacen("\"version\": 2", "\"version\": 1", 1);
+	assert!(program_from_json(&old).is_err());
+}
+
+#[test]
+fn golden_test() {
+	// run with RO_BLESS set to write new snapshots instead of checking them
+	let bless = std::env::var_os("RO_BLESS").is_some();
+
+	let mut fixtures: Vec<std::path::PathBuf> = std::fs::read_dir("fixtures")
+		.unwrap()
+		.map(|entry| entry.unwrap().path())
+		.filter(|path| path.extension().is_some_and(|e| e == "ro"))
+		.collect();
+	fixtures.sort();
+	assert!(!fixtures.is_empty());
+
+	let mut failures = Vec::new();
+	for fixture in fixtures {
+		let code = std::fs::read_to_string(&fixture).unwrap();
+		let sexp = sexp::program(&parse(code));
+		let snapshot = fixture.with_extension("sexp");
+		if bless {
+			std::fs::write(&snapshot, sexp).unwrap();
+			continue;
+		}
+
+		match std::fs::read_to_string(&snapshot) {
+			Ok(expected) if expected == sexp => (),
+			Ok(_) => failures.push(format!("{} changed", snapshot.display())),
+			Err(_) => {
+				failures.push(format!("{} is missing", snapshot.display()))
+			}
+		}
+	}
+	assert!(
+		failures.is_empty(),
+		"{}\nrun with RO_BLESS=1 to update the snapshots",
+		failures.join("\n")
+	);
+}
+
+#[test]
+fn lex_test() {
+	let mut code = std::fs::read_to_string("src/benchmark.ro").unwrap();
+	code.push_str("fn f() { var s = \"a \\\" b\" ret 1.5 + s }");
+
+	// the tokens are slices of the code
+	let lexemes = lex_with_block_comments(&code);
+	for lexeme in &lexemes {
+		assert_eq!(lexeme.text, &code[lexeme.span.start..lexeme.span.end]);
+		let offset = lexeme.text.as_ptr() as usize - code.as_ptr() as usize;
+		assert_eq!(offset, lexeme.span.start);
+	}
+	assert_eq!(
+		lexemes.iter().map(|l| l.text).collect::<Vec<&str>>(),
+		tokenize_with_block_comments(code.clone())
+	);
+
+	// they're the same as the owned tokens, without the comments
+	let texts: Vec<&str> = lex(&code).iter().map(|l| l.text).collect();
+	assert_eq!(texts, tokenize_str(&code));
+	assert_eq!(
+		texts[texts.len() - 7..],
+		["=", "\"a \\\" b\"", "ret", "1.5", "+", "s", "}"][..]
+	);
+}
+
+#[test]
+fn lexer_test() {
+	// gives the lexer one byte at a time, to split tokens and characters
+	struct Trickle<'a>(&'a [u8]);
+	impl std::io::Read for Trickle<'_> {
+		fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+			match self.0.split_first() {
+				Some((byte, rest)) if !buffer.is_empty() => {
+					buffer[0] = *byte;
+					self.0 = rest;
+					Ok(1)
+				}
+				_ => Ok(0),
+			}
+		}
+	}
+
+	let mut code = std::fs::read_to_string("src/benchmark.ro").unwrap();
+	code.push_str("fn f() { print(\"ünïcödé \\\" ✓\") ret 1.5 }");
+	let tokens: Vec<Token> = Lexer::from_reader(Trickle(code.as_bytes()))
+		.map(Result::unwrap)
+		.collect();
+	assert_eq!(tokens, tokenize_spanned(&code));
+	assert!(tokens.iter().any(|t| t.text == "\"ünïcödé \\\" ✓\""));
+
+	let with_comments: Vec<Token> = Lexer::new(&code)
+		.with_block_comments()
+		.map(Result::unwrap)
+		.collect();
+	assert_eq!(with_comments, tokenize_spanned_with_block_comments(&code));
+
+	// looking ahead doesn't skip any tokens
+	let mut lexer = Lexer::new("fn main() {}");
+	assert_eq!(lexer.peek().unwrap().as_ref().unwrap().text, "fn");
+	assert_eq!(lexer.peek_nth(2).unwrap().as_ref().unwrap().text, "(");
+	assert_eq!(lexer.next().unwrap().unwrap().text, "fn");
+	assert_eq!(lexer.peek().unwrap().as_ref().unwrap().text, "main");
+	assert_eq!(lexer.count(), 5);
+
+	// readers can give code which isn't valid, which ends the token before it
+	let mut lexer = Lexer::from_reader(&b"fn ma\xffin"[..]);
+	assert_eq!(lexer.next().unwrap().unwrap().text, "fn");
+	assert_eq!(lexer.next().unwrap().unwrap().text, "ma");
+	assert!(matches!(lexer.next(), Some(Err(LexError::InvalidUtf8(5)))));
+	assert!(lexer.next().is_none());
+
+	// even when the invalid character starts at the end of a chunk
+	let mut code = b"fn ".to_vec();
+	code.resize(tokenizer::CHUNK_SIZE - 1, b'x');
+	code.extend_from_slice(b"\xe2(x");
+	let mut lexer = Lexer::from_reader(&code[..]);
+	assert_eq!(lexer.next().unwrap().unwrap().text, "fn");
+	let name = lexer.next().unwrap().unwrap();
+	assert_eq!(name.span, span::Span::new(3, tokenizer::CHUNK_SIZE - 1));
+	assert!(matches!(
+		lexer.next(),
+		Some(Err(LexError::InvalidUtf8(offset))) if offset == tokenizer::CHUNK_SIZE - 1
+	));
+	assert!(lexer.next().is_none());
+
+	// and when the code ends in the middle of a character
+	let mut lexer = Lexer::from_reader(&b"fn ma\xe2\x9c"[..]);
+	assert_eq!(lexer.nth(1).unwrap().unwrap().text, "ma");
+	assert!(matches!(lexer.next(), Some(Err(LexError::InvalidUtf8(5)))));
+}
+
+#[test]
+fn symbol_test() {
+	// the same name always gives the same symbol
+	let add = Symbol::intern("add");
+	assert_eq!(add, Symbol::from(String::from("add")));
+	assert_ne!(add, Symbol::intern("sub"));
+	assert_eq!(add.as_str(), "add");
+	assert_eq!(add, "add");
+	assert_eq!(add.to_string(), "add");
+
+	// symbols sort by their names, not by when they were interned
+	let mut names = vec![Symbol::intern("zeta"), add, Symbol::intern("beta")];
+	names.sort();
+	assert_eq!(names, vec!["add", "beta", "zeta"]);
+
+	// names only used by a dropped arena are freed when they're collected
+	let kept = Symbol::intern("kept");
+	let arena = Arena::new();
+	let (stale, shared) = arena.enter(|| {
+		for i in 0..1000 {
+			Symbol::intern(&format!("unused {}", i));
+		}
+		(Symbol::intern("stale"), Symbol::intern("kept"))
+	});
+	assert_eq!(shared, kept);
+	assert_eq!(stale, "stale");
+	drop(arena);
+	assert_eq!(unsafe { Symbol::collect() }, 1001);
+	assert_eq!(kept, "kept");
+	assert_ne!(Symbol::intern("stale"), stale);
+	assert!(std::panic::catch_unwind(|| stale.as_str()).is_err());
+
+	// every name in the AST is interned
+	let program = parse(String::from("fn add(one: int): int { ret one }"));
+	let function = &program.functions[0];
+	assert_eq!(function.signature.name, Some(add));
+	assert_eq!(function.parameter_order, vec![Symbol::intern("one")]);
+	assert_eq!(
+		function.calls,
+		vec![CallType::Return(Symbol::intern("one"))]
+	);
+}
+
+#[test]
+fn parser_test() {
+	// the parser gives the same program as the token list parsers
+	let code = std::fs::read_to_string("src/benchmark.ro").unwrap();
+	let tokens = tokenize(code);
+	let program = Parser::new(&tokens).program();
+	assert_eq!(program, ProgramParser::from_tokens(tokens).parse());
+	assert_eq!(program.results[0].functions.len(), 5);
+
+	// statements can be read with or without breaking up expressions
+	let tokens = tokenize(String::from("var x = f(1 + 2) * 3 ret x"));
+	assert_eq!(
+		Parser::new(&tokens).without_lowering().statements(),
+		CallType::vec_from_tokens(tokens.clone())
+	);
+	assert_eq!(
+		Parser::new(&tokens).statements(),
+		CallType::sets_to_ops(CallType::vec_from_tokens(tokens.clone()))
+	);
+
+	// signatures stop at the body
+	let tokens = tokenize(String::from("fn add(one: int, two = b): int {"));
+	let mut parser = Parser::new(&tokens);
+	parser.skip();
+	let (signature, order) = parser.signature();
+	assert_eq!(signature.to_string(), "add(one: int, two: b): int");
+	assert_eq!(order, vec!["one", "two"]);
+	assert_eq!(parser.peek(), Some("{"));
+
+	// code which doesn't make sense is skipped instead of panicking
+	for code in ["fn", "fn f(", "result", "fn f { var }", "fn f { x = ) }"] {
+		Parser::new(&tokenize(String::from(code))).program();
+	}
+
+	// and the parser always finishes, even when a body closes the parameters
+	let (sender, receiver) = std::sync::mpsc::channel();
+	std::thread::spawn(move || {
+		for code in ["fn ( }", "fn f(a }", "result r(a, 1 } fn g(} {", "fn f("]
+		{
+			parse(String::from(code));
+		}
+		let tokens = vec!["(", "a", "}"].into_iter().map(String::from);
+		FuncSig::from_tokens(tokens.collect());
+		Repl::new().feed("fn f( }", &mut Vec::new()).unwrap();
+		sender.send(()).unwrap();
+	});
+	let finished = receiver.recv_timeout(std::time::Duration::from_secs(5));
+	assert!(finished.is_ok(), "the parser didn't finish");
+}
+
+#[test]
+fn closure_test() {
+	let code = "fn main() {
+	var base = 10
+	fn add(x: int): int {
+		return x + base
+	}
+	var twice = fn(x: int): int {
+		return x * 2
+	}
+	print(add(1), twice(add(2)))
+	print(apply(fn(y: int) {
+		return y - base
+	}, 5))
+}
+
+fn apply(f: fn, v: int) {
+	return f(v)
+}
+";
+
+	// local functions are stored in variables, with their bodies parsed
+	let program = parse(String::from(code));
+	let main = &program.functions[0];
+	let add = Function {
+		signature: FuncSig {
+			name: Some(Symbol::from("add")),
+			parameters: Some(HashMap::from([(
+				Symbol::from("x"),
+				Symbol::from("int"),
+			)])),
+			return_type: Some(Symbol::from("int")),
+			result: None,
+		},
+		calls: vec![
+			CallType::Operate(
+				Symbol::from("return"),
+				Symbol::from("x"),
+				Operation::Add,
+				Symbol::from("base"),
+			),
+			CallType::Return(Symbol::from("return")),
+		],
+		parameter_order: vec![Symbol::from("x")],
+	};
+	assert_eq!(main.calls[2], CallType::Closure(Symbol::from("add"), add));
+	assert!(matches!(
+		&main.calls[4],
+		CallType::Closure(var, f) if *var == "twice" && f.signature.name.is_none()
+	));
+
+	// they're printed where they were written
+	assert_eq!(program.to_string(), code);
+
+	// closures can see the variables around them
+	let mut output = Vec::new();
+	let value = Interpreter::new(&program, &mut output).run();
+	assert_eq!(value, Ok(Value::Void));
+	assert_eq!(String::from_utf8(output).unwrap(), "11 24\n-5\n");
+
+	// the resolver knows which names are in scope inside of them
+	let messages = |code: &str| -> Vec<String> {
+		check::check(code)
+			.into_iter()
+			.map(|d| d.primary.message)
+			.collect()
+	};
+	assert!(messages(code).is_empty());
+	assert_eq!(
+		messages(
+			"fn main() { inner(1) fn inner(x: int) { ret x + y } inner(2) }"
+		),
+		vec!["unknown variable `y`", "unknown function `inner`"]
+	);
+}
+
+#[test]
+fn codegen_test() {
+	// writes each operation on its own line
+	#[derive(Default)]
+	struct Listing(Vec<String>);
+
+	impl codegen::Backend for Listing {
+		type Output = Vec<String>;
+
+		fn function(&mut self, name: &str, parameters: &[Symbol]) {
+			self.0.push(format!("fn {} {:?}", name, parameters));
+		}
+
+		fn declare(&mut self, var: Symbol) {
+			self.0.push(format!("var {}", var));
+		}
+
+		fn operate(
+			&mut self,
+			var: Symbol,
+			left: Symbol,
+			op: &Operation,
+			right: Symbol,
+		) {
+			self.0.push(format!("{} = {} {} {}", var, left, op, right));
+		}
+
+		fn move_value(&mut self, var: Symbol, value: Symbol) {
+			self.0.push(format!("{} = {}", var, value));
+		}
+
+		fn call(
+			&mut self,
+			var: Option<Symbol>,
+			function: Symbol,
+			args: &[Symbol],
+		) {
+			let var = var.map_or(String::new(), |v| format!("{} = ", v));
+			self.0.push(format!("{}{}{:?}", var, function, args));
+		}
+
+		fn ret(&mut self, value: Symbol) {
+			self.0.push(format!("return {}", value));
+		}
+
+		fn finish(self) -> Vec<String> {
+			self.0
+		}
+	}
+
+	// the benchmark compiles end to end, with every implementation
+	let code = std::fs::read_to_string("src/benchmark.ro").unwrap();
+	assert_eq!(
+		codegen::compile(&code, Listing::default()).unwrap(),
+		vec![
+			"fn add.0 [\"one\", \"two\"]",
+			"var return",
+			"return = one + two",
+			"return return",
+			"fn add.1 [\"one\", \"two\"]",
+			"var temp",
+			"temp = one + two",
+			"return temp",
+			"fn add.2 [\"one\", \"two\"]",
+			"var first",
+			"first = one",
+			"var second",
+			"second = two",
+			"var return",
+			"return = first + second",
+			"return return",
+			"fn add.3 [\"one\", \"two\"]",
+			"var return",
+			"return = one + two",
+			"return return",
+			"fn add.4 [\"one\", \"two\"]",
+			"var one'",
+			"var one'.0",
+			"one'.0 = one + 1",
+			"one' = one'.0 - 1",
+			"var return",
+			"return = one' + two",
+			"return return",
+			"fn add [\"one\", \"two\"]",
+			"var return",
+			"return = add.0[\"one\", \"two\"]",
+			"return return",
+			"fn main []",
+			"var print(0)",
+			"print(0) = add[\"1\", \"2\"]",
+			"print[\"print(0)\"]",
+		]
+	);
+
+	// programs with errors aren't compiled
+	assert!(codegen::compile("fn main() { f(x) }", Listing::default()).is_err());
+	assert!(codegen::compile(
+		"fn main() { var f = fn() { ret 1 } }",
+		Listing::default()
+	)
+	.is_err());
+}
+
+#[test]
+fn ir_test() {
+	// the benchmark runs its first implementation
+	let code = std::fs::read_to_string("src/benchmark.ro").unwrap();
+	let module = ir::lower(&parse(code)).unwrap();
+	assert_eq!(
+		module.to_string(),
+		"fn add(%0, %1): float {
+	%0 one: float
+	%1 two: float
+	%2 return: float
+bb0:
+	%2 = %0 + %1
+	return %2
+}
+
+fn main(): unknown {
+	%0 print(0): float
+bb0:
+	%0 = call add(1, 2)
+	call print(%0)
+	return void
+}
+"
+	);
+
+	// registers given different types are unknown
+	let module =
+		ir::lower(&parse(String::from("fn main() { var x = 1 x = 2.5 }")))
+			.unwrap();
+	assert_eq!(module.functions[0].registers[0].value_type, Type::Unknown);
+
+	// parameters which the result doesn't declare are errors
+	let code = "result sub(one: int): int { fn(a = two) { ret a } }";
+	let error = ir::lower(&parse(String::from(code))).unwrap_err();
+	assert_eq!(
+		error.primary.message,
+		"`two` isn't a parameter of the result, in `sub`"
+	);
+	let mut program = parse(String::from("result sub(one: int) {}"));
+	program.results[0].signature.parameters.clear();
+	let error = ir::lower(&program).unwrap_err();
+	assert_eq!(error.primary.message, "`one` doesn't have a type, in `sub`");
+
+	// blocks can jump and branch to each other
+	let mut function = ir::IrFunction::new(Symbol::from("pick"), Type::Int);
+	let condition = function.register(Type::Bool, None);
+	let yes = function.block();
+	let no = function.block();
+	function.parameters.push(condition);
+	function.blocks[0].terminator =
+		ir::Terminator::Branch(ir::Operand::Register(condition), yes, no);
+	function.blocks[yes.0].terminator =
+		ir::Terminator::Return(ir::Operand::Constant(Value::Int(1)));
+	function.blocks[no.0].terminator = ir::Terminator::Jump(yes);
+	assert_eq!(function.blocks[0].terminator.successors(), vec![yes, no]);
+	assert_eq!(
+		function.to_string(),
+		"fn pick(%0): int {
+	%0: bool
+bb0:
+	branch %0, bb1, bb2
+bb1:
+	return 1
+bb2:
+	jump bb1
+}"
+	);
+
+	// closures can't be lowered yet
+	let program = parse(String::from("fn main() { var f = fn() { ret 1 } }"));
+	assert!(ir::lower(&program).is_err());
+}
+
+#[test]
+fn ssa_test() {
+	// x is written before a branch and in one side of it
+	let mut function = ir::IrFunction::new(Symbol::from("pick"), Type::Int);
+	let condition = function.register(Type::Bool, Some(Symbol::from("c")));
+	let x = function.register(Type::Int, Some(Symbol::from("x")));
+	function.parameters.push(condition);
+	let yes = function.block();
+	let no = function.block();
+	let end = function.block();
+	let set = |value| {
+		ir::Instruction::Move(x, ir::Operand::Constant(Value::Int(value)))
+	};
+	function.blocks[0].instructions.push(set(1));
+	function.blocks[0].terminator =
+		ir::Terminator::Branch(ir::Operand::Register(condition), yes, no);
+	function.blocks[yes.0].instructions.push(set(2));
+	function.blocks[yes.0].terminator = ir::Terminator::Jump(end);
+	function.blocks[no.0].terminator = ir::Terminator::Jump(end);
+	function.blocks[end.0].terminator =
+		ir::Terminator::Return(ir::Operand::Register(x));
+	assert!(!ssa::is_ssa(&function));
+
+	// the first block dominates the others
+	let dominators = ssa::immediate_dominators(&function);
+	assert_eq!(
+		dominators,
+		vec![
+			None,
+			Some(ir::BlockId(0)),
+			Some(ir::BlockId(0)),
+			Some(ir::BlockId(0))
+		]
+	);
+
+	// the two values meet at a phi
+	ssa::construct(&mut function);
+	assert!(ssa::is_ssa(&function));
+	assert_eq!(
+		function.to_string(),
+		"fn pick(%0): int {
+	%0 c: bool
+	%1 x: int
+	%2 x: int
+	%3 x: int
+bb0:
+	%1 = 1
+	branch %0, bb1, bb2
+bb1:
+	%2 = 2
+	jump bb3
+bb2:
+	jump bb3
+bb3:
+	%3 = phi [bb1: %2], [bb2: %1]
+	return %3
+}"
+	);
+
+	// a loop reads the value from before it and from the last time around
+	let mut function = ir::IrFunction::new(Symbol::from("spin"), Type::Int);
+	let x = function.register(Type::Int, Some(Symbol::from("x")));
+	let body = function.block();
+	let one = ir::Operand::Constant(Value::Int(1));
+	function.blocks[0]
+		.instructions
+		.push(ir::Instruction::Move(x, one.clone()));
+	function.blocks[0].terminator = ir::Terminator::Jump(body);
+	function.blocks[body.0]
+		.instructions
+		.push(ir::Instruction::Operate(
+			x,
+			ir::Operand::Register(x),
+			Operation::Add,
+			one,
+		));
+	function.blocks[body.0].terminator = ir::Terminator::Jump(body);
+	ssa::construct(&mut function);
+	assert!(ssa::is_ssa(&function));
+	assert_eq!(
+		function.blocks[body.0].instructions[0].to_string(),
+		"%1 = phi [bb0: %0], [bb1: %2]"
+	);
+}
+
+#[test]
+fn opt_test() {
+	let optimize = |code: &str, manager: opt::PassManager| {
+		let mut module = ir::lower(&parse(String::from(code))).unwrap();
+		manager.run(&mut module);
+		module.functions[0].to_string()
+	};
+	let code = "fn f(one: float): float {
+		var one' = one + 1 - 1
+		var a = one * 2
+		var b = 2 * one
+		var unused
+		return one' + a + b
+	}";
+
+	// the sum folds away, b reuses a and the unused variable is removed
+	let optimized = "fn f(%0): float {
+	%0 one: float
+	%1 a: float
+	%2 return.0: float
+	%3 return: float
+bb0:
+	%1 = %0 * 2
+	%2 = %0 + %1
+	%3 = %2 + %1
+	return %3
+}";
+	assert_eq!(optimize(code, opt::PassManager::default()), optimized);
+
+	// passes can be turned off
+	let manager = opt::PassManager::default()
+		.without(opt::Pass::EliminateCommonSubexpressions);
+	assert!(optimize(code, manager).contains("2 * %0"));
+	let manager =
+		opt::PassManager::default().without(opt::Pass::EliminateDeadCode);
+	assert!(optimize(code, manager).contains("unused"));
+	assert_eq!(
+		opt::Pass::from_name("fold-constants"),
+		Some(opt::Pass::FoldConstants)
+	);
+
+	// each pass can be run on its own
+	let mut module = ir::lower(&parse(String::from(
+		"fn f(x: int): int { var y = 2 * 3 var z = y return x + z }",
+	)))
+	.unwrap();
+	let function = &mut module.functions[0];
+	ssa::construct(function);
+	assert!(opt::fold_constants(function));
+	assert!(function.to_string().contains("= 6\n"));
+	assert!(opt::propagate_copies(function));
+	assert!(function.to_string().contains("%0 + 6"));
+	assert!(opt::eliminate_dead_code(function));
+	assert!(!function.to_string().contains("y"));
+	assert!(!opt::eliminate_common_subexpressions(function));
+
+	// divisions which might fail aren't removed
+	let kept = optimize(
+		"fn f(x: int) { var y = 1 / x }",
+		opt::PassManager::default(),
+	);
+	assert!(kept.contains("1 / %0"));
+}
+
+#[test]
+fn c_test() {
+	let dir = std::env::temp_dir().join(format!("ro-c-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+
+	// compiles some code with the system C compiler, and runs it
+	let run_c = |name: &str, code: &str| {
+		let source = dir.join(format!("{}.c", name));
+		let binary = dir.join(name);
+		let c = c::program(&parse(String::from(code))).unwrap();
+		std::fs::write(&source, c).unwrap();
+		let status = std::process::Command::new("cc")
+			.args(["-std=c99", "-pedantic", "-Wall", "-Wextra", "-Werror"])
+			.arg(&source)
+			.arg("-o")
+			.arg(&binary)
+			.arg("-lm")
+			.status()
+			.unwrap();
+		assert!(status.success(), "{} didn't compile", name);
+		std::process::Command::new(&binary).output().unwrap()
+	};
+	let interpret = |code: &str| {
+		let program = parse(String::from(code));
+		let mut output = Vec::new();
+		Interpreter::new(&program, &mut output).run().unwrap();
+		String::from_utf8(output).unwrap()
+	};
+
+	// the compiled programs print the same things as the interpreter
+	let mut programs = vec![
+		(
+			"floats",
+			String::from(
+				"fn main() {
+					print(0.1 + 0.2, 1e21 * 1.0, 0.000015, 0 - 2.5)
+				}",
+			),
+		),
+		(
+			"builtins",
+			String::from(
+				"fn half(x: int): float { return x / 2.0 }
+				fn main() {
+					var s = string(half(7)) + \"\\t\" + string(false)
+					print(s, int(\" 42 \") * 2, float(\"1.25\"), int(3.9))
+					print(9 % 4, 0 - 7 / 2, print())
+				}",
+			),
+		),
+		(
+			"trigraphs",
+			String::from("fn main() { print(\"??= ??/ ??' ??( what?\") }"),
+		),
+	];
+	for name in ["benchmark", "literals", "results"] {
+		let file = match name {
+			"benchmark" => String::from("src/benchmark.ro"),
+			_ => format!("fixtures/{}.ro", name),
+		};
+		programs.push((name, std::fs::read_to_string(file).unwrap()));
+	}
+	for (name, code) in &programs {
+		let output = run_c(name, code);
+		assert!(output.status.success(), "{} failed", name);
+		let stdout = String::from_utf8(output.stdout).unwrap();
+		assert_eq!(stdout, interpret(code), "{} printed something else", name);
+	}
+
+	// question marks are escaped, so they can't make trigraphs
+	let c = c::program(&parse(String::from(&programs[2].1))).unwrap();
+	assert!(c.contains(r#""\?\?= \?\?/ \?\?' \?\?( what\?""#));
+
+	// errors exit with the interpreter's message
+	let output = run_c("divide", "fn main() { var x = 0 print(1 / x) }");
+	assert_eq!(output.status.code(), Some(1));
+	let stderr = String::from_utf8(output.stderr).unwrap();
+	assert_eq!(stderr, "error: division by zero\n");
+
+	// only values whose types are known can be compiled
+	let compile = |code: &str| c::program(&parse(String::from(code)));
+	assert!(compile("fn f(x) { return x } fn main() { f(1) }").is_err());
+	assert!(compile("fn main() { var f = fn(x: int) { ret x } }").is_err());
+	assert!(compile("fn other() {}").is_err());
+
+	std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn vm_test() {
+	let interpret = |code: &str| {
+		let program = parse(String::from(code));
+		let mut output = Vec::new();
+		let value = Interpreter::with_source(&program, code, &mut output).run();
+		(value, String::from_utf8(output).unwrap())
+	};
+	let run = |code: &str| {
+		let module = bytecode::compile(&parse(String::from(code)));
+		let mut output = Vec::new();
+		let value = vm::Vm::with_source(&module, code, &mut output).run();
+		(value, String::from_utf8(output).unwrap())
+	};
+
+	// the VM prints and returns the same things as the interpreter
+	let mut programs = vec![
+		String::from(
+			"fn half(x: int): float { return x / 2.0 }
+			fn main() {
+				var s = string(half(7)) + \"!\"
+				print(s, int(\" 42 \") * 2, float(\"1.25\"), 9 % 4, print())
+				return s
+			}",
+		),
+		std::fs::read_to_string("src/benchmark.ro").unwrap(),
+	];
+	for fixture in ["literals", "results", "closures"] {
+		let file = format!("fixtures/{}.ro", fixture);
+		programs.push(std::fs::read_to_string(file).unwrap());
+	}
+	for code in &programs {
+		assert_eq!(run(code), interpret(code), "{}", code);
+	}
+
+	// and fails in the same places, with the same backtraces
+	for code in [
+		"fn main() { f(0) } fn f(x: int) { var y = 1 / x }",
+		"fn main() { var x = 1 print(x) f(x, 0) }
+		fn f(a: int, b: int) { var y = a + 1 print(y / b) }",
+		"fn main() { missing(1) }",
+		"fn main() { print(x) }",
+		"result r(a: int) { fn { ret a } } fn main() { r(1, 2) }",
+		"fn main() { var f = fn(x: int) { ret int(x) } f(\"no\") }",
+	] {
+		let (value, _) = run(code);
+		assert!(value.is_err(), "{}", code);
+		assert_eq!(value, interpret(code).0, "{}", code);
+	}
+
+	// including when the recursion is too deep
+	let deep = std::thread::Builder::new().stack_size(64 << 20);
+	let deep = deep.spawn(move || {
+		for code in [
+			"fn f(n: int) { f(n + 1) } fn main() { f(0) }",
+			"fn main() { fn f(n: int) { f(n + 1) } f(0) }",
+		] {
+			let (value, _) = run(code);
+			assert!(value.is_err(), "{}", code);
+			assert_eq!(value, interpret(code).0, "{}", code);
+		}
+	});
+	deep.unwrap().join().unwrap();
+
+	// closures can call themselves, and see what they captured
+	let (value, _) = run("fn main() {
+		var n = 3
+		fn f(x: int) { return n + x }
+		var g = f
+		return g(1)
+	}");
+	assert_eq!(value, Ok(Value::Int(4)));
+
+	// the disassembler names the locals and constants
+	let module = bytecode::compile(&parse(String::from(
+		"fn main() { var x = 1 print(x + 2, \"hi\") }",
+	)));
+	let listing = "#0 main:
+	0000  constant 0        ; void
+	0001  store 0           ; x
+	0002  constant 1        ; 1
+	0003  store 0           ; x
+	0004  load 0            ; x
+	0005  constant 2        ; 2
+	0006  add
+	0007  store 1           ; print(0)
+	0008  load 1            ; print(0)
+	0009  constant 3        ; \"hi\"
+	0010  call print 2
+	0011  pop
+	0012  constant 0        ; void
+	0013  return
+";
+	assert_eq!(module.to_string(), listing);
+
+	// jumps aren't written by the compiler yet, but the VM runs them
+	let mut chunk = bytecode::Chunk::default();
+	let yes = chunk.constant(Value::Str(String::from("yes")));
+	let no = chunk.constant(Value::Bool(false));
+	chunk.code = vec![
+		bytecode::Instruction::Constant(no),
+		bytecode::Instruction::JumpUnless(4),
+		bytecode::Instruction::Constant(no),
+		bytecode::Instruction::Return,
+		bytecode::Instruction::Constant(yes),
+		bytecode::Instruction::Jump(3),
+	];
+	let module = bytecode::Module {
+		functions: vec![chunk],
+		globals: HashMap::from([(
+			Symbol::from("main"),
+			bytecode::Target::Function(0),
+		)]),
+		..Default::default()
+	};
+	let value = vm::Vm::new(&module, Vec::new()).run();
+	assert_eq!(value, Ok(Value::Str(String::from("yes"))));
+}
+
+#[test]
+fn wasm_test() {
+	use wasmi::Caller;
+	use wasmi::Engine;
+	use wasmi::Linker;
+	use wasmi::Store;
+
+	// reads a string out of the module's memory
+	fn string(caller: &Caller<'_, String>, address: i32) -> String {
+		let memory =
+			caller.get_export("memory").unwrap().into_memory().unwrap();
+		let data = memory.data(caller);
+		let address = address as usize;
+		let mut length = [0; 4];
+		length.copy_from_slice(&data[address..address + 4]);
+		let length = u32::from_le_bytes(length) as usize;
+		String::from_utf8_lossy(&data[address + 4..address + 4 + length])
+			.into_owned()
+	}
+
+	// runs one of the functions in a module
+	let run_module = |text: &str, function: &str, arguments: &[wasmi::Val]| {
+		let binary = ::wat::parse_str(text).unwrap();
+		let engine = Engine::default();
+		let module = wasmi::Module::new(&engine, &binary[..]).unwrap();
+		let mut store = Store::new(&engine, String::new());
+		let mut linker = Linker::<String>::new(&engine);
+		linker
+			.func_wrap(
+				"ro",
+				"print_int",
+				|mut c: Caller<'_, String>, v: i64| {
+					c.data_mut().push_str(&v.to_string())
+				},
+			)
+			.unwrap()
+			.func_wrap(
+				"ro",
+				"print_float",
+				|mut c: Caller<'_, String>, v: f64| {
+					c.data_mut().push_str(&v.to_string())
+				},
+			)
+			.unwrap()
+			.func_wrap(
+				"ro",
+				"print_string",
+				|mut c: Caller<'_, String>, s: i32| {
+					let s = string(&c, s);
+					c.data_mut().push_str(&s)
+				},
+			)
+			.unwrap()
+			.func_wrap("ro", "error", |c: Caller<'_, String>, s: i32| {
+				Err::<(), _>(wasmi::Error::new(string(&c, s)))
+			})
+			.unwrap()
+			.func_wrap("ro", "fmod", |a: f64, b: f64| a % b)
+			.unwrap();
+		let instance = linker
+			.instantiate(&mut store, &module)
+			.unwrap()
+			.start(&mut store)
+			.unwrap();
+		let function = instance.get_func(&store, function).unwrap();
+		let mut results = [wasmi::Val::I32(0)];
+		let ty = function.ty(&store);
+		if let Some(t) = ty.results().first() {
+			results[0] = wasmi::Val::default(*t);
+		}
+		let value = function
+			.call(&mut store, arguments, &mut results)
+			.map(|_| results[0].clone())
+			.map_err(|e| e.to_string());
+		(value, store.into_data())
+	};
+	let run = |code: &str, function: &str, arguments: &[wasmi::Val]| {
+		let text = wasm::program(&parse(String::from(code))).unwrap();
+		run_module(&text, function, arguments)
+	};
+	let interpret = |code: &str| {
+		let program = parse(String::from(code));
+		let mut output = Vec::new();
+		Interpreter::new(&program, &mut output).run().unwrap();
+		String::from_utf8(output).unwrap()
+	};
+
+	// the modules print the same things as the interpreter
+	let mut programs = vec![String::from(
+		"fn half(x: int): float { return x / 2.0 }
+		fn main() {
+			var s = string(0 - 42) + \" \" + string(false)
+			print(s, int(half(7)), float(2), 0.1 + 0.2, 5.5 % 2)
+			print(9 % 4, 0 - 7 / 2, 1e21 * 1.0, print(), \"\\\"q\\\"\")
+		}",
+	)];
+	for file in [
+		"src/benchmark.ro",
+		"fixtures/literals.ro",
+		"fixtures/results.ro",
+	] {
+		programs.push(std::fs::read_to_string(file).unwrap());
+	}
+	for code in &programs {
+		let (value, output) = run(code, "main", &[]);
+		assert!(value.is_ok(), "{:?}", value);
+		assert_eq!(output, interpret(code), "{}", code);
+	}
+
+	// functions and results are exported with their names
+	let code = std::fs::read_to_string("fixtures/results.ro").unwrap();
+	let arguments = [wasmi::Val::F64(2.0.into()), wasmi::Val::F64(4.0.into())];
+	let (value, _) = run(&code, "area", &arguments);
+	assert_eq!(value.map(|v| v.f64().map(f64::from)), Ok(Some(8.0)));
+
+	// errors trap, with the interpreter's message
+	let (value, output) =
+		run("fn main() { print(1) var x = 0 print(1 / x) }", "main", &[]);
+	assert!(value.unwrap_err().contains("division by zero"));
+	assert_eq!(output, "1\n");
+
+	// functions with more than one block jump between them in a loop
+	let mut function = ir::IrFunction::new(Symbol::from("pick"), Type::Int);
+	let condition = function.register(Type::Bool, Some(Symbol::from("c")));
+	let x = function.register(Type::Int, Some(Symbol::from("x")));
+	function.parameters.push(condition);
+	let yes = function.block();
+	let no = function.block();
+	let end = function.block();
+	let set = |value| {
+		ir::Instruction::Move(x, ir::Operand::Constant(Value::Int(value)))
+	};
+	function.blocks[0].instructions.push(set(1));
+	function.blocks[0].terminator =
+		ir::Terminator::Branch(ir::Operand::Register(condition), yes, no);
+	function.blocks[yes.0].instructions.push(set(2));
+	function.blocks[yes.0].terminator = ir::Terminator::Jump(end);
+	function.blocks[no.0].terminator = ir::Terminator::Jump(end);
+	function.blocks[end.0].terminator =
+		ir::Terminator::Return(ir::Operand::Register(x));
+	ssa::construct(&mut function);
+	let module = typed::TypedModule {
+		definitions: vec![typed::Definition {
+			name: Symbol::from("pick"),
+			implementation: None,
+			function,
+		}],
+		callees: HashMap::from([(Symbol::from("pick"), 0)]),
+	};
+	let text = wasm::module(&module).unwrap();
+	assert!(text.contains("br_table"));
+	for (condition, expected) in [(1, 2), (0, 1)] {
+		let arguments = [wasmi::Val::I32(condition)];
+		let (value, _) = run_module(&text, "pick", &arguments);
+		assert_eq!(value.map(|v| v.i64()), Ok(Some(expected)));
+	}
+
+	// values whose types aren't known can't be compiled
+	let compile = |code: &str| wasm::program(&parse(String::from(code)));
+	assert!(compile("fn f(x) { return x } fn main() { f(1) }").is_err());
+	assert!(compile("fn main() { print(string(1.5)) }").is_err());
+}
+
+#[test]
+fn analysis_test() {
+	use analysis::Analysis;
+	use analysis::SymbolKind;
+
+	let code = std::fs::read_to_string("src/benchmark.ro").unwrap();
+	let analysis = Analysis::new(&code);
+	assert!(analysis.diagnostics.is_empty());
+	let at = |text: &str| code.find(text).unwrap();
+	let names = |scope: Vec<analysis::Definition>| -> Vec<String> {
+		scope.into_iter().map(|d| d.name).collect()
+	};
+
+	// results are listed with their implementations
+	let outline = analysis.outline();
+	let outline: Vec<(&str, usize)> = outline
+		.iter()
+		.map(|o| (o.name.as_str(), o.children.len()))
+		.collect();
+	assert_eq!(outline, vec![("add", 5), ("main", 0)]);
+
+	// calls go to the definition of what they call
+	let call = at("add(1, 2)");
+	let add = analysis.definition(call).unwrap();
+	assert_eq!(add.kind, SymbolKind::Result);
+	assert_eq!(add.span.map(|s| s.start), Some(at("result add")));
+	assert!(analysis.definition(at("print")).unwrap().span.is_none());
+
+	// and so do variables and parameters
+	let temp = at("return temp") + "return ".len();
+	let variable = analysis.definition(temp).unwrap();
+	assert_eq!(variable.span.map(|s| s.start), Some(at("temp :")));
+	let first = at("first + second");
+	let parameter = analysis.definition(first).unwrap();
+	assert_eq!(parameter.detail, "first = one");
+
+	// hovering shows the declaration and its doc comment
+	let (_, text) = analysis.hover(temp).unwrap();
+	assert_eq!(text, "```ro\nvar temp: float\n```\n\ndocstring");
+	let (_, text) = analysis.hover(call).unwrap();
+	assert_eq!(
+		text,
+		"```ro\nresult add(one: float, two: float) : float\n```"
+	);
+
+	// implementations see the result's parameters and their own
+	let scope = names(analysis.scope(first));
+	assert_eq!(scope[..4], ["first", "second", "one", "two"]);
+	assert!(scope.contains(&String::from("print")));
+
+	// variables are only in scope after they're declared
+	let scope = names(analysis.scope(at("var temp")));
+	assert!(!scope.contains(&String::from("temp")));
+	assert!(!names(analysis.scope(call)).contains(&String::from("one'")));
+
+	// completions are filtered by what's been typed
+	let code =
+		"fn main() {\n\tvar total = 1\n\tfn twice(x) { return x * 2 }\n\tt\n}";
+	let analysis = Analysis::new(code);
+	let offset = code.find("\tt\n").unwrap() + 2;
+	assert_eq!(names(analysis.completions(offset)), vec!["twice", "total"]);
+
+	// positions count lines from zero, and columns in UTF-16
+	let analysis = Analysis::new("fn main() {\n\tprint(\"é𝄞\", x)\n}");
+	let x = analysis.code.find('x').unwrap();
+	assert_eq!(analysis.position(x), (1, 14));
+	assert_eq!(analysis.offset(1, 14), x);
+
+	// code which parses finds the same definitions as code which doesn't
+	let code = std::fs::read_to_string("src/benchmark.ro").unwrap();
+	let parsed = Analysis::unchecked(&code);
+	let broken = Analysis::unchecked(&format!("{}\nfn broken(", code));
+	assert!(check::parse(&broken.code).is_err());
+	for name in parsed.names() {
+		let offset = name.span.start;
+		assert_eq!(parsed.definition(offset), broken.definition(offset));
+	}
+
+	// names are looked up in the same scopes that `check` uses
+	let code =
+		"fn main() {\n\tfn f() { var y = 1\n\treturn f() }\n\tprint(y)\n}";
+	let analysis = Analysis::new(code);
+	let message = &analysis.diagnostics[0].primary.message;
+	assert_eq!(message, "unknown variable `y`");
+	assert_eq!(analysis.definition(code.rfind('y').unwrap()), None);
+	let f = analysis.definition(code.rfind("f()").unwrap()).unwrap();
+	assert_eq!(f.kind, SymbolKind::Closure);
+	assert_eq!(f.span.map(|s| s.start), code.find("fn f"));
+}
+
+#[test]
+fn highlight_test() {
+	use highlight::Highlight;
+
+	let code = "/** adds */
+result add(one: int, two: int): int {
+	fn(first = one) {
+		return first + two
+	}
+}
+
+fn main() {
+	// prints
+	var x: bool = true
+	var f = fn(g: fn) { return g() }
+	print(add(1, 2), \"x\", x, unknown)
+}";
+	let highlights: Vec<(&str, &str)> = highlight::highlight(code)
+		.into_iter()
+		.map(|(span, h)| (&code[span.start..span.end], h.name()))
+		.collect();
+	let expected = vec![
+		("/** adds */", "doc-comment"),
+		("result", "keyword"),
+		("add", "result"),
+		("(", "operator"),
+		("one", "parameter"),
+		(":", "operator"),
+		("int", "type"),
+		(",", "operator"),
+		("two", "parameter"),
+		(":", "operator"),
+		("int", "type"),
+		(")", "operator"),
+		(":", "operator"),
+		("int", "type"),
+		("{", "operator"),
+		("fn", "keyword"),
+		("(", "operator"),
+		("first", "parameter"),
+		("=", "operator"),
+		("one", "parameter"),
+		(")", "operator"),
+		("{", "operator"),
+		("return", "keyword"),
+		("first", "parameter"),
+		("+", "operator"),
+		("two", "parameter"),
+		("}", "operator"),
+		("}", "operator"),
+		("fn", "keyword"),
+		("main", "function"),
+		("(", "operator"),
+		(")", "operator"),
+		("{", "operator"),
+		("// prints", "comment"),
+		("var", "keyword"),
+		("x", "variable"),
+		(":", "operator"),
+		("bool", "type"),
+		("=", "operator"),
+		("true", "keyword"),
+		("var", "keyword"),
+		("f", "variable"),
+		("=", "operator"),
+		("fn", "keyword"),
+		("(", "operator"),
+		("g", "parameter"),
+		(":", "operator"),
+		("fn", "type"),
+		(")", "operator"),
+		("{", "operator"),
+		("return", "keyword"),
+		("g", "parameter"),
+		("(", "operator"),
+		(")", "operator"),
+		("}", "operator"),
+		("print", "function"),
+		("(", "operator"),
+		("add", "result"),
+		("(", "operator"),
+		("1", "number"),
+		(",", "operator"),
+		("2", "number"),
+		(")", "operator"),
+		(",", "operator"),
+		("\"x\"", "string"),
+		(",", "operator"),
+		("x", "variable"),
+		(",", "operator"),
+		(")", "operator"),
+		("}", "operator"),
+	];
+	assert_eq!(highlights, expected);
+	assert_eq!(Highlight::ALL.len(), 11);
+
+	// a tree which was reparsed after an edit is highlighted like new code,
+	// which is how the language server keeps up with changes
+	let start = analysis::offset(code, 11, 26);
+	assert_eq!(start, code.find("unknown").unwrap());
+	let edit = span::TextEdit::new(span::Span::new(start, start + 7), "f(add)");
+	let tree = cst::reparse(&cst::parse(code), &edit);
+	let edited = edit.apply(code);
+	let analysis = analysis::Analysis::new(&edited);
+	assert_eq!(
+		highlight::highlight_tree(&analysis, &tree),
+		highlight::highlight(&edited)
+	);
+
+	// the HTML has every character of the code, escaped
+	assert_eq!(
+		highlight::html("fn f() { ret \"<&>\" }"),
+		"<pre class=\"ro\"><code><span class=\"ro-keyword\">fn</span> \
+		 <span class=\"ro-function\">f</span>\
+		 <span class=\"ro-operator\">(</span>\
+		 <span class=\"ro-operator\">)</span> \
+		 <span class=\"ro-operator\">{</span> \
+		 <span class=\"ro-keyword\">ret</span> \
+		 <span class=\"ro-string\">&quot;&lt;&amp;&gt;&quot;</span> \
+		 <span class=\"ro-operator\">}</span></code></pre>\n"
+	);
+}
+
+#[test]
+fn refactor_test() {
+	use analysis::Analysis;
+
+	let code = "/// adds two numbers
+result add(one: int, two: int): int {
+	fn(first = one) {
+		return first + two
+	}
+	fn(one) {
+		return one * two
+	}
+}
+
+fn => add {
+	return one - two
+}
+
+fn main() {
+	var one = 1
+	print(add(one,   2))
+}
+";
+	assert!(check::check(code).is_empty());
+	let analysis = Analysis::new(code);
+	let rename = |at: &str, name: &str| -> Result<String, String> {
+		let edits = refactor::rename(&analysis, code.find(at).unwrap(), name)
+			.map_err(|d| d.primary.message)?;
+		let mut renamed = String::from(code);
+		for edit in edits.iter().rev() {
+			renamed = edit.apply(&renamed);
+		}
+		Ok(renamed)
+	};
+	let references = |at: &str| -> Vec<usize> {
+		refactor::references(&analysis, code.find(at).unwrap())
+			.into_iter()
+			.map(|span| span.start)
+			.collect()
+	};
+
+	// results are referred to by their implementations and calls
+	let calls = [
+		code.find("add(one: int").unwrap(),
+		code.find("add {").unwrap(),
+		code.find("add(one,").unwrap(),
+	];
+	assert_eq!(references("add(one,"), calls);
+	assert_eq!(
+		rename("add(one,", "sum").unwrap(),
+		code.replace("add(", "sum(").replace("=> add", "=> sum")
+	);
+
+	// a result's parameters are bound by its implementations, but not by
+	// other functions
+	assert_eq!(references("one: int").len(), 5);
+	assert_eq!(
+		rename("one: int", "left").unwrap(),
+		"/// adds two numbers
+result add(left: int, two: int): int {
+	fn(first = left) {
+		return first + two
+	}
+	fn(left) {
+		return left * two
+	}
+}
+
+fn => add {
+	return left - two
+}
+
+fn main() {
+	var one = 1
+	print(add(one,   2))
+}
+"
+	);
+	let first = code.find("first").unwrap();
+	let used = code.find("first +").unwrap();
+	assert_eq!(references("first +"), vec![first, used]);
+
+	// variables keep the formatting around them
+	let renamed = rename("one = 1", "x").unwrap();
+	assert!(renamed.ends_with("\tvar x = 1\n\tprint(add(x,   2))\n}\n"));
+	assert!(check::check(&renamed).is_empty());
+
+	// renames which would change the meaning of the code are refused
+	let refused = |at: &str, name: &str| rename(at, name).unwrap_err();
+	assert_eq!(
+		refused("one = 1", "add"),
+		"the rename would change what `add` refers to"
+	);
+	assert_eq!(
+		refused("one = 1", "print"),
+		"the rename would change what `print` refers to"
+	);
+	assert_eq!(
+		refused("first = one", "two"),
+		"the rename would change what `two` refers to"
+	);
+	assert_eq!(refused("print", "show"), "`print` is built in");
+	assert_eq!(refused("one = 1", "var"), "`var` can't be used as a name");
+	assert_eq!(refused("one = 1", "true"), "`true` can't be used as a name");
+	assert_eq!(refused("one = 1", "a b"), "`a b` can't be used as a name");
+	assert_eq!(refused("= 1", "x"), "there's nothing here to rename");
+
+	// so are renames which would define a name twice
+	let code = "fn f() {} fn main() { f() }";
+	let analysis = Analysis::new(code);
+	let error = refactor::rename(&analysis, code.find("f()").unwrap(), "main");
+	assert_eq!(
+		error.unwrap_err().primary.message,
+		"the rename would cause an error: `main` is defined more than once"
+	);
+}
@@ -1,4 +1,9 @@
-use std::iter::FromIterator;
+use crate::span::Span;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::io::Read;
 
 // a list of valid operators
 pub const OPERATORS: [&str; 17] = [
@@ -10,15 +15,44 @@ pub const OPERATORS: [&str; 17] = [
 const WHITESPACE: [char; 4] = [' ', '\n', '\t', '\r'];
 
 // tells the tokenizer what to expect next
-#[derive(PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 enum TokenizerMode {
 	LineComment,
 	Operator,
+	#[default]
 	Normal,
+	String,
 }
 
 pub type TokenList = Vec<String>;
 
+// a token, along with where it was found in the code
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Token {
+	pub text: String,
+	pub span: Span,
+}
+
+pub type SpannedTokenList = Vec<Token>;
+
+// a token which borrows its text from the code
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lexeme<'a> {
+	pub text: &'a str,
+	pub span: Span,
+}
+
+impl Lexeme<'_> {
+	/** Copies the text, so the token doesn't borrow the code */
+	pub fn to_token(self) -> Token {
+		Token {
+			text: String::from(self.text),
+			span: self.span,
+		}
+	}
+}
+
 /**
  * Converts Ro code into a list of tokens
  * Does not remove block comments
@@ -27,71 +61,150 @@ pub type TokenList = Vec<String>;
  * @param   code the code as a String
  */
 pub fn tokenize_with_block_comments(code: String) -> TokenList {
-	let mut tokens = TokenList::new(); // what will be returned
-	let mut current_token = String::new(); // the token currently being parsed
-	let mut mode = TokenizerMode::Normal; // the mode that tells the tokenizer what to expect
+	Lexer::new(&code)
+		.with_block_comments()
+		.flatten()
+		.map(|token| token.text)
+		.collect()
+}
+
+/**
+ * Converts Ro code into a list of tokens which know where they came from
+ * Does not remove block comments
+ */
+pub fn tokenize_spanned_with_block_comments(code: &str) -> SpannedTokenList {
+	Lexer::new(code).with_block_comments().flatten().collect()
+}
+
+/**
+ * Splits Ro code into tokens which borrow from it
+ * Does not remove block comments
+ */
+pub fn lex_with_block_comments(code: &str) -> Vec<Lexeme<'_>> {
+	let mut lexemes = Vec::new(); // what will be returned
+	let mut scanner = Scanner::default();
+	let mut push = |span: Span| {
+		lexemes.push(Lexeme {
+			text: &code[span.start..span.end],
+			span,
+		})
+	};
+
+	for (index, character) in code.char_indices() {
+		if let Some(span) = scanner.step(code, 0, index, character) {
+			push(span);
+		}
+	}
+	if let Some(span) = scanner.finish(code.len()) {
+		push(span);
+	}
+	lexemes
+}
+
+// the state of the tokenizer between two characters
+#[derive(Clone, Copy, Debug, Default)]
+struct Scanner {
+	start: Option<usize>, // where the current token started, if there is one
+	mode: TokenizerMode,  // the mode that tells the tokenizer what to expect
+}
+
+impl Scanner {
+	/**
+	 * Reads the character at `index`, returning a token if it ended one
+	 * `code` is the part of the code starting at `offset`, and it needs to
+	 * contain the current token and the character
+	 */
+	fn step(
+		&mut self,
+		code: &str,
+		offset: usize,
+		index: usize,
+		character: char,
+	) -> Option<Span> {
+		let end = index + character.len_utf8();
+		let text =
+			|start: usize, end: usize| &code[start - offset..end - offset];
+		let mut token = None;
 
-	// check each character in the String
-	for character in code.chars() {
 		// skip over the rest of the line if there's a line comment
-		if mode == TokenizerMode::LineComment {
+		if self.mode == TokenizerMode::LineComment {
 			if character == '\n' {
-				mode = TokenizerMode::Normal;
+				self.mode = TokenizerMode::Normal;
 			}
 
+		// keep everything in a string literal, including whitespace
+		} else if self.mode == TokenizerMode::String {
+			let first = self.start.unwrap_or(index);
+			if character == '"' && !text(first, end).ends_with("\\\"") {
+				token = Some(Span::new(first, end));
+				self.start = None;
+				self.mode = TokenizerMode::Normal;
+			}
+
+		// starts a string literal
+		} else if character == '"' {
+			token = self.start.map(|first| Span::new(first, index));
+			self.start = Some(index);
+			self.mode = TokenizerMode::String;
+
 		// end tokens at whitespace
 		} else if WHITESPACE.contains(&character) {
-			if current_token != String::new() {
-				tokens.push(current_token.clone());
-			}
-			current_token = String::new();
-			mode = TokenizerMode::Normal;
+			token = self.start.map(|first| Span::new(first, index));
+			self.start = None;
+			self.mode = TokenizerMode::Normal;
+
+		// runs if the character is an operator, other than a decimal point
+		} else if OPERATORS.contains(&text(index, end))
+			&& !(character == '.'
+				&& self.mode == TokenizerMode::Normal
+				&& self.start.is_some_and(|first| {
+					text(first, index).bytes().all(|c| c.is_ascii_digit())
+				})) {
+			let first = self.start.unwrap_or(index);
 
-		// runs if the character is an operator
-		} else if OPERATORS
-			.contains(&String::from_iter(vec![character]).as_str())
-		{
 			// runs if the character combined with the rest of the current token is an operator
-			if OPERATORS.contains(
-				&(current_token.clone() + &String::from_iter(vec![character]))
-					.as_str(),
-			) {
-				current_token.push(character.clone());
+			if OPERATORS.contains(&text(first, end)) {
+				self.start = Some(first);
 
 				// ignores the rest of the line if there's a line comment
-				if current_token == "//" {
-					current_token = String::new();
-					mode = TokenizerMode::LineComment;
+				if text(first, end) == "//" {
+					self.start = None;
+					self.mode = TokenizerMode::LineComment;
 
 				// otherwise makes sure the tokenizer expects an operator
 				} else {
-					mode = TokenizerMode::Operator;
+					self.mode = TokenizerMode::Operator;
 				}
 
 			// runs if it's now two operators
 			} else {
-				tokens.push(current_token.clone());
-				current_token = String::from_iter(vec![character]);
-				mode = TokenizerMode::Operator;
+				token = Some(Span::new(first, index));
+				self.start = Some(index);
+				self.mode = TokenizerMode::Operator;
 			}
 
 		// ends the operator token if it was expecting more operators
-		} else if mode == TokenizerMode::Operator {
-			tokens.push(current_token.clone());
-			current_token = String::from_iter(vec![character]);
-			mode = TokenizerMode::Normal;
+		} else if self.mode == TokenizerMode::Operator {
+			token = self.start.map(|first| Span::new(first, index));
+			self.start = Some(index);
+			self.mode = TokenizerMode::Normal;
 
 		// otherwise just add the character to the token
-		} else {
-			current_token.push(character);
+		} else if self.start.is_none() {
+			self.start = Some(index);
 		}
+
+		token
 	}
-	if current_token != String::new() {
-		tokens.push(current_token);
+
+	/** Ends the current token at the end of the code */
+	fn finish(&mut self, end: usize) -> Option<Span> {
+		self.mode = TokenizerMode::Normal;
+		self.start.take().map(|first| Span::new(first, end))
 	}
-	tokens
 }
 
+/** Removes block comments from a list of tokens */
 pub fn remove_block_comments(tokens: TokenList) -> TokenList {
 	let mut in_comment = false;
 	let mut new_list = TokenList::new();
@@ -109,6 +222,293 @@ pub fn remove_block_comments(tokens: TokenList) -> TokenList {
 	new_list
 }
 
+/** Removes block comments from a list of tokens which have spans */
+pub fn remove_spanned_block_comments(
+	tokens: SpannedTokenList,
+) -> SpannedTokenList {
+	let mut in_comment = false;
+	let mut new_list = SpannedTokenList::new();
+
+	for token in tokens {
+		if token.text == "/*" {
+			in_comment = true;
+		} else if token.text == "*/" {
+			in_comment = false;
+		} else if !in_comment {
+			new_list.push(token);
+		}
+	}
+
+	new_list
+}
+
+/**
+ * Converts Ro code into a list of tokens, without any comments
+ * Lexing a string can't fail, so there aren't any errors to skip
+ */
 pub fn tokenize(code: String) -> TokenList {
-	remove_block_comments(tokenize_with_block_comments(code))
+	tokenize_str(&code)
+}
+
+/** Converts Ro code into a list of tokens, without taking ownership of it */
+pub fn tokenize_str(code: &str) -> TokenList {
+	Lexer::new(code).flatten().map(|token| token.text).collect()
+}
+
+/** Removes block comments from a list of borrowed tokens */
+pub fn remove_lexeme_block_comments(
+	lexemes: Vec<Lexeme<'_>>,
+) -> Vec<Lexeme<'_>> {
+	let mut in_comment = false;
+	lexemes
+		.into_iter()
+		.filter(|lexeme| match lexeme.text {
+			"/*" => {
+				in_comment = true;
+				false
+			}
+			"*/" => {
+				in_comment = false;
+				false
+			}
+			_ => !in_comment,
+		})
+		.collect()
+}
+
+/** Splits Ro code into tokens which borrow from it, without any comments */
+pub fn lex(code: &str) -> Vec<Lexeme<'_>> {
+	remove_lexeme_block_comments(lex_with_block_comments(code))
+}
+
+/** Converts Ro code into a list of tokens, keeping track of their spans */
+pub fn tokenize_spanned(code: &str) -> SpannedTokenList {
+	Lexer::new(code).flatten().collect()
+}
+
+// the number of bytes a Lexer reads at a time
+pub(crate) const CHUNK_SIZE: usize = 8192;
+
+// a problem reading the code, which can only happen with a reader
+#[derive(Debug)]
+pub enum LexError {
+	Io(io::Error),
+	InvalidUtf8(usize), // the offset of the first invalid byte
+}
+
+impl fmt::Display for LexError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			LexError::Io(error) => {
+				write!(f, "couldn't read the code: {}", error)
+			}
+			LexError::InvalidUtf8(offset) => {
+				write!(f, "the code isn't valid UTF-8 at byte {}", offset)
+			}
+		}
+	}
+}
+
+impl std::error::Error for LexError {}
+
+impl From<io::Error> for LexError {
+	fn from(error: io::Error) -> Self {
+		LexError::Io(error)
+	}
+}
+
+/**
+ * Reads tokens one at a time, without reading all of the code first
+ * Only the current token is kept in memory, so it can lex huge files
+ */
+pub struct Lexer<R> {
+	reader: R,
+	bytes: Vec<u8>, // bytes which have been read but aren't a whole character yet
+	buffer: String, // code which has been read, starting at the current token
+	offset: usize,  // where the buffer starts in the code
+	position: usize, // where the next character starts in the code
+	scanner: Scanner,
+	keep_block_comments: bool,
+	in_comment: bool,
+	lookahead: VecDeque<Result<Token, LexError>>,
+	error: Option<LexError>, // an error after the code in the buffer
+	done: bool,
+}
+
+impl<'a> Lexer<&'a [u8]> {
+	/** Creates a lexer for some code */
+	pub fn new(code: &'a str) -> Self {
+		Lexer::from_reader(code.as_bytes())
+	}
+}
+
+impl<R: Read> Lexer<R> {
+	/** Creates a lexer which reads code as it's needed */
+	pub fn from_reader(reader: R) -> Self {
+		Lexer {
+			reader,
+			bytes: Vec::new(),
+			buffer: String::new(),
+			offset: 0,
+			position: 0,
+			scanner: Scanner::default(),
+			keep_block_comments: false,
+			in_comment: false,
+			lookahead: VecDeque::new(),
+			error: None,
+			done: false,
+		}
+	}
+
+	/** Makes the lexer return block comment tokens like `/*` and `*/` */
+	pub fn with_block_comments(mut self) -> Self {
+		self.keep_block_comments = true;
+		self
+	}
+
+	/** Looks at the next token without moving past it */
+	pub fn peek(&mut self) -> Option<&Result<Token, LexError>> {
+		self.peek_nth(0)
+	}
+
+	/** Looks at a token `n` tokens ahead, where 0 is the next one */
+	pub fn peek_nth(&mut self, n: usize) -> Option<&Result<Token, LexError>> {
+		while self.lookahead.len() <= n {
+			let token = self.lex_next()?;
+			self.lookahead.push_back(token);
+		}
+		self.lookahead.get(n)
+	}
+
+	/** Finds the next token, skipping over block comments if needed */
+	fn lex_next(&mut self) -> Option<Result<Token, LexError>> {
+		loop {
+			let token = match self.scan() {
+				Ok(Some(token)) => token,
+				Ok(None) => return None,
+				Err(error) => return Some(Err(error)),
+			};
+
+			if self.keep_block_comments {
+				return Some(Ok(token));
+			} else if token.text == "/*" {
+				self.in_comment = true;
+			} else if token.text == "*/" {
+				self.in_comment = false;
+			} else if !self.in_comment {
+				return Some(Ok(token));
+			}
+		}
+	}
+
+	/** Reads characters until a token ends */
+	fn scan(&mut self) -> Result<Option<Token>, LexError> {
+		loop {
+			// look at the characters which have already been read
+			while let Some(character) =
+				self.buffer[self.position - self.offset..].chars().next()
+			{
+				let index = self.position;
+				self.position += character.len_utf8();
+				let span = self.scanner.step(
+					&self.buffer,
+					self.offset,
+					index,
+					character,
+				);
+				if let Some(span) = span {
+					return Ok(Some(self.token(span)));
+				}
+			}
+
+			if self.done {
+				return Ok(None);
+			}
+			if let Some(error) = self.error.take() {
+				// the token before the error still ends there
+				if let Some(span) = self.scanner.finish(self.position) {
+					self.error = Some(error);
+					return Ok(Some(self.token(span)));
+				}
+				self.done = true;
+				return Err(error);
+			}
+
+			// the code before the current token isn't needed anymore
+			let keep = self.scanner.start.unwrap_or(self.position);
+			self.buffer.drain(..keep - self.offset);
+			self.offset = keep;
+
+			if !self.fill() {
+				self.done = true;
+				let span = self.scanner.finish(self.position);
+				return Ok(span.map(|span| self.token(span)));
+			}
+		}
+	}
+
+	/**
+	 * Reads more of the code, returning false at the end
+	 * An error is kept until the code before it has been lexed
+	 */
+	fn fill(&mut self) -> bool {
+		let mut chunk = [0; CHUNK_SIZE];
+		let read = loop {
+			match self.reader.read(&mut chunk) {
+				Ok(read) => break read,
+				Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+				Err(e) => {
+					self.error = Some(e.into());
+					return true;
+				}
+			}
+		};
+
+		// the end of the code can't be in the middle of a character
+		if read == 0 {
+			if self.bytes.is_empty() {
+				return false;
+			}
+			let offset = self.offset + self.buffer.len();
+			self.error = Some(LexError::InvalidUtf8(offset));
+			self.bytes.clear();
+			return true;
+		}
+
+		self.bytes.extend_from_slice(&chunk[..read]);
+		let valid = match std::str::from_utf8(&self.bytes) {
+			Ok(text) => text.len(),
+			Err(error) if error.error_len().is_none() => error.valid_up_to(),
+			Err(error) => {
+				// the code before the invalid bytes can still be lexed
+				self.error = Some(LexError::InvalidUtf8(
+					self.offset + self.buffer.len() + error.valid_up_to(),
+				));
+				self.bytes.truncate(error.valid_up_to());
+				error.valid_up_to()
+			}
+		};
+		let text = std::str::from_utf8(&self.bytes[..valid]).unwrap();
+		self.buffer.push_str(text);
+		self.bytes.drain(..valid);
+		true
+	}
+
+	/** Copies a token out of the buffer */
+	fn token(&self, span: Span) -> Token {
+		Token {
+			text: String::from(
+				&self.buffer[span.start - self.offset..span.end - self.offset],
+			),
+			span,
+		}
+	}
+}
+
+impl<R: Read> Iterator for Lexer<R> {
+	type Item = Result<Token, LexError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.lookahead.pop_front().or_else(|| self.lex_next())
+	}
 }
@@ -0,0 +1,369 @@
+use crate::diagnostic::Diagnostic;
+use crate::function::CallType;
+use crate::function::Function;
+use crate::function::Operation;
+use crate::interp::Value;
+use crate::program::Program;
+use crate::resolve::callee;
+use crate::resolve::Callee;
+use crate::resolve::Owner;
+use crate::span::SourceMap;
+use crate::symbol::Symbol;
+
+use std::collections::HashMap;
+use std::fmt;
+
+// the types the checker knows about
+// anything else, like a variable without a value yet, is Unknown
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Type {
+	Int,
+	Float,
+	Bool,
+	Str,
+	Void,
+	Function,
+	Unknown,
+}
+
+// the parameter and return types of something which can be called
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallSig {
+	pub parameters: Option<Vec<Type>>, // None if it takes any arguments
+	pub return_type: Type,
+}
+
+impl Type {
+	/** Converts the name of a type, like `float` */
+	pub fn from_name(name: &str) -> Self {
+		match name {
+			"int" => Type::Int,
+			"float" => Type::Float,
+			"bool" => Type::Bool,
+			"string" => Type::Str,
+			"void" => Type::Void,
+			"fn" => Type::Function,
+			_ => Type::Unknown,
+		}
+	}
+
+	/** Gets the type of a literal, or None for a variable */
+	pub fn of_literal(token: &str) -> Option<Self> {
+		Value::from_literal(token).map(|value| Type::of_value(&value))
+	}
+
+	/** Gets the type of a value */
+	pub fn of_value(value: &Value) -> Self {
+		match value {
+			Value::Int(_) => Type::Int,
+			Value::Float(_) => Type::Float,
+			Value::Bool(_) => Type::Bool,
+			Value::Str(_) => Type::Str,
+			Value::Void => Type::Void,
+			Value::Closure(_) => Type::Function,
+		}
+	}
+
+	/**
+	 * Checks if a value of this type can be used where `expected` is needed
+	 * Ints can be used as floats
+	 */
+	pub fn fits(self, expected: Type) -> bool {
+		self == expected
+			|| self == Type::Unknown
+			|| expected == Type::Unknown
+			|| (self == Type::Int && expected == Type::Float)
+	}
+
+	/** Gets the type of the result of an operation */
+	pub fn operate(self, op: &Operation, other: Type) -> Option<Type> {
+		match (self, other) {
+			(Type::Unknown, _) | (_, Type::Unknown) => Some(Type::Unknown),
+			(Type::Int, Type::Int) => Some(Type::Int),
+			(Type::Int, Type::Float)
+			| (Type::Float, Type::Int)
+			| (Type::Float, Type::Float) => Some(Type::Float),
+			(Type::Str, Type::Str) if *op == Operation::Add => Some(Type::Str),
+			_ => None,
+		}
+	}
+}
+
+impl fmt::Display for Type {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let name = match self {
+			Type::Int => "int",
+			Type::Float => "float",
+			Type::Bool => "bool",
+			Type::Str => "string",
+			Type::Void => "void",
+			Type::Function => "fn",
+			Type::Unknown => "unknown",
+		};
+		write!(f, "{}", name)
+	}
+}
+
+/** Gets the parameter and return types of a called name */
+pub fn call_signature(program: &Program, name: &str) -> Option<CallSig> {
+	match callee(program, name)? {
+		Callee::Function(index) => {
+			let function = &program.functions[index];
+			let parameters = function.signature.parameters.as_ref();
+			Some(CallSig {
+				parameters: Some(
+					function
+						.parameter_order
+						.iter()
+						.map(|p| type_of_parameter(parameters, p))
+						.collect(),
+				),
+				return_type: return_type(
+					function.signature.return_type.as_ref(),
+				),
+			})
+		}
+		Callee::Result(index) => {
+			let result = &program.results[index];
+			let parameters = &result.signature.parameters;
+			Some(CallSig {
+				parameters: Some(
+					result
+						.parameter_order
+						.iter()
+						.map(|p| type_of_parameter(Some(parameters), p))
+						.collect(),
+				),
+				return_type: return_type(result.signature.return_type.as_ref()),
+			})
+		}
+		Callee::Builtin => Some(match name {
+			"print" => CallSig {
+				parameters: None,
+				return_type: Type::Void,
+			},
+			_ => CallSig {
+				parameters: Some(vec![Type::Unknown]),
+				return_type: Type::from_name(name),
+			},
+		}),
+	}
+}
+
+fn type_of_parameter(
+	parameters: Option<&HashMap<Symbol, Symbol>>,
+	name: &Symbol,
+) -> Type {
+	parameters
+		.and_then(|p| p.get(name))
+		.map_or(Type::Unknown, |t| Type::from_name(t))
+}
+
+// functions which don't say what they return might return anything
+fn return_type(name: Option<&Symbol>) -> Type {
+	name.map_or(Type::Unknown, |t| Type::from_name(t))
+}
+
+/**
+ * Checks that the types in a program are used correctly
+ * This should only be run on programs which have been resolved
+ */
+pub fn typecheck(program: &Program, source_map: &SourceMap) -> Vec<Diagnostic> {
+	let mut diagnostics = Vec::new();
+
+	for function in &program.functions {
+		let name = function.signature.name.as_deref().unwrap_or_default();
+		let owner = Owner::function(name, source_map);
+		let parameters = function.signature.parameters.as_ref();
+		let variables = function
+			.parameter_order
+			.iter()
+			.map(|p| (*p, type_of_parameter(parameters, p)))
+			.collect();
+		let expected = return_type(function.signature.return_type.as_ref());
+		check_function(
+			program,
+			function,
+			variables,
+			expected,
+			&owner,
+			&mut diagnostics,
+		);
+	}
+
+	for result in &program.results {
+		let name = &result.signature.name;
+		for (index, function) in result.functions.iter().enumerate() {
+			let owner = Owner::implementation(name, index, source_map);
+			let mut variables: HashMap<Symbol, Type> = result
+				.signature
+				.parameters
+				.iter()
+				.map(|(p, t)| (*p, Type::from_name(t)))
+				.collect();
+
+			// renamed parameters have the type of the original
+			for (parameter, value) in
+				function.signature.parameters.iter().flatten()
+			{
+				let t = match variables.get(value) {
+					Some(t) => *t,
+					None => Type::from_name(value),
+				};
+				variables.insert(*parameter, t);
+			}
+
+			let expected = return_type(
+				function
+					.signature
+					.return_type
+					.as_ref()
+					.or(result.signature.return_type.as_ref()),
+			);
+			check_function(
+				program,
+				function,
+				variables,
+				expected,
+				&owner,
+				&mut diagnostics,
+			);
+		}
+	}
+
+	diagnostics
+}
+
+/** Checks the calls in a function */
+fn check_function(
+	program: &Program,
+	function: &Function,
+	mut variables: HashMap<Symbol, Type>,
+	expected: Type,
+	owner: &Owner,
+	diagnostics: &mut Vec<Diagnostic>,
+) {
+	let type_of = |token: &Symbol, variables: &HashMap<Symbol, Type>| {
+		Type::of_literal(token)
+			.or_else(|| variables.get(token).copied())
+			.unwrap_or(Type::Unknown)
+	};
+
+	for call in &function.calls {
+		match call {
+			CallType::Init(var) => {
+				variables.insert(*var, Type::Unknown);
+			}
+			CallType::Move(var, value) => {
+				let t = type_of(value, &variables);
+				variables.insert(*var, t);
+			}
+			CallType::Operate(var, left, op, right) => {
+				let left = type_of(left, &variables);
+				let right = type_of(right, &variables);
+				let t = left.operate(op, right).unwrap_or_else(|| {
+					diagnostics.push(owner.error(format!(
+						"can't apply {} to {} and {}",
+						op, left, right
+					)));
+					Type::Unknown
+				});
+				variables.insert(*var, t);
+			}
+			CallType::Closure(var, closure) => {
+				variables.insert(*var, Type::Function);
+
+				// the body can see the variables around it
+				let parameters = closure.signature.parameters.as_ref();
+				let mut inner = variables.clone();
+				for parameter in &closure.parameter_order {
+					let t = type_of_parameter(parameters, parameter);
+					inner.insert(*parameter, t);
+				}
+				let expected =
+					return_type(closure.signature.return_type.as_ref());
+				check_function(
+					program,
+					closure,
+					inner,
+					expected,
+					owner,
+					diagnostics,
+				);
+			}
+			CallType::Call(name, parameters)
+			| CallType::SetCall(_, name, parameters) => {
+				// the types of a closure's parameters aren't tracked
+				if variables.get(name) == Some(&Type::Function) {
+					if let CallType::SetCall(var, ..) = call {
+						variables.insert(*var, Type::Unknown);
+					}
+					continue;
+				}
+				let signature = match call_signature(program, name) {
+					Some(signature) => signature,
+					None => continue,
+				};
+				let arguments: Vec<Type> =
+					parameters.iter().map(|p| type_of(p, &variables)).collect();
+				check_arguments(
+					name,
+					&signature,
+					&arguments,
+					owner,
+					diagnostics,
+				);
+				if let CallType::SetCall(var, ..) = call {
+					variables.insert(*var, signature.return_type);
+				}
+			}
+			CallType::Return(value) => {
+				let t = type_of(value, &variables);
+				if !t.fits(expected) {
+					diagnostics.push(owner.error(format!(
+						"expected to return {}, but this returns {}",
+						expected, t
+					)));
+				}
+			}
+			CallType::Set(..) => (),
+		}
+	}
+}
+
+fn check_arguments(
+	name: &str,
+	signature: &CallSig,
+	arguments: &[Type],
+	owner: &Owner,
+	diagnostics: &mut Vec<Diagnostic>,
+) {
+	let parameters = match &signature.parameters {
+		Some(parameters) => parameters,
+		None => return,
+	};
+
+	if parameters.len() != arguments.len() {
+		diagnostics.push(owner.error(format!(
+			"`{}` takes {} arguments but {} were given",
+			name,
+			parameters.len(),
+			arguments.len()
+		)));
+		return;
+	}
+
+	for (i, (argument, parameter)) in
+		arguments.iter().zip(parameters).enumerate()
+	{
+		if !argument.fits(*parameter) {
+			diagnostics.push(owner.error(format!(
+				"argument {} of `{}` should be {}, but it's {}",
+				i + 1,
+				name,
+				parameter,
+				argument
+			)));
+		}
+	}
+}
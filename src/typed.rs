@@ -0,0 +1,240 @@
+use crate::diagnostic::Diagnostic;
+use crate::ir;
+use crate::ir::Instruction;
+use crate::ir::IrFunction;
+use crate::ir::Register;
+use crate::ir::Terminator;
+use crate::opt::PassManager;
+use crate::program::Program;
+use crate::ssa;
+use crate::symbol::Symbol;
+use crate::typeck::Type;
+
+use std::collections::HashMap;
+
+/*
+ * Lowers programs into functions where the type of every value is known
+ * before the program runs, for backends which can't check types as they go
+ *
+ * Each implementation of a result is lowered on its own, and the types of
+ * registers are worked out from the values written to them once the
+ * functions are in SSA form. The functions are optimized afterwards.
+ */
+
+// a function with the type of every register known
+#[derive(Clone, Debug, PartialEq)]
+pub struct Definition {
+	pub name: Symbol, // the name it's called by in Ro
+	pub implementation: Option<usize>, // which implementation of a result
+	pub function: IrFunction,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TypedModule {
+	pub definitions: Vec<Definition>,
+	pub callees: HashMap<Symbol, usize>, // the definition each name calls
+}
+
+/**
+ * Lowers a resolved program, working out the type of every value
+ * Calls to a result run its first implementation, like in the interpreter
+ * Fails if a value's type can't be worked out, or if the program uses
+ * something which can't be lowered, like closures
+ */
+pub fn lower(program: &Program) -> Result<TypedModule, Diagnostic> {
+	let mut module = TypedModule::default();
+	for result in &program.results {
+		let name = result.signature.name;
+		module.callees.insert(name, module.definitions.len());
+		let implementations: Vec<Option<&_>> = if result.functions.is_empty() {
+			vec![None]
+		} else {
+			result.functions.iter().map(Some).collect()
+		};
+		for (i, function) in implementations.into_iter().enumerate() {
+			module.definitions.push(Definition {
+				name,
+				implementation: Some(i),
+				function: ir::lower_implementation(program, result, function)?,
+			});
+		}
+	}
+
+	// free functions are found before results
+	let lowered = ir::lower(program)?;
+	for function in lowered.functions.into_iter().skip(program.results.len()) {
+		let name = function.name;
+		module.callees.insert(name, module.definitions.len());
+		module.definitions.push(Definition {
+			name,
+			implementation: None,
+			function,
+		});
+	}
+
+	for definition in &mut module.definitions {
+		ssa::construct(&mut definition.function);
+	}
+	infer(&mut module.definitions, &module.callees)?;
+	let passes = PassManager::default();
+	for definition in &mut module.definitions {
+		passes.run_function(&mut definition.function);
+	}
+	Ok(module)
+}
+
+fn error(message: &str) -> Diagnostic {
+	Diagnostic::error(String::from(message), None)
+}
+
+/** Gets the type of value a builtin returns */
+pub fn builtin_type(name: &str) -> Type {
+	match name {
+		"print" => Type::Void,
+		_ => Type::from_name(name),
+	}
+}
+
+/**
+ * Combines the types of values which meet in the same place
+ * Ints and floats become floats, and anything else has to match
+ */
+fn join(types: &[Type]) -> Option<Type> {
+	let first = *types.first()?;
+	if types.iter().all(|t| *t == first) {
+		Some(first)
+	} else if types.iter().all(|t| *t == Type::Int || *t == Type::Float) {
+		Some(Type::Float)
+	} else {
+		None
+	}
+}
+
+/**
+ * Works out the type of every register, and the return types which
+ * weren't written
+ * The functions must be in SSA form, so each register only has one value
+ */
+fn infer(
+	definitions: &mut [Definition],
+	callees: &HashMap<Symbol, usize>,
+) -> Result<(), Diagnostic> {
+	let mut declared = Vec::new();
+	for definition in definitions.iter_mut() {
+		let function = &mut definition.function;
+		for (i, info) in function.registers.iter_mut().enumerate() {
+			if !function.parameters.contains(&Register(i)) {
+				info.value_type = Type::Unknown;
+			}
+		}
+		declared.push(function.return_type != Type::Unknown);
+	}
+
+	let mut changed = true;
+	while changed {
+		changed = false;
+		let returns: Vec<Type> =
+			definitions.iter().map(|d| d.function.return_type).collect();
+		for (i, definition) in definitions.iter_mut().enumerate() {
+			let name = definition.name;
+			let function = &mut definition.function;
+			let mut found = Vec::new();
+			for block in &function.blocks {
+				for instruction in &block.instructions {
+					let register = match instruction.destination() {
+						Some(register) => register,
+						None => continue,
+					};
+					if function.registers[register.0].value_type
+						!= Type::Unknown
+					{
+						continue;
+					}
+					let t = match instruction {
+						Instruction::Move(_, value) => function.type_of(value),
+						Instruction::Operate(_, left, op, right) => {
+							let left = function.type_of(left);
+							let right = function.type_of(right);
+							left.operate(op, right).ok_or_else(|| {
+								error(&format!(
+									"can't apply {} to {} and {}, in `{}`",
+									op, left, right, name
+								))
+							})?
+						}
+						Instruction::Call(_, called, _) => {
+							match callees.get(called) {
+								Some(callee) => returns[*callee],
+								None => builtin_type(called),
+							}
+						}
+						Instruction::Phi(_, values) => {
+							let types: Vec<Type> = values
+								.iter()
+								.map(|v| function.type_of(&v.1))
+								.collect();
+							if types.contains(&Type::Unknown) {
+								continue;
+							}
+							join(&types).unwrap_or(Type::Unknown)
+						}
+					};
+					if t != Type::Unknown {
+						found.push((register, t));
+					}
+				}
+			}
+			for (register, t) in found {
+				function.registers[register.0].value_type = t;
+				changed = true;
+			}
+
+			// the return type comes from the values which are returned
+			if declared[i] {
+				continue;
+			}
+			let types: Vec<Type> = function
+				.blocks
+				.iter()
+				.filter_map(|b| match &b.terminator {
+					Terminator::Return(value) => Some(function.type_of(value)),
+					_ => None,
+				})
+				.collect();
+			if types.contains(&Type::Unknown) {
+				continue;
+			}
+			let t = join(&types).ok_or_else(|| {
+				error(&format!("`{}` returns values of different types", name))
+			})?;
+			if t != function.return_type {
+				function.return_type = t;
+				changed = true;
+			}
+		}
+	}
+
+	// everything needs a type which C has
+	for definition in definitions.iter() {
+		let function = &definition.function;
+		let unknown = |t: Type| t == Type::Unknown || t == Type::Function;
+		if unknown(function.return_type) {
+			return Err(error(&format!(
+				"the return type of `{}` can't be worked out",
+				definition.name
+			)));
+		}
+		for info in &function.registers {
+			if unknown(info.value_type) {
+				let name = info
+					.name
+					.map_or(String::from("a value"), |n| format!("`{}`", n));
+				return Err(error(&format!(
+					"the type of {} in `{}` can't be worked out",
+					name, definition.name
+				)));
+			}
+		}
+	}
+	Ok(())
+}
@@ -0,0 +1,340 @@
+use crate::bytecode::Chunk;
+use crate::bytecode::Instruction;
+use crate::bytecode::Module;
+use crate::bytecode::Target;
+use crate::interp;
+use crate::interp::Closure;
+use crate::interp::Frame;
+use crate::interp::InterpError;
+use crate::interp::RuntimeError;
+use crate::interp::Value;
+use crate::interp::Variables;
+use crate::span::SourceMap;
+use crate::span::Span;
+use crate::symbol::Symbol;
+
+use std::io::Write;
+use std::rc::Rc;
+
+/*
+ * Runs bytecode, giving the same output and errors as the interpreter
+ *
+ * Each call gets its own locals, which are empty until something is stored
+ * in them, and shares the value stack with its callers. Closures are the
+ * same values as in the interpreter, so they keep the variables they
+ * captured by name.
+ */
+
+// a closure's chunk is kept with the variables it captured, under a name
+// which can't be written in Ro
+const PROTOTYPE: &str = "(prototype)";
+
+pub struct Vm<'a, W: Write> {
+	pub module: &'a Module,
+	pub output: W,
+	pub source_map: SourceMap,
+	stack: Vec<Value>,
+	depth: usize,           // the number of calls which are running
+	function: Option<Span>, // the header of the function being run
+}
+
+impl<'a, W: Write> Vm<'a, W> {
+	/** Creates a VM which writes its output to `output` */
+	pub fn new(module: &'a Module, output: W) -> Self {
+		Vm {
+			module,
+			output,
+			source_map: SourceMap::default(),
+			stack: Vec::new(),
+			depth: 0,
+			function: None,
+		}
+	}
+
+	/**
+	 * Creates a VM which knows the code the module was compiled from, so
+	 * that its errors can point to it
+	 */
+	pub fn with_source(module: &'a Module, code: &str, output: W) -> Self {
+		Vm {
+			module,
+			output,
+			source_map: SourceMap::new(code),
+			stack: Vec::new(),
+			depth: 0,
+			function: None,
+		}
+	}
+
+	/** Runs the program, starting at main */
+	pub fn run(&mut self) -> Result<Value, InterpError> {
+		self.call("main", Vec::new())
+	}
+
+	/** Calls a free function, a result or a built-in by its name */
+	pub fn call(
+		&mut self,
+		name: &str,
+		arguments: Vec<Value>,
+	) -> Result<Value, InterpError> {
+		let name = Symbol::from(name);
+		let target = match self.module.globals.get(&name) {
+			Some(target) => *target,
+			None => Target::Unknown(name),
+		};
+		self.call_target(target, arguments)
+	}
+
+	/** Runs whatever a call was compiled to */
+	fn call_target(
+		&mut self,
+		target: Target,
+		arguments: Vec<Value>,
+	) -> Result<Value, InterpError> {
+		match target {
+			Target::Function(index) => {
+				let chunk = &self.module.functions[index];
+				let locals = bind(chunk, Vec::new(), arguments)?;
+				let name = &chunk.name;
+				let function = match chunk.implementation {
+					Some(i) => self.source_map.implementation(name, i),
+					None => self.source_map.function(name),
+				};
+				let outer = self.enter(function)?;
+				let value = self.execute(chunk, locals);
+				self.exit(outer);
+				value.map_err(|(call, e)| {
+					e.within(Frame {
+						name: name.to_string(),
+						implementation: chunk.implementation,
+						span: self.span(function, call),
+					})
+				})
+			}
+			Target::Builtin(builtin) => Ok(interp::builtin(
+				builtin.name(),
+				arguments,
+				&mut self.output,
+			)?),
+			Target::Unknown(name) => {
+				Err(RuntimeError::UnknownFunction(name.to_string()).into())
+			}
+		}
+	}
+
+	/**
+	 * Runs a closure with the variables it captured
+	 * The closure can call itself by the name it was defined as
+	 */
+	fn call_closure(
+		&mut self,
+		closure: &Rc<Closure>,
+		arguments: Vec<Value>,
+	) -> Result<Value, InterpError> {
+		let prototype = match closure.captured.get(&Symbol::from(PROTOTYPE)) {
+			Some(Value::Int(index)) => &self.module.closures[*index as usize],
+			_ => {
+				return Err(RuntimeError::TypeMismatch(String::from(
+					"this closure wasn't made by the VM",
+				))
+				.into())
+			}
+		};
+		let chunk = &prototype.chunk;
+		let mut captured: Vec<Option<Value>> = chunk
+			.locals
+			.iter()
+			.map(|name| closure.captured.get(name).cloned())
+			.collect();
+		captured[prototype.this] = Some(Value::Closure(Rc::clone(closure)));
+		let locals = bind(chunk, captured, arguments)?;
+		let outer = self.enter(closure.span)?;
+		let value = self.execute(chunk, locals);
+		self.exit(outer);
+		value.map_err(|(call, e)| {
+			e.within(Frame {
+				name: chunk.name.to_string(),
+				implementation: None,
+				span: self.span(closure.span, call),
+			})
+		})
+	}
+
+	/**
+	 * Starts running the function whose header is at `function`, unless too
+	 * many calls are already running, like the interpreter
+	 * Gives back the function which was running before, for `exit`
+	 */
+	fn enter(
+		&mut self,
+		function: Option<Span>,
+	) -> Result<Option<Span>, RuntimeError> {
+		if self.depth >= interp::MAX_DEPTH {
+			return Err(RuntimeError::StackOverflow);
+		}
+		self.depth += 1;
+		Ok(std::mem::replace(&mut self.function, function))
+	}
+
+	/** Goes back to running the function which was running before `enter` */
+	fn exit(&mut self, outer: Option<Span>) {
+		self.depth -= 1;
+		self.function = outer;
+	}
+
+	/**
+	 * Gets the span of the statement that a call in a function came from,
+	 * or else the function's header
+	 */
+	fn span(
+		&self,
+		function: Option<Span>,
+		call: Option<usize>,
+	) -> Option<Span> {
+		function.map(|f| {
+			let call = call.and_then(|c| self.source_map.call(f, c));
+			call.unwrap_or(f)
+		})
+	}
+
+	/**
+	 * Runs a chunk until it returns
+	 * An error comes with the index of the call it happened in
+	 */
+	fn execute(
+		&mut self,
+		chunk: &Chunk,
+		mut locals: Vec<Option<Value>>,
+	) -> Result<Value, (Option<usize>, InterpError)> {
+		let base = self.stack.len();
+		let mut pc = 0;
+		let result = self.step(chunk, &mut locals, &mut pc);
+		self.stack.truncate(base);
+		result.map_err(|e| (chunk.calls.get(pc - 1).copied(), e))
+	}
+
+	/** Runs instructions from `pc`, leaving it after the last one it ran */
+	fn step(
+		&mut self,
+		chunk: &Chunk,
+		locals: &mut [Option<Value>],
+		pc: &mut usize,
+	) -> Result<Value, InterpError> {
+		loop {
+			let instruction = &chunk.code[*pc];
+			*pc += 1;
+			match instruction {
+				Instruction::Constant(constant) => {
+					self.stack.push(chunk.constants[*constant].clone())
+				}
+				Instruction::Load(local) => match &locals[*local] {
+					Some(value) => self.stack.push(value.clone()),
+					None => {
+						let name = chunk.locals[*local].to_string();
+						return Err(RuntimeError::UnknownVariable(name).into());
+					}
+				},
+				Instruction::Store(local) => locals[*local] = Some(self.pop()),
+				Instruction::Pop => {
+					self.pop();
+				}
+				Instruction::Operate(op) => {
+					let right = self.pop();
+					let left = self.pop();
+					self.stack.push(interp::operate(left, op, right)?);
+				}
+				Instruction::Call(target, count) => {
+					let arguments = self.arguments(*count);
+					let value = self.call_target(*target, arguments)?;
+					self.stack.push(value);
+				}
+				Instruction::CallLocal(local, target, count) => {
+					let arguments = self.arguments(*count);
+					let value = match &locals[*local] {
+						Some(Value::Closure(closure)) => {
+							let closure = Rc::clone(closure);
+							self.call_closure(&closure, arguments)?
+						}
+						_ => self.call_target(*target, arguments)?,
+					};
+					self.stack.push(value);
+				}
+				Instruction::Closure(index) => {
+					let prototype = &self.module.closures[*index];
+					let mut captured: Variables = chunk
+						.locals
+						.iter()
+						.zip(locals.iter())
+						.filter_map(|(name, value)| {
+							value.as_ref().map(|v| (*name, v.clone()))
+						})
+						.collect();
+					captured.insert(
+						Symbol::from(PROTOTYPE),
+						Value::Int(*index as i64),
+					);
+					let call = chunk.calls.get(*pc - 1);
+					let span = self.function.zip(call).and_then(|(f, call)| {
+						self.source_map.closure(f, *call)
+					});
+					let closure = Closure {
+						name: prototype.chunk.name,
+						function: prototype.function.clone(),
+						captured,
+						span,
+					};
+					self.stack.push(Value::Closure(Rc::new(closure)));
+				}
+				Instruction::Jump(to) => *pc = *to,
+				Instruction::JumpUnless(to) => match self.pop() {
+					Value::Bool(true) => (),
+					Value::Bool(false) => *pc = *to,
+					value => {
+						return Err(RuntimeError::TypeMismatch(format!(
+							"expected a bool, but found {}",
+							value.type_name()
+						))
+						.into())
+					}
+				},
+				Instruction::Return => return Ok(self.pop()),
+			}
+		}
+	}
+
+	fn pop(&mut self) -> Value {
+		self.stack.pop().unwrap_or(Value::Void)
+	}
+
+	/** Pops the arguments of a call, keeping them in order */
+	fn arguments(&mut self, count: usize) -> Vec<Value> {
+		let start = self.stack.len().saturating_sub(count);
+		self.stack.split_off(start)
+	}
+}
+
+/** Puts the arguments of a call into its locals */
+fn bind(
+	chunk: &Chunk,
+	mut locals: Vec<Option<Value>>,
+	arguments: Vec<Value>,
+) -> Result<Vec<Option<Value>>, RuntimeError> {
+	if chunk.parameters.len() != arguments.len() {
+		return Err(RuntimeError::WrongArgumentCount(
+			chunk.name.to_string(),
+			chunk.parameters.len(),
+			arguments.len(),
+		));
+	}
+	locals.resize(chunk.locals.len(), None);
+	for (local, argument) in chunk.parameters.iter().zip(arguments) {
+		locals[*local] = Some(argument);
+	}
+	Ok(locals)
+}
+
+/** Runs a compiled program, printing to stdout */
+pub fn run(module: &Module) -> Result<Value, InterpError> {
+	let stdout = std::io::stdout();
+	Vm::new(module, stdout.lock()).run()
+}
@@ -0,0 +1,745 @@
+use crate::diagnostic::Diagnostic;
+use crate::function::Operation;
+use crate::interp::Value;
+use crate::ir::BlockId;
+use crate::ir::Instruction;
+use crate::ir::IrFunction;
+use crate::ir::Operand;
+use crate::ir::Register;
+use crate::ir::Terminator;
+use crate::program::Program;
+use crate::typeck::Type;
+use crate::typed;
+use crate::typed::Definition;
+use crate::typed::TypedModule;
+
+use std::collections::HashMap;
+
+/*
+ * Writes programs as WebAssembly text, which can be turned into a binary
+ * module with tools like `wat2wasm`
+ *
+ * Ints are `i64`, floats are `f64`, and bools, strings and void are `i32`.
+ * Strings are pointers into the module's memory, where each one is stored
+ * as its length in bytes followed by the bytes. Each free function and
+ * result is exported with its Ro name, along with the memory.
+ *
+ * The module imports a few functions from the host, under `ro`:
+ *
+ * 	print_int(i64)           writes an int, like the interpreter would
+ * 	print_float(f64)         writes a float, like the interpreter would
+ * 	print_string(i32)        writes a string, without a new line
+ * 	error(i32)               reports an error with a string, then traps
+ * 	fmod(f64, f64) -> f64    the remainder of dividing two floats, like `%`
+ * 	                         in JavaScript
+ *
+ * Converting between strings and floats, and parsing strings, aren't
+ * supported yet.
+ */
+
+// the start of the module, before the functions which use these
+const IMPORTS: &str = r#"(module
+	(import "ro" "print_int" (func $ro.print_int (param i64)))
+	(import "ro" "print_float" (func $ro.print_float (param f64)))
+	(import "ro" "print_string" (func $ro.print_string (param i32)))
+	(import "ro" "error" (func $ro.error (param i32)))
+	(import "ro" "fmod" (func $ro.fmod (param f64 f64) (result f64)))
+	(memory (export "memory") 1)
+"#;
+
+// the helpers which the generated code uses, with the addresses of the
+// strings they need filled in
+const HELPERS: &str = r#"
+	(func $ro.alloc (param $size i32) (result i32)
+		(local $start i32)
+		(local $pages i32)
+		(local.set $start (global.get $ro.heap))
+		(global.set $ro.heap
+			(i32.and
+				(i32.add (i32.add (local.get $start) (local.get $size)) (i32.const 3))
+				(i32.const -4)))
+		(local.set $pages
+			(i32.sub
+				(i32.add (i32.shr_u (global.get $ro.heap) (i32.const 16)) (i32.const 1))
+				(memory.size)))
+		(if (i32.gt_s (local.get $pages) (i32.const 0))
+			(then
+				(if (i32.eq (memory.grow (local.get $pages)) (i32.const -1))
+					(then
+						(call $ro.error (i32.const {out_of_memory}))
+						(unreachable)))))
+		(local.get $start))
+
+	(func $ro.div_int (param $a i64) (param $b i64) (result i64)
+		(if (i64.eqz (local.get $b))
+			(then
+				(call $ro.error (i32.const {division_by_zero}))
+				(unreachable)))
+		(if (i64.eq (local.get $b) (i64.const -1))
+			(then (return (i64.sub (i64.const 0) (local.get $a)))))
+		(i64.div_s (local.get $a) (local.get $b)))
+
+	(func $ro.mod_int (param $a i64) (param $b i64) (result i64)
+		(if (i64.eqz (local.get $b))
+			(then
+				(call $ro.error (i32.const {division_by_zero}))
+				(unreachable)))
+		(if (i64.eq (local.get $b) (i64.const -1))
+			(then (return (i64.const 0))))
+		(i64.rem_s (local.get $a) (local.get $b)))
+
+	(func $ro.div_float (param $a f64) (param $b f64) (result f64)
+		(if (f64.eq (local.get $b) (f64.const 0))
+			(then
+				(call $ro.error (i32.const {division_by_zero}))
+				(unreachable)))
+		(f64.div (local.get $a) (local.get $b)))
+
+	(func $ro.mod_float (param $a f64) (param $b f64) (result f64)
+		(if (f64.eq (local.get $b) (f64.const 0))
+			(then
+				(call $ro.error (i32.const {division_by_zero}))
+				(unreachable)))
+		(call $ro.fmod (local.get $a) (local.get $b)))
+
+	(func $ro.concat (param $a i32) (param $b i32) (result i32)
+		(local $a_length i32)
+		(local $b_length i32)
+		(local $string i32)
+		(local.set $a_length (i32.load (local.get $a)))
+		(local.set $b_length (i32.load (local.get $b)))
+		(local.set $string
+			(call $ro.alloc
+				(i32.add
+					(i32.const 4)
+					(i32.add (local.get $a_length) (local.get $b_length)))))
+		(i32.store
+			(local.get $string)
+			(i32.add (local.get $a_length) (local.get $b_length)))
+		(memory.copy
+			(i32.add (local.get $string) (i32.const 4))
+			(i32.add (local.get $a) (i32.const 4))
+			(local.get $a_length))
+		(memory.copy
+			(i32.add (i32.add (local.get $string) (i32.const 4)) (local.get $a_length))
+			(i32.add (local.get $b) (i32.const 4))
+			(local.get $b_length))
+		(local.get $string))
+
+	;; writes the digits backwards from the end, then moves them after the
+	;; length
+	(func $ro.string_int (param $value i64) (result i32)
+		(local $string i32)
+		(local $start i32)
+		(local $length i32)
+		(local $magnitude i64)
+		(local.set $string (call $ro.alloc (i32.const 24)))
+		(local.set $start (i32.add (local.get $string) (i32.const 24)))
+		(local.set $magnitude (local.get $value))
+		(if (i64.lt_s (local.get $value) (i64.const 0))
+			(then (local.set $magnitude (i64.sub (i64.const 0) (local.get $value)))))
+		(loop $digits
+			(local.set $start (i32.sub (local.get $start) (i32.const 1)))
+			(i32.store8
+				(local.get $start)
+				(i32.add
+					(i32.const 48)
+					(i32.wrap_i64 (i64.rem_u (local.get $magnitude) (i64.const 10)))))
+			(local.set $magnitude (i64.div_u (local.get $magnitude) (i64.const 10)))
+			(br_if $digits (i64.ne (local.get $magnitude) (i64.const 0))))
+		(if (i64.lt_s (local.get $value) (i64.const 0))
+			(then
+				(local.set $start (i32.sub (local.get $start) (i32.const 1)))
+				(i32.store8 (local.get $start) (i32.const 45))))
+		(local.set $length
+			(i32.sub (i32.add (local.get $string) (i32.const 24)) (local.get $start)))
+		(memory.copy
+			(i32.add (local.get $string) (i32.const 4))
+			(local.get $start)
+			(local.get $length))
+		(i32.store (local.get $string) (local.get $length))
+		(local.get $string))
+"#;
+
+// the strings in the module's memory, and where each one is
+#[derive(Default)]
+struct Strings {
+	data: Vec<u8>,
+	addresses: HashMap<String, usize>,
+}
+
+impl Strings {
+	/** Gets the address of a string, adding it if it isn't there yet */
+	fn address(&mut self, string: &str) -> usize {
+		if let Some(address) = self.addresses.get(string) {
+			return *address;
+		}
+		while !self.data.len().is_multiple_of(4) {
+			self.data.push(0);
+		}
+		let address = self.data.len();
+		self.data.extend((string.len() as u32).to_le_bytes());
+		self.data.extend(string.bytes());
+		self.addresses.insert(String::from(string), address);
+		address
+	}
+}
+
+/**
+ * Writes a resolved program as a WebAssembly text module
+ * Fails if a value's type can't be worked out, or if the program uses
+ * something which can't be compiled, like closures
+ */
+pub fn program(program: &Program) -> Result<String, Diagnostic> {
+	module(&typed::lower(program)?)
+}
+
+/** Writes functions whose types are all known as a WebAssembly text module */
+pub fn module(module: &TypedModule) -> Result<String, Diagnostic> {
+	let definitions = &module.definitions;
+	let mut strings = Strings::default();
+
+	let mut functions = String::new();
+	for definition in definitions {
+		functions.push('\n');
+		write_function(&mut functions, definition, module, &mut strings)?;
+	}
+
+	// results call their first implementation
+	for result in definitions.iter().filter(|d| d.implementation == Some(0)) {
+		let function = &result.function;
+		let arguments: String = function
+			.parameters
+			.iter()
+			.map(|p| format!(" (local.get {})", register(*p)))
+			.collect();
+		functions.push_str(&format!(
+			"\n{}\n\t\t(call {}{}))\n",
+			signature(&identifier(&result.name), function),
+			function_name(result),
+			arguments
+		));
+	}
+
+	let mut exports = String::new();
+	for definition in definitions {
+		if definition.implementation.unwrap_or(0) == 0 {
+			exports.push_str(&format!(
+				"\t(export {} (func {}))\n",
+				string_literal(definition.name.as_bytes()),
+				identifier(&definition.name)
+			));
+		}
+	}
+
+	let helpers = HELPERS
+		.replace(
+			"{division_by_zero}",
+			&strings.address("division by zero").to_string(),
+		)
+		.replace(
+			"{out_of_memory}",
+			&strings.address("out of memory").to_string(),
+		);
+	let heap = strings.data.len().div_ceil(8) * 8;
+	Ok(format!(
+		"{}\t(global $ro.heap (mut i32) (i32.const {}))\n\t(data (i32.const 0) {})\n{}{}\n{})\n",
+		IMPORTS,
+		heap.max(8),
+		string_literal(&strings.data),
+		helpers,
+		functions,
+		exports
+	))
+}
+
+fn error(message: &str) -> Diagnostic {
+	Diagnostic::error(String::from(message), None)
+}
+
+/**
+ * Converts a Ro name into an identifier, like `$add`
+ * Characters which can't be in identifiers are written as `%` and their
+ * bytes in hex
+ */
+fn identifier(name: &str) -> String {
+	let mut identifier = String::from("$");
+	for byte in name.bytes() {
+		match byte {
+			b'"' | b'(' | b')' | b',' | b';' | b'[' | b']' | b'{' | b'}'
+			| b'%' => identifier.push_str(&format!("%{:02x}", byte)),
+			b'!'..=b'~' => identifier.push(byte as char),
+			_ => identifier.push_str(&format!("%{:02x}", byte)),
+		}
+	}
+	identifier
+}
+
+/**
+ * Gets the identifier of the function a definition is written as
+ * Implementations of results have their index after a dot, which can't
+ * be in Ro names
+ */
+fn function_name(definition: &Definition) -> String {
+	match definition.implementation {
+		Some(i) => format!("{}.{}", identifier(&definition.name), i),
+		None => identifier(&definition.name),
+	}
+}
+
+/** Writes some bytes as a string, escaping anything that isn't printable */
+fn string_literal(bytes: &[u8]) -> String {
+	let mut literal = String::from("\"");
+	for byte in bytes {
+		match byte {
+			b'"' | b'\\' => literal.push_str(&format!("\\{:02x}", byte)),
+			b' '..=b'~' => literal.push(*byte as char),
+			_ => literal.push_str(&format!("\\{:02x}", byte)),
+		}
+	}
+	literal.push('"');
+	literal
+}
+
+fn value_type(t: Type) -> &'static str {
+	match t {
+		Type::Int => "i64",
+		Type::Float => "f64",
+		_ => "i32",
+	}
+}
+
+fn register(register: Register) -> String {
+	format!("$r{}", register.0)
+}
+
+/** Writes the start of a function, up to its locals */
+fn signature(name: &str, function: &IrFunction) -> String {
+	let mut signature = format!("\t(func {}", name);
+	for parameter in &function.parameters {
+		let t = function.registers[parameter.0].value_type;
+		signature.push_str(&format!(
+			" (param {} {})",
+			register(*parameter),
+			value_type(t)
+		));
+	}
+	signature
+		.push_str(&format!(" (result {})", value_type(function.return_type)));
+	signature
+}
+
+/** Writes a constant, as a value of the type it's used as */
+fn constant(value: &Value, strings: &mut Strings) -> String {
+	match value {
+		Value::Int(int) => format!("(i64.const {})", int),
+		Value::Float(float) if float.is_nan() => {
+			String::from("(f64.const nan)")
+		}
+		Value::Float(float) if float.is_infinite() => {
+			let sign = if *float > 0.0 { "" } else { "-" };
+			format!("(f64.const {}inf)", sign)
+		}
+		Value::Float(float) => format!("(f64.const {:?})", float),
+		Value::Bool(boolean) => format!("(i32.const {})", *boolean as i32),
+		Value::Str(string) => {
+			format!("(i32.const {})", strings.address(string))
+		}
+		Value::Void | Value::Closure(_) => String::from("(i32.const 0)"),
+	}
+}
+
+// writes the parts of a function which need to know about the module
+struct Writer<'a> {
+	definition: &'a Definition,
+	module: &'a TypedModule,
+	strings: &'a mut Strings,
+}
+
+impl<'a> Writer<'a> {
+	fn function(&self) -> &'a IrFunction {
+		&self.definition.function
+	}
+
+	/** Gets a value, converting ints to floats if a float is expected */
+	fn operand(&mut self, operand: &Operand, expected: Type) -> String {
+		let value = match operand {
+			Operand::Register(r) => format!("(local.get {})", register(*r)),
+			Operand::Constant(value) => constant(value, self.strings),
+		};
+		match (self.function().type_of(operand), expected) {
+			(Type::Int, Type::Float) => {
+				format!("(f64.convert_i64_s {})", value)
+			}
+			_ => value,
+		}
+	}
+
+	fn string(&mut self, string: &str) -> String {
+		format!("(i32.const {})", self.strings.address(string))
+	}
+
+	/** Writes an instruction, which doesn't leave anything on the stack */
+	fn instruction(
+		&mut self,
+		instruction: &Instruction,
+	) -> Result<String, Diagnostic> {
+		let function = self.function();
+		let name = self.definition.name;
+		let set = |register: &Option<Register>, value: String| match register {
+			Some(r) => format!("(local.set {} {})", self::register(*r), value),
+			None => format!("(drop {})", value),
+		};
+
+		match instruction {
+			Instruction::Move(register, value) => {
+				let t = function.registers[register.0].value_type;
+				Ok(set(&Some(*register), self.operand(value, t)))
+			}
+			Instruction::Operate(register, left, op, right) => {
+				let types = (function.type_of(left), function.type_of(right));
+				let value = match types {
+					(Type::Int, Type::Int) => {
+						let left = self.operand(left, Type::Int);
+						let right = self.operand(right, Type::Int);
+						match op {
+							Operation::Add => {
+								format!("(i64.add {} {})", left, right)
+							}
+							Operation::Sub => {
+								format!("(i64.sub {} {})", left, right)
+							}
+							Operation::Mult => {
+								format!("(i64.mul {} {})", left, right)
+							}
+							Operation::Div => {
+								format!("(call $ro.div_int {} {})", left, right)
+							}
+							Operation::Mod => {
+								format!("(call $ro.mod_int {} {})", left, right)
+							}
+						}
+					}
+					(Type::Int | Type::Float, Type::Int | Type::Float) => {
+						let left = self.operand(left, Type::Float);
+						let right = self.operand(right, Type::Float);
+						match op {
+							Operation::Add => {
+								format!("(f64.add {} {})", left, right)
+							}
+							Operation::Sub => {
+								format!("(f64.sub {} {})", left, right)
+							}
+							Operation::Mult => {
+								format!("(f64.mul {} {})", left, right)
+							}
+							Operation::Div => {
+								format!(
+									"(call $ro.div_float {} {})",
+									left, right
+								)
+							}
+							Operation::Mod => {
+								format!(
+									"(call $ro.mod_float {} {})",
+									left, right
+								)
+							}
+						}
+					}
+					(Type::Str, Type::Str) if *op == Operation::Add => format!(
+						"(call $ro.concat {} {})",
+						self.operand(left, Type::Str),
+						self.operand(right, Type::Str)
+					),
+					(left, right) => {
+						return Err(error(&format!(
+							"can't apply {} to {} and {}, in `{}`",
+							op, left, right, name
+						)))
+					}
+				};
+				Ok(set(&Some(*register), value))
+			}
+			Instruction::Call(register, callee, arguments) => {
+				let types: Vec<Type> =
+					arguments.iter().map(|a| function.type_of(a)).collect();
+				if let Some(index) = self.module.callees.get(callee) {
+					let callee = &self.module.definitions[*index];
+					let parameters: Vec<Type> = callee
+						.function
+						.parameters
+						.iter()
+						.map(|p| callee.function.registers[p.0].value_type)
+						.collect();
+					if parameters.len() != types.len()
+						|| types
+							.iter()
+							.zip(&parameters)
+							.any(|(a, p)| !a.fits(*p))
+					{
+						return Err(error(&format!(
+							"`{}` can't be called with ({}), in `{}`",
+							callee.name,
+							types
+								.iter()
+								.map(|t| t.to_string())
+								.collect::<Vec<String>>()
+								.join(", "),
+							name
+						)));
+					}
+					let mut call =
+						format!("(call {}", identifier(&callee.name));
+					for (argument, t) in arguments.iter().zip(parameters) {
+						call.push(' ');
+						call.push_str(&self.operand(argument, t));
+					}
+					call.push(')');
+					return Ok(set(register, call));
+				}
+
+				if *callee == "print" {
+					let mut line = String::new();
+					for (i, (argument, t)) in
+						arguments.iter().zip(&types).enumerate()
+					{
+						if i > 0 {
+							let space = self.string(" ");
+							line.push_str(&format!(
+								"(call $ro.print_string {})\n\t\t",
+								space
+							));
+						}
+						let value = self.operand(argument, *t);
+						let print = match t {
+							Type::Int => {
+								format!("(call $ro.print_int {})", value)
+							}
+							Type::Float => {
+								format!("(call $ro.print_float {})", value)
+							}
+							Type::Bool => format!(
+								"(call $ro.print_string (select {} {} {}))",
+								self.string("true"),
+								self.string("false"),
+								value
+							),
+							Type::Str => {
+								format!("(call $ro.print_string {})", value)
+							}
+							_ => format!(
+								"(call $ro.print_string {})",
+								self.string("void")
+							),
+						};
+						line.push_str(&print);
+						line.push_str("\n\t\t");
+					}
+					let newline = self.string("\n");
+					line.push_str(&format!(
+						"(call $ro.print_string {})",
+						newline
+					));
+					if let Some(register) = register {
+						line.push_str(&format!(
+							"\n\t\t(local.set {} (i32.const 0))",
+							self::register(*register)
+						));
+					}
+					return Ok(line);
+				}
+
+				let (argument, t) =
+					match (arguments.as_slice(), types.as_slice()) {
+						([argument], [t]) => (argument, *t),
+						_ => {
+							return Err(error(&format!(
+							"`{}` takes 1 arguments but {} were given, in `{}`",
+							callee,
+							arguments.len(),
+							name
+						)))
+						}
+					};
+				let value = self.operand(argument, t);
+				let value = match (callee.as_str(), t) {
+					("int", Type::Int) | ("float", Type::Float) => value,
+					("int", Type::Float) => {
+						format!("(i64.trunc_sat_f64_s {})", value)
+					}
+					("float", Type::Int) => {
+						format!("(f64.convert_i64_s {})", value)
+					}
+					("string", Type::Int) => {
+						format!("(call $ro.string_int {})", value)
+					}
+					("string", Type::Bool) => format!(
+						"(select {} {} {})",
+						self.string("true"),
+						self.string("false"),
+						value
+					),
+					("string", Type::Str) => value,
+					("string", Type::Void) => self.string("void"),
+					(_, t) => {
+						return Err(error(&format!(
+							"converting {} to {} isn't supported in \
+							 WebAssembly yet, in `{}`",
+							t, callee, name
+						)))
+					}
+				};
+				Ok(set(register, value))
+			}
+			Instruction::Phi(..) => Ok(String::new()),
+		}
+	}
+
+	/**
+	 * Jumps from one block to another, setting the phis at the start of it
+	 * The values are all pushed before any are set, in case a phi reads
+	 * another
+	 */
+	fn edge(&mut self, from: BlockId, to: BlockId) -> String {
+		let function = self.function();
+		let mut copies = Vec::new();
+		for instruction in &function.blocks[to.0].instructions {
+			if let Instruction::Phi(register, values) = instruction {
+				if let Some((_, value)) = values.iter().find(|v| v.0 == from) {
+					copies.push((*register, value));
+				}
+			}
+		}
+
+		let mut edge = String::new();
+		for (register, value) in &copies {
+			let t = function.registers[register.0].value_type;
+			edge.push_str(&self.operand(value, t));
+			edge.push(' ');
+		}
+		for (register, _) in copies.iter().rev() {
+			edge.push_str(&format!(
+				"(local.set {}) ",
+				self::register(*register)
+			));
+		}
+		edge.push_str(&format!(
+			"(local.set $block (i32.const {})) (br $dispatch)",
+			to.0
+		));
+		edge
+	}
+
+	fn terminator(&mut self, block: BlockId) -> Result<String, Diagnostic> {
+		let function = self.function();
+		match &function.blocks[block.0].terminator {
+			Terminator::Jump(to) => Ok(self.edge(block, *to)),
+			Terminator::Branch(condition, yes, no) => {
+				if function.type_of(condition) != Type::Bool {
+					return Err(error(&format!(
+						"branches need a bool, in `{}`",
+						self.definition.name
+					)));
+				}
+				Ok(format!(
+					"(if {}\n\t\t\t(then {})\n\t\t\t(else {}))",
+					self.operand(condition, Type::Bool),
+					self.edge(block, *yes),
+					self.edge(block, *no)
+				))
+			}
+			Terminator::Return(value) => {
+				if !function.type_of(value).fits(function.return_type) {
+					return Err(error(&format!(
+						"`{}` should return {}, but this returns {}",
+						self.definition.name,
+						function.return_type,
+						function.type_of(value)
+					)));
+				}
+				let value = self.operand(value, function.return_type);
+				Ok(format!("(return {})", value))
+			}
+		}
+	}
+
+	/** Writes the instructions of a block, and how it ends */
+	fn block(&mut self, block: BlockId) -> Result<String, Diagnostic> {
+		let mut code = String::new();
+		let function = self.function();
+		for instruction in &function.blocks[block.0].instructions {
+			if !matches!(instruction, Instruction::Phi(..)) {
+				let line = self.instruction(instruction)?;
+				code.push_str(&format!("\t\t{}\n", line));
+			}
+		}
+		code.push_str(&format!("\t\t{}\n", self.terminator(block)?));
+		Ok(code)
+	}
+}
+
+/**
+ * Writes a function, with its registers as locals
+ * A function with more than one block runs in a loop, with a `br_table`
+ * going to whichever block is next
+ */
+fn write_function(
+	wat: &mut String,
+	definition: &Definition,
+	module: &TypedModule,
+	strings: &mut Strings,
+) -> Result<(), Diagnostic> {
+	let function = &definition.function;
+	wat.push_str(&signature(&function_name(definition), function));
+	wat.push('\n');
+	for (i, info) in function.registers.iter().enumerate() {
+		if !function.parameters.contains(&Register(i)) {
+			let name = info.name.map_or(String::new(), |n| {
+				format!(" ;; {}", n.replace('\n', " "))
+			});
+			wat.push_str(&format!(
+				"\t\t(local {} {}){}\n",
+				register(Register(i)),
+				value_type(info.value_type),
+				name
+			));
+		}
+	}
+
+	let mut writer = Writer {
+		definition,
+		module,
+		strings,
+	};
+	let order = function.reverse_postorder();
+	if order.len() == 1 {
+		wat.push_str(&writer.block(order[0])?);
+		wat.truncate(wat.trim_end().len());
+		wat.push_str(")\n");
+		return Ok(());
+	}
+
+	// each block is nested inside of the ones after it, so breaking out of
+	// a block goes to its code
+	wat.push_str("\t\t(local $block i32)\n\t\t(loop $dispatch\n");
+	for block in order.iter().rev() {
+		wat.push_str(&format!("\t\t(block $bb{}\n", block.0));
+	}
+	let table: Vec<String> = (0..function.blocks.len())
+		.map(|i| match order.contains(&BlockId(i)) {
+			true => format!("$bb{}", i),
+			false => format!("$bb{}", order[0].0),
+		})
+		.collect();
+	wat.push_str(&format!(
+		"\t\t(br_table {} (local.get $block)))\n",
+		table.join(" ")
+	));
+	for block in &order {
+		wat.push_str(&writer.block(*block)?);
+		wat.push_str("\t\t)\n");
+	}
+	wat.push_str("\t\t(unreachable))\n");
+	Ok(())
+}